@@ -0,0 +1,574 @@
+//! Bot mode: answers incoming messages according to a rules file.
+//!
+//! Rules are tried in order and the first one matching the sender, channel and
+//! text pattern replies with its template. Replies are rate limited per sender so
+//! a chatty node can't make the bot flood the mesh.
+//!
+//! ```toml
+//! [limits]
+//! max_replies = 5      # per sender and window
+//! window_secs = 3600
+//! cooldown_secs = 30   # minimum gap between replies to the same sender
+//!
+//! [[rule]]
+//! match = "(?i)^ping$"
+//! reply = "pong {sender} (SNR {snr}, {hops} hops)"
+//!
+//! [[rule]]
+//! channel = "#test"
+//! match = "(?i)^!info"
+//! reply = "{time}: battery {battery}"
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+use meshcore::event::Event;
+use meshcore::transport::Transport;
+use meshcore::types::{ChannelMessage, ContactMessage};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::commands::messaging::MAX_TEXT_LEN;
+use crate::commands::{CommandContext, current_timestamp, lookup_sender_name};
+use crate::error::{CliError, Result};
+use crate::events::{channel_label, split_channel_sender};
+use crate::history::HistoryEntry;
+
+/// Path length reported for messages that arrived over a direct route.
+const PATH_LEN_DIRECT: u8 = 0xff;
+
+/// Reply limits, shared by all rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    /// Replies allowed per sender within the window.
+    pub max_replies: usize,
+    /// Rate limit window in seconds.
+    pub window_secs: u64,
+    /// Minimum seconds between two replies to the same sender.
+    pub cooldown_secs: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_replies: 5,
+            window_secs: 3600,
+            cooldown_secs: 30,
+        }
+    }
+}
+
+/// A rule as written in the rules file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    /// Rule name, shown in the log (defaults to its position).
+    name: Option<String>,
+    /// Regex matched against the message text.
+    #[serde(rename = "match")]
+    pattern: String,
+    /// Only answer this sender (name or public key prefix, case-insensitive).
+    from: Option<String>,
+    /// Channel name or number to listen on, `*` for all; direct messages if unset.
+    channel: Option<String>,
+    /// Reply template.
+    reply: String,
+    /// Cooldown override for this rule.
+    cooldown_secs: Option<u64>,
+}
+
+/// The rules file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default)]
+    limits: Limits,
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleConfig>,
+}
+
+/// A compiled rule.
+#[derive(Debug)]
+pub struct Rule {
+    /// Rule name.
+    pub name: String,
+    pattern: Regex,
+    from: Option<String>,
+    channel: Option<String>,
+    reply: String,
+    cooldown: Option<Duration>,
+}
+
+impl Rule {
+    /// Returns true if the rule applies to the message.
+    fn matches(&self, msg: &Incoming) -> bool {
+        let channel_ok = match (&self.channel, &msg.channel) {
+            (None, None) => true,
+            (Some(wanted), Some((index, label))) => {
+                wanted == "*"
+                    || *wanted == index.to_string()
+                    || channel_label(0, Some(wanted)).eq_ignore_ascii_case(label)
+            }
+            _ => false,
+        };
+        let sender_ok = self.from.as_ref().is_none_or(|from| {
+            msg.sender.eq_ignore_ascii_case(from)
+                || msg
+                    .sender_key
+                    .as_deref()
+                    .is_some_and(|key| key.starts_with(&from.to_lowercase()))
+        });
+
+        channel_ok && sender_ok && self.pattern.is_match(&msg.text)
+    }
+}
+
+/// Compiled bot rules.
+#[derive(Debug)]
+pub struct Rules {
+    /// Reply limits.
+    pub limits: Limits,
+    /// Rules, in match order.
+    pub rules: Vec<Rule>,
+}
+
+impl Rules {
+    /// Loads and compiles a rules file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        Self::parse(&content).map_err(|e| match e {
+            CliError::Config(message) => CliError::Config(format!("{}: {message}", path.display())),
+            e => e,
+        })
+    }
+
+    /// Parses and compiles rules from TOML.
+    pub fn parse(content: &str) -> Result<Self> {
+        let file: RulesFile =
+            toml::from_str(content).map_err(|e| CliError::Config(e.to_string()))?;
+
+        let rules = file
+            .rules
+            .into_iter()
+            .enumerate()
+            .map(|(i, rule)| {
+                let name = rule.name.unwrap_or_else(|| format!("rule {}", i + 1));
+                let pattern = Regex::new(&rule.pattern)
+                    .map_err(|e| CliError::Config(format!("{name}: invalid pattern: {e}")))?;
+                Ok(Rule {
+                    name,
+                    pattern,
+                    from: rule.from,
+                    channel: rule.channel,
+                    reply: rule.reply,
+                    cooldown: rule.cooldown_secs.map(Duration::from_secs),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            limits: file.limits,
+            rules,
+        })
+    }
+
+    /// Returns the first rule matching the message.
+    fn find(&self, msg: &Incoming) -> Option<&Rule> {
+        self.rules.iter().find(|rule| rule.matches(msg))
+    }
+}
+
+/// Per-sender reply bookkeeping.
+#[derive(Debug, Default)]
+struct RateLimiter {
+    replies: HashMap<String, VecDeque<Instant>>,
+}
+
+impl RateLimiter {
+    /// Records a reply to `sender` at `now` if the limits allow it.
+    fn allow(&mut self, sender: &str, limits: &Limits, cooldown: Duration, now: Instant) -> bool {
+        let window = Duration::from_secs(limits.window_secs);
+        let sent = self.replies.entry(sender.to_string()).or_default();
+        while sent
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= window)
+        {
+            sent.pop_front();
+        }
+
+        let cooling_down = sent
+            .back()
+            .is_some_and(|t| now.duration_since(*t) < cooldown);
+        if cooling_down || sent.len() >= limits.max_replies {
+            return false;
+        }
+        sent.push_back(now);
+        true
+    }
+}
+
+/// An incoming message, as seen by the rules.
+#[derive(Debug, Clone)]
+struct Incoming {
+    /// Sender name (or key prefix when unknown).
+    sender: String,
+    /// Sender public key prefix, in hex (direct messages only).
+    sender_key: Option<String>,
+    /// Channel index and label, for channel messages.
+    channel: Option<(u8, String)>,
+    text: String,
+    snr: Option<f32>,
+    path_len: u8,
+}
+
+/// Replaces `{name}` placeholders with their values in one pass, so values that
+/// contain braces (like the message text) are never expanded. Unknown ones are kept.
+fn render(template: &str, vars: &[(&str, String)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after.find('}').and_then(|end| {
+            vars.iter()
+                .find(|(name, _)| *name == &after[..end])
+                .map(|(_, value)| (value, end))
+        });
+        if let Some((value, end)) = value {
+            out.push_str(value);
+            rest = &after[end + 1..];
+        } else {
+            out.push('{');
+            rest = after;
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Truncates text to the message limit without splitting a UTF-8 character.
+fn truncate(mut text: String, limit: usize) -> String {
+    if text.len() > limit {
+        let mut end = limit;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+    }
+    text
+}
+
+/// Runs the bot until Ctrl+C.
+pub async fn run<T: Transport>(ctx: &CommandContext<T>, path: &Path) -> Result<()> {
+    let rules = Rules::load(path)?;
+    ctx.display.print_ok(&format!(
+        "bot running with {} rule(s) from {}",
+        rules.rules.len(),
+        path.display()
+    ));
+
+    let mut limiter = RateLimiter::default();
+    let mut events = ctx.event_stream().await;
+    loop {
+        tokio::select! {
+            event = events.next() => {
+                let Some(event) = event else { break };
+                // A failed fetch (e.g. a device timeout) must not stop the bot
+                let events = match ctx.resolve_waiting(event).await {
+                    Ok(events) => events,
+                    Err(e) => {
+                        ctx.display
+                            .print_warning(&format!("Failed to fetch waiting messages: {e}"));
+                        continue;
+                    }
+                };
+                for event in events {
+                    if let Some(msg) = incoming(ctx, &event).await {
+                        handle(ctx, &rules, &mut limiter, &msg).await;
+                    }
+                }
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the message carried by an event, if any.
+async fn incoming<T: Transport>(ctx: &CommandContext<T>, event: &Event) -> Option<Incoming> {
+    match event {
+        Event::ContactMessage(msg) => Some(from_contact(ctx, msg).await),
+        Event::ChannelMessage(msg) => Some(from_channel(ctx, msg).await),
+        _ => None,
+    }
+}
+
+async fn from_contact<T: Transport>(ctx: &CommandContext<T>, msg: &ContactMessage) -> Incoming {
+    let contacts = ctx.client.lock().await.contacts().await;
+    Incoming {
+        sender: lookup_sender_name(&contacts, &msg.sender_prefix),
+        sender_key: Some(hex::encode(msg.sender_prefix)),
+        channel: None,
+        text: msg.text.clone(),
+        snr: msg.signal.as_ref().map(|s| s.snr),
+        path_len: msg.path_len,
+    }
+}
+
+async fn from_channel<T: Transport>(ctx: &CommandContext<T>, msg: &ChannelMessage) -> Incoming {
+    let name = ctx
+        .state
+        .lock()
+        .await
+        .channels
+        .get(&msg.channel_index)
+        .cloned();
+    let (sender, text) = split_channel_sender(&msg.text);
+    Incoming {
+        sender: sender.unwrap_or("unknown").to_string(),
+        sender_key: None,
+        channel: Some((
+            msg.channel_index,
+            channel_label(msg.channel_index, name.as_deref()),
+        )),
+        text: text.to_string(),
+        snr: msg.signal.as_ref().map(|s| s.snr),
+        path_len: msg.path_len,
+    }
+}
+
+/// Answers a message if a rule matches and the sender isn't rate limited.
+async fn handle<T: Transport>(
+    ctx: &CommandContext<T>,
+    rules: &Rules,
+    limiter: &mut RateLimiter,
+    msg: &Incoming,
+) {
+    let Some(rule) = rules.find(msg) else {
+        return;
+    };
+
+    let cooldown = rule
+        .cooldown
+        .unwrap_or(Duration::from_secs(rules.limits.cooldown_secs));
+    if !limiter.allow(&msg.sender, &rules.limits, cooldown, Instant::now()) {
+        tracing::info!("Rate limited reply to {} ({})", msg.sender, rule.name);
+        return;
+    }
+
+    let reply = render(&rule.reply, &variables(ctx, msg, &rule.reply).await);
+    if let Err(e) = send_reply(ctx, msg, &reply).await {
+        ctx.display
+            .print_warning(&format!("Reply to {} failed: {e}", msg.sender));
+        return;
+    }
+    ctx.display.print_bot_reply(
+        &rule.name,
+        &msg.sender,
+        msg.channel.as_ref().map(|(_, label)| label.as_str()),
+        &reply,
+    );
+}
+
+/// Collects template variables; the battery is only read when the template uses it.
+async fn variables<T: Transport>(
+    ctx: &CommandContext<T>,
+    msg: &Incoming,
+    template: &str,
+) -> Vec<(&'static str, String)> {
+    let mut vars = vec![
+        ("sender", msg.sender.clone()),
+        ("text", msg.text.clone()),
+        (
+            "channel",
+            msg.channel
+                .as_ref()
+                .map_or_else(String::new, |(_, label)| label.clone()),
+        ),
+        (
+            "snr",
+            msg.snr
+                .map_or_else(|| "?".to_string(), |snr| format!("{snr:.1}")),
+        ),
+        (
+            "hops",
+            if msg.path_len == PATH_LEN_DIRECT {
+                "direct".to_string()
+            } else {
+                msg.path_len.to_string()
+            },
+        ),
+        ("time", Local::now().format("%H:%M:%S").to_string()),
+    ];
+
+    if template.contains("{battery}") {
+        let battery = match ctx.commands().await.get_battery().await {
+            Ok(Event::Battery(battery)) => {
+                format!("{:.2}V", f64::from(battery.millivolts) / 1000.0)
+            }
+            _ => "?".to_string(),
+        };
+        vars.push(("battery", battery));
+    }
+
+    vars
+}
+
+/// Sends a reply back where the message came from.
+async fn send_reply<T: Transport>(
+    ctx: &CommandContext<T>,
+    msg: &Incoming,
+    reply: &str,
+) -> Result<()> {
+    let event = if let Some((index, _)) = &msg.channel {
+        let text = truncate(reply.to_string(), ctx.channel_text_limit().await);
+        let event = ctx
+            .commands()
+            .await
+            .send_channel_message(*index, &text, current_timestamp())
+            .await?;
+        ctx.record(&HistoryEntry::channel_sent(*index, &text));
+        event
+    } else {
+        let contact = ctx
+            .get_contact(msg.sender_key.as_deref().unwrap_or(&msg.sender))
+            .await?;
+        let text = truncate(reply.to_string(), MAX_TEXT_LEN);
+        let event = ctx
+            .commands()
+            .await
+            .send_message(&contact.public_key, &text, 0, current_timestamp())
+            .await?;
+        let ack = match event {
+            Event::MessageSent { expected_ack, .. } => Some(expected_ack),
+            _ => None,
+        };
+        ctx.record(&HistoryEntry::sent(
+            &contact.name,
+            &contact.public_key.to_hex(),
+            &text,
+            ack,
+        ));
+        event
+    };
+
+    match event {
+        Event::Error { message } => Err(CliError::Command(message)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &str = r##"
+[limits]
+max_replies = 2
+window_secs = 60
+cooldown_secs = 10
+
+[[rule]]
+name = "ping"
+match = "(?i)^ping$"
+reply = "pong {sender} ({snr} dB, {hops} hops)"
+
+[[rule]]
+channel = "#test"
+from = "Alice"
+match = "^!info"
+reply = "{sender} on {channel}"
+cooldown_secs = 0
+"##;
+
+    fn direct(sender: &str, text: &str) -> Incoming {
+        Incoming {
+            sender: sender.into(),
+            sender_key: Some("112233445566".into()),
+            channel: None,
+            text: text.into(),
+            snr: Some(6.25),
+            path_len: 2,
+        }
+    }
+
+    fn channel(sender: &str, label: &str, text: &str) -> Incoming {
+        Incoming {
+            channel: Some((1, label.into())),
+            sender_key: None,
+            ..direct(sender, text)
+        }
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let rules = Rules::parse(RULES).unwrap();
+        assert_eq!(rules.rules.len(), 2);
+        assert_eq!(rules.limits.max_replies, 2);
+        assert_eq!(rules.rules[0].name, "ping");
+        assert_eq!(rules.rules[1].name, "rule 2");
+
+        let err = Rules::parse("[[rule]]\nmatch = \"(\"\nreply = \"x\"\n").unwrap_err();
+        assert!(matches!(err, CliError::Config(_)));
+    }
+
+    #[test]
+    fn test_rule_matching() {
+        let rules = Rules::parse(RULES).unwrap();
+
+        assert_eq!(rules.find(&direct("Bob", "PING")).unwrap().name, "ping");
+        assert!(rules.find(&direct("Bob", "ping me")).is_none());
+        // Direct-message rules don't answer on channels
+        assert!(rules.find(&channel("Bob", "#test", "ping")).is_none());
+
+        assert_eq!(
+            rules
+                .find(&channel("alice", "#Test", "!info"))
+                .unwrap()
+                .name,
+            "rule 2"
+        );
+        assert!(rules.find(&channel("Bob", "#test", "!info")).is_none());
+        assert!(rules.find(&channel("Alice", "#other", "!info")).is_none());
+        assert!(rules.find(&direct("Alice", "!info")).is_none());
+    }
+
+    #[test]
+    fn test_render() {
+        let vars = [("sender", "Bob".to_string()), ("snr", "6.2".to_string())];
+        assert_eq!(
+            render("pong {sender} {snr} {unknown}", &vars),
+            "pong Bob 6.2 {unknown}"
+        );
+        assert_eq!(truncate("ää".into(), 3), "ä");
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let limits = Limits {
+            max_replies: 2,
+            window_secs: 60,
+            cooldown_secs: 10,
+        };
+        let cooldown = Duration::from_secs(10);
+        let mut limiter = RateLimiter::default();
+        let start = Instant::now();
+
+        assert!(limiter.allow("Bob", &limits, cooldown, start));
+        // Cooldown
+        assert!(!limiter.allow("Bob", &limits, cooldown, start + Duration::from_secs(5)));
+        assert!(limiter.allow("Alice", &limits, cooldown, start + Duration::from_secs(5)));
+        assert!(limiter.allow("Bob", &limits, cooldown, start + Duration::from_secs(11)));
+        // Window full
+        assert!(!limiter.allow("Bob", &limits, cooldown, start + Duration::from_secs(30)));
+        // First reply left the window
+        assert!(limiter.allow("Bob", &limits, cooldown, start + Duration::from_secs(61)));
+    }
+}
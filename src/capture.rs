@@ -0,0 +1,582 @@
+//! Frame capture and offline replay.
+//!
+//! A capture records every raw frame exchanged with the radio as a JSON line with a
+//! timestamp relative to the start of the session. `ReplayTransport` plays a capture
+//! back in place of the radio, so a misbehaving session can be reproduced without the
+//! user's hardware.
+//!
+//! Captures are meant to be attached to bug reports, so frames carrying secrets
+//! (login passwords, private keys, channel secrets) are recorded with those bytes
+//! zeroed.
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use meshcore::transport::Transport;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::display::Display;
+use crate::error::{CliError, Result};
+
+/// Frame direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// Command sent to the radio.
+    Tx,
+    /// Response or push received from the radio.
+    Rx,
+}
+
+/// A captured frame, stored as one JSON line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureRecord {
+    /// Milliseconds since the start of the capture.
+    pub t_ms: u64,
+    /// Frame direction.
+    pub dir: Direction,
+    /// Frame bytes, hex encoded.
+    pub frame: String,
+}
+
+impl CaptureRecord {
+    /// Returns the frame bytes.
+    pub fn bytes(&self) -> Result<Vec<u8>> {
+        hex::decode(&self.frame)
+            .map_err(|e| CliError::InvalidArgument(format!("Invalid frame in capture: {e}")))
+    }
+
+    /// Returns the packet type name of the frame.
+    #[must_use]
+    pub fn packet_type(&self) -> &'static str {
+        let code = hex::decode(self.frame.get(..2).unwrap_or_default())
+            .ok()
+            .and_then(|b| b.first().copied());
+        match (self.dir, code) {
+            (_, None) => "Empty",
+            (Direction::Tx, Some(code)) => command_name(code),
+            (Direction::Rx, Some(code)) => response_name(code),
+        }
+    }
+}
+
+/// Appends frames to a capture file.
+#[derive(Debug, Clone)]
+struct CaptureWriter {
+    file: Arc<Mutex<File>>,
+    started: Instant,
+}
+
+impl CaptureWriter {
+    /// Creates (or truncates) a capture file.
+    fn create(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        Ok(Self {
+            file: Arc::new(Mutex::new(File::create(path)?)),
+            started: Instant::now(),
+        })
+    }
+
+    /// Records a frame. Write errors are logged so they never break the session.
+    fn record(&self, dir: Direction, frame: &[u8]) {
+        let record = CaptureRecord {
+            t_ms: u64::try_from(self.started.elapsed().as_millis()).unwrap_or(u64::MAX),
+            dir,
+            frame: hex::encode(redact(dir, frame)),
+        };
+        let Ok(line) = serde_json::to_string(&record) else {
+            return;
+        };
+
+        let mut file = self
+            .file
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if let Err(e) = writeln!(file, "{line}") {
+            tracing::warn!("Failed to write capture: {e}");
+        }
+    }
+}
+
+/// Offset of the channel secret in `SetChannel` and `ChannelInfo` frames, after the code,
+/// index and 32-byte name.
+const CHANNEL_SECRET_OFFSET: usize = 34;
+
+/// Returns the frame as it may be recorded: frames carrying secrets keep their code and
+/// length, but the secret bytes are zeroed.
+fn redact(dir: Direction, frame: &[u8]) -> Cow<'_, [u8]> {
+    let secret = match (dir, frame.first()) {
+        // SendLogin and ImportPrivateKey commands, PrivateKey responses
+        (Direction::Tx, Some(26 | 24)) | (Direction::Rx, Some(14)) => 1..frame.len(),
+        // SetChannel commands and ChannelInfo responses keep their index and name
+        (Direction::Tx, Some(32)) | (Direction::Rx, Some(18)) => {
+            CHANNEL_SECRET_OFFSET.min(frame.len())..frame.len()
+        }
+        _ => return Cow::Borrowed(frame),
+    };
+
+    let mut redacted = frame.to_vec();
+    redacted[secret].fill(0);
+    Cow::Owned(redacted)
+}
+
+/// Transport wrapper that records all frames to a capture file, if one is given.
+pub struct CaptureTransport<T> {
+    inner: T,
+    capture: Option<CaptureWriter>,
+}
+
+impl<T: Transport> CaptureTransport<T> {
+    /// Wraps a transport, capturing its frames to `path` (passes frames through if `None`).
+    pub fn new(inner: T, path: Option<&Path>) -> Result<Self> {
+        Ok(Self {
+            inner,
+            capture: path.map(CaptureWriter::create).transpose()?,
+        })
+    }
+}
+
+impl<T: Transport> Transport for CaptureTransport<T> {
+    async fn connect(
+        &mut self,
+    ) -> std::result::Result<mpsc::UnboundedReceiver<Vec<u8>>, meshcore::Error> {
+        let mut frames = self.inner.connect().await?;
+        let Some(capture) = self.capture.clone() else {
+            return Ok(frames);
+        };
+
+        // Record received frames on their way to the client
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(frame) = frames.recv().await {
+                capture.record(Direction::Rx, &frame);
+                if tx.send(frame).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(rx)
+    }
+
+    async fn disconnect(&mut self) -> std::result::Result<(), meshcore::Error> {
+        self.inner.disconnect().await
+    }
+
+    async fn send(&mut self, frame: &[u8]) -> std::result::Result<(), meshcore::Error> {
+        if let Some(capture) = &self.capture {
+            capture.record(Direction::Tx, frame);
+        }
+        self.inner.send(frame).await
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+}
+
+/// Reads all records of a capture file.
+pub fn read_capture(path: &Path) -> Result<Vec<CaptureRecord>> {
+    let file = File::open(path)
+        .map_err(|e| CliError::InvalidArgument(format!("{}: {e}", path.display())))?;
+
+    let mut records = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line).map_err(|e| {
+            CliError::InvalidArgument(format!("{}:{}: {e}", path.display(), index + 1))
+        })?;
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+/// Prints the frames of a capture file.
+///
+/// This doesn't need a device connection, so it can run before connecting.
+pub fn decode(display: &Display, path: &Path) -> Result<()> {
+    display.print_capture(&read_capture(path)?);
+    Ok(())
+}
+
+/// Transport that answers commands from a capture instead of a radio.
+///
+/// Each command is matched with the next recorded command with the same code; the
+/// frames received after it are replayed with their recorded delays.
+pub struct ReplayTransport {
+    records: VecDeque<CaptureRecord>,
+    link: Option<mpsc::UnboundedSender<Vec<u8>>>,
+}
+
+impl ReplayTransport {
+    /// Loads a capture file for replay.
+    pub fn open(path: &Path) -> Result<Self> {
+        let records = read_capture(path)?;
+        for record in &records {
+            record.bytes()?;
+        }
+
+        Ok(Self {
+            records: records.into(),
+            link: None,
+        })
+    }
+
+    /// Removes the frames received before the next command.
+    fn take_received(&mut self) -> Vec<CaptureRecord> {
+        let count = self
+            .records
+            .iter()
+            .position(|r| r.dir == Direction::Tx)
+            .unwrap_or(self.records.len());
+        self.records.drain(..count).collect()
+    }
+
+    /// Sends frames to the client, delayed relative to `start_ms` as recorded.
+    fn replay(&self, start_ms: u64, records: Vec<CaptureRecord>) {
+        let Some(link) = self.link.clone() else {
+            return;
+        };
+
+        tokio::spawn(async move {
+            let started = tokio::time::Instant::now();
+            for record in records {
+                let delay = Duration::from_millis(record.t_ms.saturating_sub(start_ms));
+                tokio::time::sleep_until(started + delay).await;
+                if let Ok(frame) = record.bytes()
+                    && link.send(frame).is_err()
+                {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+impl Transport for ReplayTransport {
+    async fn connect(
+        &mut self,
+    ) -> std::result::Result<mpsc::UnboundedReceiver<Vec<u8>>, meshcore::Error> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.link = Some(tx);
+
+        // Frames pushed before the first command
+        let leading = self.take_received();
+        let start_ms = leading.first().map_or(0, |r| r.t_ms);
+        self.replay(start_ms, leading);
+        Ok(rx)
+    }
+
+    async fn disconnect(&mut self) -> std::result::Result<(), meshcore::Error> {
+        self.link = None;
+        Ok(())
+    }
+
+    async fn send(&mut self, frame: &[u8]) -> std::result::Result<(), meshcore::Error> {
+        let code = hex::encode(frame.get(..1).unwrap_or_default());
+        let Some(position) = self
+            .records
+            .iter()
+            .position(|r| r.dir == Direction::Tx && r.frame.starts_with(&code))
+        else {
+            tracing::warn!(
+                "No recorded response for {} in capture",
+                command_name(frame.first().copied().unwrap_or_default())
+            );
+            return Ok(());
+        };
+
+        // Frames between the previous command and the matched one don't belong to it
+        let skipped: Vec<_> = self.records.drain(..position).collect();
+        if !skipped.is_empty() {
+            tracing::debug!("Skipping {} recorded frames", skipped.len());
+        }
+        let command = self.records.pop_front();
+        if command
+            .as_ref()
+            .is_some_and(|c| c.frame != hex::encode(frame))
+        {
+            tracing::debug!("Command differs from the recording: {}", hex::encode(frame));
+        }
+
+        let responses = self.take_received();
+        self.replay(command.map_or(0, |c| c.t_ms), responses);
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.link.is_some()
+    }
+}
+
+/// Returns the packet type name of a command code.
+#[must_use]
+pub fn command_name(code: u8) -> &'static str {
+    match code {
+        1 => "AppStart",
+        2 => "SendTxtMsg",
+        3 => "SendChannelTxtMsg",
+        4 => "GetContacts",
+        5 => "GetDeviceTime",
+        6 => "SetDeviceTime",
+        7 => "SendSelfAdvert",
+        8 => "SetAdvertName",
+        9 => "AddUpdateContact",
+        10 => "SyncNextMessage",
+        11 => "SetRadioParams",
+        12 => "SetRadioTxPower",
+        13 => "ResetPath",
+        14 => "SetAdvertLatLon",
+        15 => "RemoveContact",
+        16 => "ShareContact",
+        17 => "ExportContact",
+        18 => "ImportContact",
+        19 => "Reboot",
+        20 => "GetBattAndStorage",
+        21 => "SetTuningParams",
+        22 => "DeviceQuery",
+        23 => "ExportPrivateKey",
+        24 => "ImportPrivateKey",
+        25 => "SendRawData",
+        26 => "SendLogin",
+        27 => "SendStatusReq",
+        28 => "HasConnection",
+        29 => "Logout",
+        30 => "GetContactByKey",
+        31 => "GetChannel",
+        32 => "SetChannel",
+        33 => "SignStart",
+        34 => "SignData",
+        35 => "SignFinish",
+        36 => "SendTracePath",
+        37 => "SetDevicePin",
+        38 => "SetOtherParams",
+        39 => "SendTelemetryReq",
+        40 => "GetCustomVars",
+        41 => "SetCustomVar",
+        42 => "GetAdvertPath",
+        43 => "GetTuningParams",
+        50 => "SendBinaryReq",
+        51 => "FactoryReset",
+        52 => "SendPathDiscoveryReq",
+        54 => "SetFloodScope",
+        55 => "SendControlData",
+        56 => "GetStats",
+        _ => "UnknownCommand",
+    }
+}
+
+/// Returns the packet type name of a response or push code.
+#[must_use]
+pub fn response_name(code: u8) -> &'static str {
+    match code {
+        0 => "Ok",
+        1 => "Error",
+        2 => "ContactsStart",
+        3 => "Contact",
+        4 => "EndOfContacts",
+        5 => "SelfInfo",
+        6 => "MsgSent",
+        7 => "ContactMsgRecv",
+        8 => "ChannelMsgRecv",
+        9 => "CurrentTime",
+        10 => "NoMoreMessages",
+        11 => "ExportContact",
+        12 => "Battery",
+        13 => "DeviceInfo",
+        14 => "PrivateKey",
+        15 => "Disabled",
+        16 => "ContactMsgRecvV3",
+        17 => "ChannelMsgRecvV3",
+        18 => "ChannelInfo",
+        19 => "SignStart",
+        20 => "Signature",
+        21 => "CustomVars",
+        22 => "AdvertPath",
+        23 => "TuningParams",
+        24 => "Stats",
+        0x80 => "Advertisement",
+        0x81 => "PathUpdate",
+        0x82 => "Ack",
+        0x83 => "MessagesWaiting",
+        0x84 => "RawData",
+        0x85 => "LoginSuccess",
+        0x86 => "LoginFailed",
+        0x87 => "StatusResponse",
+        0x88 => "LogRxData",
+        0x89 => "TraceData",
+        0x8a => "NewAdvert",
+        0x8b => "TelemetryResponse",
+        0x8c => "BinaryResponse",
+        0x8d => "PathDiscoveryResponse",
+        0x8e => "ControlData",
+        _ => "Unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(t_ms: u64, dir: Direction, frame: &str) -> CaptureRecord {
+        CaptureRecord {
+            t_ms,
+            dir,
+            frame: frame.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_packet_type() {
+        assert_eq!(record(0, Direction::Tx, "0103").packet_type(), "AppStart");
+        assert_eq!(record(0, Direction::Rx, "05aa").packet_type(), "SelfInfo");
+        assert_eq!(record(0, Direction::Rx, "82deadbeef").packet_type(), "Ack");
+        assert_eq!(record(0, Direction::Rx, "").packet_type(), "Empty");
+        assert_eq!(
+            record(0, Direction::Tx, "7f").packet_type(),
+            "UnknownCommand"
+        );
+    }
+
+    #[test]
+    fn test_capture_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.mcap");
+
+        let writer = CaptureWriter::create(&path).unwrap();
+        writer.record(Direction::Tx, &[1, 3]);
+        writer.record(Direction::Rx, &[5, 0xaa]);
+
+        let records = read_capture(&path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].dir, Direction::Tx);
+        assert_eq!(records[0].frame, "0103");
+        assert_eq!(records[1].bytes().unwrap(), vec![5, 0xaa]);
+        assert!(records[0].t_ms <= records[1].t_ms);
+
+        fs::write(
+            &path,
+            "{\"t_ms\":0,\"dir\":\"sideways\",\"frame\":\"01\"}\n",
+        )
+        .unwrap();
+        assert!(read_capture(&path).is_err());
+    }
+
+    #[test]
+    fn test_secrets_are_redacted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.mcap");
+
+        let mut login = vec![26];
+        login.extend_from_slice(&[0x22; 32]);
+        login.extend_from_slice(b"hunter2");
+        let mut import_key = vec![24];
+        import_key.extend_from_slice(&[0x5a; 64]);
+        let mut export_key = vec![14];
+        export_key.extend_from_slice(&[0x5b; 64]);
+
+        let writer = CaptureWriter::create(&path).unwrap();
+        writer.record(Direction::Tx, &login);
+        writer.record(Direction::Tx, &import_key);
+        writer.record(Direction::Rx, &export_key);
+        writer.record(Direction::Tx, &[14, 1, 2]);
+
+        let records = read_capture(&path).unwrap();
+        let expected = [(&login, "SendLogin"), (&import_key, "ImportPrivateKey")];
+        for (record, (frame, name)) in records.iter().zip(expected) {
+            let bytes = record.bytes().unwrap();
+            assert_eq!(record.packet_type(), name);
+            assert_eq!(bytes.len(), frame.len());
+            assert!(bytes[1..].iter().all(|&b| b == 0));
+        }
+        assert_eq!(records[2].packet_type(), "PrivateKey");
+        assert_eq!(records[2].bytes().unwrap(), {
+            let mut zeroed = vec![0; 65];
+            zeroed[0] = 14;
+            zeroed
+        });
+        assert!(
+            !fs::read_to_string(&path)
+                .unwrap()
+                .contains(&hex::encode("hunter2"))
+        );
+
+        // SetAdvertLatLon shares code 14 with PrivateKey but isn't secret
+        assert_eq!(records[3].frame, "0e0102");
+    }
+
+    #[test]
+    fn test_channel_secrets_are_redacted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.mcap");
+
+        let mut name = [0; 32];
+        name[..5].copy_from_slice(b"#team");
+        let mut set_channel = vec![32, 3];
+        set_channel.extend_from_slice(&name);
+        set_channel.extend_from_slice(&[0x7c; 16]);
+        let mut channel_info = set_channel.clone();
+        channel_info[0] = 18;
+
+        let writer = CaptureWriter::create(&path).unwrap();
+        writer.record(Direction::Tx, &set_channel);
+        writer.record(Direction::Rx, &channel_info);
+        // ImportContact shares code 18 with ChannelInfo but isn't secret
+        writer.record(Direction::Tx, &channel_info);
+
+        let records = read_capture(&path).unwrap();
+        assert_eq!(records[0].packet_type(), "SetChannel");
+        assert_eq!(records[1].packet_type(), "ChannelInfo");
+        for (record, frame) in records.iter().zip([&set_channel, &channel_info]) {
+            let bytes = record.bytes().unwrap();
+            assert_eq!(bytes.len(), frame.len());
+            assert_eq!(bytes[..34], frame[..34]);
+            assert!(bytes[34..].iter().all(|&b| b == 0));
+        }
+        assert_eq!(records[2].bytes().unwrap(), channel_info);
+    }
+
+    #[tokio::test]
+    async fn test_replay_answers_matching_commands() {
+        let mut replay = ReplayTransport {
+            records: vec![
+                record(0, Direction::Rx, "83"),
+                record(10, Direction::Tx, "0103"),
+                record(15, Direction::Rx, "05aa"),
+                record(20, Direction::Tx, "14"),
+                record(25, Direction::Rx, "0c9e0f"),
+                record(30, Direction::Tx, "16"),
+                record(40, Direction::Rx, "0d08"),
+            ]
+            .into(),
+            link: None,
+        };
+
+        let mut frames = replay.connect().await.unwrap();
+        assert_eq!(frames.recv().await, Some(vec![0x83]));
+
+        replay.send(&[1, 3]).await.unwrap();
+        assert_eq!(frames.recv().await, Some(vec![5, 0xaa]));
+
+        // Battery was never requested in this replay, device query skips past it
+        replay.send(&[0x16]).await.unwrap();
+        assert_eq!(frames.recv().await, Some(vec![0x0d, 8]));
+
+        // Nothing left to answer with
+        replay.send(&[0x14]).await.unwrap();
+        assert!(replay.records.is_empty());
+        replay.disconnect().await.unwrap();
+        assert_eq!(frames.recv().await, None);
+    }
+}
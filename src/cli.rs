@@ -1,28 +1,63 @@
 //! Command line argument parsing.
 
-use clap::{Parser, Subcommand, ValueEnum};
+use std::fmt::Write;
+
+use std::path::PathBuf;
+
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
 
 /// `MeshCore` CLI - Command line interface to `MeshCore` companion radios.
 #[derive(Parser, Debug)]
 #[command(name = "meshcore-cli-rs")]
 #[command(author, version, about, long_about = None)]
 #[command(propagate_version = true)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Cli {
     /// JSON output mode (disables init file).
     #[arg(short = 'j', long, global = true)]
     pub json: bool,
 
+    /// Output format (every format but `human` disables the init file, like `-j`).
+    #[arg(long, value_name = "FORMAT", global = true, conflicts_with = "json")]
+    pub format: Option<OutputFormat>,
+
     /// Debug logging.
     #[arg(short = 'D', long, global = true)]
     pub debug: bool,
 
-    /// Serial port to use.
+    /// Device profile from the config directory (`profiles/<NAME>.toml`).
+    #[arg(short = 'p', long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Serial port to use, or "auto" to probe all ports (defaults to `default_port` from the
+    /// config file, or auto-detection).
     #[arg(short = 's', long, value_name = "PORT")]
     pub serial: Option<String>,
 
-    /// Baud rate for serial port.
-    #[arg(short = 'b', long, value_name = "BAUD", default_value = "115200")]
-    pub baudrate: u32,
+    /// Name of the radio to use when auto-detecting the serial port.
+    #[arg(long, value_name = "NAME", conflicts_with = "tcp")]
+    pub device_name: Option<String>,
+
+    /// TCP address of a networked companion radio.
+    #[arg(short = 't', long, value_name = "HOST:PORT", conflicts_with = "serial")]
+    pub tcp: Option<String>,
+
+    /// Run commands through a daemon listening on this Unix socket.
+    #[arg(long, value_name = "SOCKET", conflicts_with_all = ["serial", "tcp"])]
+    pub remote: Option<PathBuf>,
+
+    /// Record every frame exchanged with the radio to a capture file.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["remote", "replay"])]
+    pub capture: Option<PathBuf>,
+
+    /// Replay a capture file instead of connecting to a radio.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["serial", "tcp", "remote", "device_name"])]
+    pub replay: Option<PathBuf>,
+
+    /// Baud rate for serial port (defaults to `default_baudrate` from the config file, or 115200).
+    #[arg(short = 'b', long, value_name = "BAUD")]
+    pub baudrate: Option<u32>,
 
     /// Disable color output.
     #[arg(short = 'c', long, value_name = "on/off", value_parser = parse_bool_arg)]
@@ -32,6 +67,14 @@ pub struct Cli {
     #[arg(short = 'l', long)]
     pub list: bool,
 
+    /// With `-l`, also probe each port for a radio (opens every port and sends `app_start`).
+    #[arg(long, requires = "list")]
+    pub probe: bool,
+
+    /// Don't reconnect when the device is lost (repeater passwords are then not kept).
+    #[arg(long)]
+    pub no_reconnect: bool,
+
     /// Commands to execute (can be chained).
     #[command(subcommand)]
     pub command: Option<Command>,
@@ -48,17 +91,19 @@ fn parse_bool_arg(s: &str) -> Result<bool, String> {
 }
 
 /// CLI commands.
-#[derive(Subcommand, Debug, Clone)]
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Command {
     // ==================== General Commands ====================
     /// Enter interactive chat mode.
     #[command(visible_aliases = ["interactive", "im"])]
     Chat,
 
-    /// Enter chat with a specific contact.
+    /// Enter chat with a specific contact or channel.
     #[command(visible_aliases = ["to", "imto"], name = "chat_to")]
     ChatTo {
-        /// Contact name or public key prefix.
+        /// Contact name or public key prefix, or a channel as `#name` or `ch:<n>`.
+        #[arg(value_name = "CONTACT")]
         contact: String,
     },
 
@@ -108,11 +153,77 @@ pub enum Command {
         commands: Vec<String>,
     },
 
+    /// Keep the device open and serve commands over a Unix socket (JSON-RPC).
+    Daemon {
+        /// Socket path (defaults to `daemon.sock` in the config directory).
+        #[arg(long, value_name = "SOCKET")]
+        socket: Option<PathBuf>,
+    },
+
+    /// Answer incoming messages according to a rules file.
+    Bot {
+        /// Rules file (TOML).
+        #[arg(value_name = "RULES")]
+        rules: PathBuf,
+    },
+
+    /// Bridge events and outgoing messages to an MQTT broker.
+    Mqtt {
+        /// Broker address (`host` or `host:port`, port 1883 by default).
+        #[arg(long, value_name = "HOST:PORT")]
+        broker: String,
+        /// Topic prefix; topics are `<prefix>/<device>/rx/...` and `.../tx/...`.
+        #[arg(long, default_value = "meshcore")]
+        prefix: String,
+        /// Broker username.
+        #[arg(long, requires = "password")]
+        username: Option<String>,
+        /// Broker password.
+        #[arg(
+            long,
+            requires = "username",
+            env = "MESHCORE_MQTT_PASSWORD",
+            hide_env_values = true
+        )]
+        password: Option<String>,
+    },
+
+    /// Serve an HTTP API with a WebSocket event stream.
+    Serve {
+        /// Address to listen on.
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+        listen: String,
+        /// Bearer token required on every request.
+        #[arg(long, env = "MESHCORE_API_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+    },
+
+    /// Serve a browser chat page (plus the HTTP API it uses).
+    Web {
+        /// Address to listen on.
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+        listen: String,
+        /// Bearer token required by the API (open the page with `?token=...`).
+        #[arg(long, env = "MESHCORE_API_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+    },
+
+    /// Act as an IRC server bridging channels and contacts to the mesh.
+    Ircd {
+        /// Address to listen on.
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:6667")]
+        listen: String,
+        /// Server password clients must send with PASS.
+        #[arg(long, env = "MESHCORE_IRC_PASSWORD", hide_env_values = true)]
+        password: Option<String>,
+    },
+
     // ==================== Messaging Commands ====================
     /// Send a private message.
-    #[command(visible_alias = "m")]
+    #[command(visible_alias = "m", alias = "{")]
     Msg {
         /// Recipient name or public key prefix.
+        #[arg(value_name = "CONTACT")]
         name: String,
         /// Message text.
         #[arg(trailing_var_arg = true)]
@@ -123,10 +234,27 @@ pub enum Command {
         /// Timeout in seconds when waiting for ACK.
         #[arg(short, long, default_value = "30")]
         timeout: u64,
+        /// Send up to N attempts until the message is acknowledged.
+        #[arg(short, long, value_name = "N")]
+        retry: Option<u32>,
+        /// Failed direct attempts before resetting the path and retrying via flood (0
+        /// floods from the start).
+        #[arg(long, value_name = "N", default_value = "2", requires = "retry")]
+        flood_after: u32,
+        /// Send oversized text as numbered parts, each waiting for its ACK.
+        #[arg(long, conflicts_with = "retry")]
+        split: bool,
+    },
+
+    /// Send a message to the current contact (selected with `to`).
+    Send {
+        /// Message text.
+        #[arg(trailing_var_arg = true, required = true)]
+        message: Vec<String>,
     },
 
     /// Wait for ACK.
-    #[command(visible_alias = "wa", name = "wait_ack")]
+    #[command(visible_alias = "wa", alias = "}", name = "wait_ack")]
     WaitAck {
         /// Timeout in seconds.
         #[arg(default_value = "30")]
@@ -136,11 +264,14 @@ pub enum Command {
     /// Send a channel message.
     #[command(visible_alias = "ch")]
     Chan {
-        /// Channel number (0-7).
-        channel: u8,
+        /// Channel number (0-7) or name.
+        channel: String,
         /// Message text.
         #[arg(trailing_var_arg = true)]
         message: Vec<String>,
+        /// Send oversized text as numbered parts.
+        #[arg(long)]
+        split: bool,
     },
 
     /// Send a message to the public channel (0).
@@ -149,6 +280,9 @@ pub enum Command {
         /// Message text.
         #[arg(trailing_var_arg = true)]
         message: Vec<String>,
+        /// Send oversized text as numbered parts.
+        #[arg(long)]
+        split: bool,
     },
 
     /// Read the next message.
@@ -178,6 +312,20 @@ pub enum Command {
     #[command(visible_alias = "ms", name = "msgs_subscribe")]
     MsgsSubscribe,
 
+    /// Show stored message history with a contact or channel.
+    #[command(visible_alias = "hist")]
+    History {
+        /// Contact name or public key prefix, or `#<channel>`.
+        #[arg(value_name = "CONTACT")]
+        target: String,
+        /// Only show messages newer than this (e.g. 2d, 12h, 30m).
+        #[arg(long)]
+        since: Option<String>,
+        /// Only show messages containing this text.
+        #[arg(long)]
+        grep: Option<String>,
+    },
+
     /// Get all channel information.
     #[command(visible_alias = "gc", name = "get_channels")]
     GetChannels,
@@ -242,7 +390,8 @@ pub enum Command {
         /// Parameter name (use "help" for list).
         param: String,
         /// Parameter value.
-        value: String,
+        #[arg(trailing_var_arg = true, required = true)]
+        value: Vec<String>,
     },
 
     /// Set device time.
@@ -286,13 +435,55 @@ pub enum Command {
     #[command(visible_alias = "ci", name = "contact_info")]
     ContactInfo {
         /// Contact name or public key prefix.
+        #[arg(value_name = "CONTACT")]
         contact: String,
     },
 
+    /// Print a contact's name (defaults to the current contact).
+    #[command(visible_alias = "cn", name = "contact_name")]
+    ContactName {
+        /// Contact name or public key prefix.
+        #[arg(value_name = "CONTACT")]
+        contact: Option<String>,
+    },
+
+    /// Print a contact's public key (defaults to the current contact).
+    #[command(visible_alias = "ck", name = "contact_key")]
+    ContactKey {
+        /// Contact name or public key prefix.
+        #[arg(value_name = "CONTACT")]
+        contact: Option<String>,
+    },
+
+    /// Print a contact's type (defaults to the current contact).
+    #[command(visible_alias = "ct", name = "contact_type")]
+    ContactType {
+        /// Contact name or public key prefix.
+        #[arg(value_name = "CONTACT")]
+        contact: Option<String>,
+    },
+
+    /// Print a contact's last modification time (defaults to the current contact).
+    #[command(visible_alias = "clm", name = "contact_lastmod")]
+    ContactLastmod {
+        /// Contact name or public key prefix.
+        #[arg(value_name = "CONTACT")]
+        contact: Option<String>,
+    },
+
+    /// Discover and display the path to a contact (defaults to the current contact).
+    #[command(visible_alias = "dt")]
+    Dtrace {
+        /// Contact name or public key prefix.
+        #[arg(value_name = "CONTACT")]
+        contact: Option<String>,
+    },
+
     /// Set temporary timeout for a contact.
     #[command(name = "contact_timeout")]
     ContactTimeout {
         /// Contact name or public key prefix.
+        #[arg(value_name = "CONTACT")]
         contact: String,
         /// Timeout in seconds.
         timeout: u64,
@@ -302,6 +493,7 @@ pub enum Command {
     #[command(visible_alias = "sc", name = "share_contact")]
     ShareContact {
         /// Contact name or public key prefix.
+        #[arg(value_name = "CONTACT")]
         contact: String,
     },
 
@@ -309,6 +501,7 @@ pub enum Command {
     #[command(visible_alias = "ec", name = "export_contact")]
     ExportContact {
         /// Contact name or public key prefix (empty for self).
+        #[arg(value_name = "CONTACT")]
         contact: Option<String>,
     },
 
@@ -323,12 +516,14 @@ pub enum Command {
     #[command(name = "remove_contact")]
     RemoveContact {
         /// Contact name or public key prefix.
+        #[arg(value_name = "CONTACT")]
         contact: String,
     },
 
     /// Display path to a contact.
     Path {
         /// Contact name or public key prefix.
+        #[arg(value_name = "CONTACT")]
         contact: String,
     },
 
@@ -336,6 +531,7 @@ pub enum Command {
     #[command(visible_alias = "dp", name = "disc_path")]
     DiscPath {
         /// Contact name or public key prefix.
+        #[arg(value_name = "CONTACT")]
         contact: String,
     },
 
@@ -343,6 +539,7 @@ pub enum Command {
     #[command(visible_alias = "rp", name = "reset_path")]
     ResetPath {
         /// Contact name or public key prefix.
+        #[arg(value_name = "CONTACT")]
         contact: String,
     },
 
@@ -350,6 +547,7 @@ pub enum Command {
     #[command(visible_alias = "cp", name = "change_path")]
     ChangePath {
         /// Contact name or public key prefix.
+        #[arg(value_name = "CONTACT")]
         contact: String,
         /// New path (comma-separated public key prefixes).
         path: String,
@@ -359,6 +557,7 @@ pub enum Command {
     #[command(visible_alias = "cf", name = "change_flags")]
     ChangeFlags {
         /// Contact name or public key prefix.
+        #[arg(value_name = "CONTACT")]
         contact: String,
         /// Flags to set/unset (e.g., "trusted", "hidden", "star").
         flags: String,
@@ -368,6 +567,7 @@ pub enum Command {
     #[command(visible_alias = "rt", name = "req_telemetry")]
     ReqTelemetry {
         /// Contact name or public key prefix.
+        #[arg(value_name = "CONTACT")]
         contact: String,
     },
 
@@ -375,6 +575,7 @@ pub enum Command {
     #[command(visible_alias = "rm", name = "req_mma")]
     ReqMma {
         /// Contact name or public key prefix.
+        #[arg(value_name = "CONTACT")]
         contact: String,
     },
 
@@ -382,6 +583,7 @@ pub enum Command {
     #[command(name = "req_acl")]
     ReqAcl {
         /// Contact name or public key prefix.
+        #[arg(value_name = "CONTACT")]
         contact: String,
     },
 
@@ -405,6 +607,7 @@ pub enum Command {
     #[command(visible_alias = "l")]
     Login {
         /// Repeater name or public key prefix.
+        #[arg(value_name = "CONTACT")]
         name: String,
         /// Password.
         password: String,
@@ -413,13 +616,15 @@ pub enum Command {
     /// Logout from a repeater.
     Logout {
         /// Repeater name or public key prefix.
+        #[arg(value_name = "CONTACT")]
         name: String,
     },
 
     /// Send a command to a repeater.
-    #[command(visible_alias = "c")]
+    #[command(visible_alias = "c", alias = "[")]
     Cmd {
         /// Repeater name or public key prefix.
+        #[arg(value_name = "CONTACT")]
         name: String,
         /// Command to send.
         #[arg(trailing_var_arg = true)]
@@ -433,13 +638,14 @@ pub enum Command {
     },
 
     /// Wait for message with 8 second timeout.
-    #[command(name = "wmt8")]
+    #[command(name = "wmt8", alias = "]")]
     Wmt8,
 
     /// Request status from a node.
     #[command(visible_alias = "rs", name = "req_status")]
     ReqStatus {
         /// Node name or public key prefix.
+        #[arg(value_name = "CONTACT")]
         name: String,
     },
 
@@ -447,6 +653,7 @@ pub enum Command {
     #[command(visible_alias = "rn", name = "req_neighbours")]
     ReqNeighbours {
         /// Node name or public key prefix.
+        #[arg(value_name = "CONTACT")]
         name: String,
     },
 
@@ -454,6 +661,7 @@ pub enum Command {
     #[command(visible_alias = "rb", name = "req_binary")]
     ReqBinary {
         /// Node name or public key prefix.
+        #[arg(value_name = "CONTACT")]
         name: String,
         /// Hex data (first byte is request type: 01=Status, 02=KeepAlive, 03=Telemetry, 04=MMA, 05=ACL, 06=Neighbours).
         data: String,
@@ -498,12 +706,135 @@ pub enum Command {
         /// Variable name.
         key: String,
         /// Variable value.
-        value: String,
+        #[arg(trailing_var_arg = true, required = true)]
+        value: Vec<String>,
+    },
+
+    /// Pretty-print the frames of a capture file (`--capture`).
+    #[command(name = "decode_capture", visible_alias = "decode-capture")]
+    DecodeCapture {
+        /// Capture file.
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+    },
+
+    // ==================== Configuration Commands ====================
+    /// Show or change settings persisted in the config file.
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
     },
 }
 
+/// Config file actions.
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigAction {
+    /// Show the current configuration.
+    Show,
+
+    /// Set and persist a configuration value.
+    Set {
+        /// Setting name (see `config show`).
+        key: String,
+        /// Value (`contact_timeout` takes a contact name and a timeout in seconds).
+        #[arg(trailing_var_arg = true, required = true)]
+        value: Vec<String>,
+    },
+
+    /// Remove a configuration value.
+    Unset {
+        /// Setting name.
+        key: String,
+        /// Contact name (for `contact_timeout`).
+        contact: Option<String>,
+    },
+}
+
+/// A single command line as accepted by scripts, init files, `apply_to` and interactive mode.
+///
+/// Shares the [`Command`] grammar with the CLI so every entry point accepts the same syntax.
+#[derive(Parser, Debug)]
+#[command(name = "command", no_binary_name = true, disable_version_flag = true)]
+struct CommandLine {
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Splits a command line into arguments, honouring shell-style quoting.
+pub fn split_line(line: &str) -> Result<Vec<String>, shell_words::ParseError> {
+    shell_words::split(line)
+}
+
+/// Parses already split arguments into a command.
+///
+/// The command name is matched case-insensitively.
+pub fn parse_args(mut args: Vec<String>) -> Result<Command, clap::Error> {
+    if let Some(name) = args.first_mut() {
+        *name = name.to_lowercase();
+    }
+    CommandLine::try_parse_from(args).map(|line| line.command)
+}
+
+/// Finds a command definition by name or alias.
+fn find_command(name: &str) -> Option<clap::Command> {
+    let name = name.to_lowercase();
+    CommandLine::command()
+        .get_subcommands()
+        .find(|c| c.get_name() == name || c.get_all_aliases().any(|a| a == name))
+        .cloned()
+}
+
+/// Returns true if `name` is a command name or alias.
+#[must_use]
+pub fn is_command(name: &str) -> bool {
+    find_command(name).is_some()
+}
+
+/// Returns true if the command's first positional argument is a contact.
+#[must_use]
+pub fn takes_contact(name: &str) -> bool {
+    find_command(name).is_some_and(|cmd| {
+        cmd.get_positionals()
+            .next()
+            .and_then(|arg| arg.get_value_names())
+            .is_some_and(|names| names.iter().any(|n| n.as_str() == "CONTACT"))
+    })
+}
+
+/// Returns all command names and aliases (for tab completion).
+#[must_use]
+pub fn command_names() -> Vec<String> {
+    CommandLine::command()
+        .get_subcommands()
+        .flat_map(|c| {
+            std::iter::once(c.get_name().to_string())
+                .chain(c.get_all_aliases().map(String::from))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Renders a one-line-per-command overview of all commands.
+#[must_use]
+pub fn commands_help() -> String {
+    let mut help = String::new();
+    for cmd in CommandLine::command().get_subcommands() {
+        let aliases: Vec<&str> = cmd.get_visible_aliases().collect();
+        let name = if aliases.is_empty() {
+            cmd.get_name().to_string()
+        } else {
+            format!("{} ({})", cmd.get_name(), aliases.join(", "))
+        };
+        let about = cmd.get_about().map(ToString::to_string).unwrap_or_default();
+        let _ = writeln!(help, "  {name:<32} - {about}");
+    }
+    help
+}
+
 /// Statistics type argument.
-#[derive(Debug, Clone, Copy, ValueEnum)]
+#[derive(Debug, Clone, Copy, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum StatsTypeArg {
     /// Core statistics (battery, uptime, errors).
     Core,
@@ -512,3 +843,110 @@ pub enum StatsTypeArg {
     /// Packet statistics (sent, received, flood/direct).
     Packets,
 }
+
+/// Output format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text.
+    Human,
+    /// Pretty-printed JSON.
+    Json,
+    /// One compact JSON object per line, per record or event.
+    Ndjson,
+    /// CSV for contacts, channels, neighbours and stats.
+    Csv,
+    /// Aligned table for contacts, channels, neighbours and stats.
+    Table,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Command {
+        parse_args(split_line(line).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+        CommandLine::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_aliases() {
+        assert!(matches!(parse("i"), Command::Infos));
+        assert!(matches!(parse("INFOS"), Command::Infos));
+        assert!(matches!(parse("]"), Command::Wmt8));
+        assert!(matches!(parse("wa 5"), Command::WaitAck { timeout: 5 }));
+    }
+
+    #[test]
+    fn test_parse_quoted_arguments() {
+        match parse(r#"msg "Bob Smith" "hello there" again"#) {
+            Command::Msg { name, message, .. } => {
+                assert_eq!(name, "Bob Smith");
+                assert_eq!(message, vec!["hello there", "again"]);
+            }
+            other => panic!("unexpected command: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_msg_retry() {
+        match parse("msg --retry 3 --flood-after 1 Bob hi") {
+            Command::Msg {
+                retry, flood_after, ..
+            } => {
+                assert_eq!(retry, Some(3));
+                assert_eq!(flood_after, 1);
+            }
+            other => panic!("unexpected command: {other:?}"),
+        }
+        assert!(matches!(
+            parse("msg Bob hi"),
+            Command::Msg {
+                retry: None,
+                flood_after: 2,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_parse_trailing_values() {
+        match parse("set coords 47.5 8.5") {
+            Command::Set { param, value } => {
+                assert_eq!(param, "coords");
+                assert_eq!(value.join(" "), "47.5 8.5");
+            }
+            other => panic!("unexpected command: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse_args(split_line("msg").unwrap()).is_err());
+        assert!(parse_args(split_line("no_such_command").unwrap()).is_err());
+        assert!(split_line("msg Bob \"unterminated").is_err());
+    }
+
+    #[test]
+    fn test_takes_contact() {
+        assert!(takes_contact("msg"));
+        assert!(takes_contact("login"));
+        assert!(takes_contact("rs"));
+        assert!(!takes_contact("infos"));
+        assert!(!takes_contact("add_channel"));
+    }
+
+    #[test]
+    fn test_command_names() {
+        let names = command_names();
+        assert!(names.iter().any(|n| n == "contacts"));
+        assert!(names.iter().any(|n| n == "lc"));
+        assert!(names.iter().any(|n| n == "{"));
+        assert!(is_command("REQ_STATUS"));
+        assert!(!is_command("hello"));
+    }
+}
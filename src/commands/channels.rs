@@ -1,9 +1,12 @@
 //! Channel-related commands.
 
+use std::collections::HashMap;
+
 use meshcore::event::Event;
+use meshcore::transport::Transport;
 use sha2::{Digest, Sha256};
 
-use super::CommandContext;
+use super::{CHANNEL_SLOTS, CommandContext, parse_channel_index};
 use crate::error::{CliError, Result};
 
 /// Checks if a channel name indicates an empty/unused channel.
@@ -39,17 +42,54 @@ fn parse_channel_secret(name: &str, key: Option<&str>) -> Result<[u8; 16]> {
     }
 }
 
-impl CommandContext {
+/// Returns a channel name without the NUL padding used for unused bytes.
+fn channel_name(name: &str) -> &str {
+    name.trim_end_matches('\0')
+}
+
+impl<T: Transport> CommandContext<T> {
+    /// Resolves a channel number or name to its slot.
+    ///
+    /// Names are looked up in the session's channel cache, which is re-read from the
+    /// device when a name isn't found.
+    pub async fn get_channel_index(&self, channel: &str) -> Result<u8> {
+        let cached = parse_channel_index(channel, &self.state.lock().await.channels);
+        match cached {
+            Err(CliError::ChannelNotFound(_)) => {
+                self.refresh_channels().await?;
+                parse_channel_index(channel, &self.state.lock().await.channels)
+            }
+            result => result,
+        }
+    }
+
+    /// Reads all channel slots from the device into the session's channel cache.
+    pub async fn refresh_channels(&self) -> Result<()> {
+        let mut channels = HashMap::new();
+        for i in 0..CHANNEL_SLOTS {
+            let event = self.commands().await.get_channel(i).await?;
+            if let Event::ChannelInfo(channel) = event
+                && !is_channel_empty(&channel.name)
+            {
+                channels.insert(i, channel_name(&channel.name).to_string());
+            }
+        }
+        self.state.lock().await.channels = channels;
+        Ok(())
+    }
+
     /// Executes the `get_channels` command.
     pub async fn cmd_get_channels(&self) -> Result<()> {
-        // Get channels 0-7
-        for i in 0..8 {
+        let mut channels = HashMap::new();
+        let mut infos = Vec::new();
+        for i in 0..CHANNEL_SLOTS {
             let event = self.commands().await.get_channel(i).await?;
 
             match event {
                 Event::ChannelInfo(channel) => {
                     if !is_channel_empty(&channel.name) {
-                        self.display.print_channel(&channel);
+                        channels.insert(i, channel_name(&channel.name).to_string());
+                        infos.push(channel);
                     }
                 }
                 Event::Error { message } => {
@@ -62,13 +102,15 @@ impl CommandContext {
                 _ => {}
             }
         }
+        self.display.print_channels(&infos);
+        self.state.lock().await.channels = channels;
 
         Ok(())
     }
 
     /// Executes the `get_channel` command.
     pub async fn cmd_get_channel(&self, channel: &str) -> Result<()> {
-        let index = Self::get_channel_index(channel)?;
+        let index = self.get_channel_index(channel).await?;
         let event = self.commands().await.get_channel(index).await?;
 
         match event {
@@ -93,6 +135,11 @@ impl CommandContext {
             .await
             .set_channel(number, name, &secret)
             .await?;
+        self.state
+            .lock()
+            .await
+            .channels
+            .insert(number, name.to_string());
         self.display
             .print_ok(&format!("channel {number} set to '{name}'"));
         Ok(())
@@ -100,13 +147,14 @@ impl CommandContext {
 
     /// Executes the `remove_channel` command.
     pub async fn cmd_remove_channel(&self, channel: &str) -> Result<()> {
-        let index = Self::get_channel_index(channel)?;
+        let index = self.get_channel_index(channel).await?;
 
         // Remove channel by setting empty name and zero secret
         self.commands()
             .await
             .set_channel(index, "", &[0u8; 16])
             .await?;
+        self.state.lock().await.channels.remove(&index);
         self.display.print_ok(&format!("channel {index} removed"));
         Ok(())
     }
@@ -116,7 +164,7 @@ impl CommandContext {
     pub async fn cmd_add_channel(&self, name: &str, key: Option<&str>) -> Result<()> {
         // Find the first empty channel slot
         let mut free_slot: Option<u8> = None;
-        for i in 0..8 {
+        for i in 0..CHANNEL_SLOTS {
             let event = self.commands().await.get_channel(i).await?;
             if let Event::ChannelInfo(channel) = event {
                 if is_channel_empty(&channel.name) {
@@ -134,6 +182,11 @@ impl CommandContext {
             .await
             .set_channel(slot, name, &secret)
             .await?;
+        self.state
+            .lock()
+            .await
+            .channels
+            .insert(slot, name.to_string());
         self.display
             .print_ok(&format!("channel added at slot {slot}: '{name}'"));
         Ok(())
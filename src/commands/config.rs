@@ -0,0 +1,223 @@
+//! Config file commands (`config show`, `config set`, `config unset`).
+
+use meshcore::transport::Transport;
+
+use super::CommandContext;
+use crate::cli::ConfigAction;
+use crate::config::{Config, HOOK_NAMES};
+use crate::display::Display;
+use crate::error::{CliError, Result};
+use crate::output::outln;
+
+/// Parses an on/off style boolean value.
+fn parse_switch(value: &str) -> Result<bool> {
+    match value.to_lowercase().as_str() {
+        "on" | "true" | "yes" | "1" => Ok(true),
+        "off" | "false" | "no" | "0" => Ok(false),
+        _ => Err(CliError::InvalidArgument(format!(
+            "Invalid value: {value}. Use on/off"
+        ))),
+    }
+}
+
+/// Returns the single value of a `config set` call.
+fn single_value<'a>(key: &str, value: &'a [String]) -> Result<&'a str> {
+    match value {
+        [v] => Ok(v),
+        _ => Err(CliError::InvalidArgument(format!(
+            "Usage: config set {key} <value>"
+        ))),
+    }
+}
+
+/// Executes a config action against the config file.
+///
+/// This doesn't need a device connection, so it can run before connecting.
+pub fn execute_config(display: &Display, action: &ConfigAction) -> Result<()> {
+    match action {
+        ConfigAction::Show => {
+            let config = Config::load()?;
+            print_config(display, &config);
+            Ok(())
+        }
+        ConfigAction::Set { key, value } => {
+            let mut config = Config::load()?;
+            match key.to_lowercase().as_str() {
+                "default_port" | "port" => {
+                    config.default_port = Some(single_value(key, value)?.to_string());
+                }
+                "default_baudrate" | "baudrate" | "baud" => {
+                    let baud = single_value(key, value)?
+                        .parse()
+                        .map_err(|_| CliError::InvalidArgument("Invalid baud rate".into()))?;
+                    config.default_baudrate = Some(baud);
+                }
+                "color" => config.color = parse_switch(single_value(key, value)?)?,
+                "message_history" => {
+                    config.message_history = parse_switch(single_value(key, value)?)?;
+                }
+                "contact_timeout" => {
+                    let [contact, timeout] = value.as_slice() else {
+                        return Err(CliError::InvalidArgument(
+                            "Usage: config set contact_timeout <contact> <secs>".into(),
+                        ));
+                    };
+                    let timeout = timeout
+                        .parse()
+                        .map_err(|_| CliError::InvalidArgument("Invalid timeout".into()))?;
+                    config.contact_timeouts.insert(contact.clone(), timeout);
+                }
+                "hook_timeout" => {
+                    config.hooks.timeout_secs = single_value(key, value)?
+                        .parse()
+                        .map_err(|_| CliError::InvalidArgument("Invalid timeout".into()))?;
+                }
+                "hook_concurrency" => {
+                    config.hooks.max_concurrent = single_value(key, value)?
+                        .parse()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .ok_or_else(|| CliError::InvalidArgument("Invalid concurrency".into()))?;
+                }
+                name if HOOK_NAMES.contains(&name) => {
+                    if value.is_empty() {
+                        return Err(CliError::InvalidArgument(format!(
+                            "Usage: config set {name} <command...>"
+                        )));
+                    }
+                    if let Some(slot) = config.hooks.slot_mut(name) {
+                        *slot = Some(value.join(" "));
+                    }
+                }
+                _ => {
+                    return Err(CliError::InvalidArgument(format!(
+                        "Unknown config key: {key}. Use 'config show' for list."
+                    )));
+                }
+            }
+            config.save()?;
+            display.print_ok(&format!("{key} saved"));
+            Ok(())
+        }
+        ConfigAction::Unset { key, contact } => {
+            let mut config = Config::load()?;
+            match key.to_lowercase().as_str() {
+                "default_port" | "port" => config.default_port = None,
+                "default_baudrate" | "baudrate" | "baud" => config.default_baudrate = None,
+                "contact_timeout" => {
+                    let contact = contact.as_deref().ok_or_else(|| {
+                        CliError::InvalidArgument(
+                            "Usage: config unset contact_timeout <contact>".into(),
+                        )
+                    })?;
+                    config.contact_timeouts.remove(contact);
+                }
+                name if HOOK_NAMES.contains(&name) => {
+                    if let Some(slot) = config.hooks.slot_mut(name) {
+                        *slot = None;
+                    }
+                }
+                _ => {
+                    return Err(CliError::InvalidArgument(format!(
+                        "Cannot unset {key}. Use 'config set {key} <value>' instead."
+                    )));
+                }
+            }
+            config.save()?;
+            display.print_ok(&format!("{key} removed"));
+            Ok(())
+        }
+    }
+}
+
+/// Prints the configuration.
+fn print_config(display: &Display, config: &Config) {
+    if display.is_json() {
+        display.print_json(config);
+        return;
+    }
+
+    if let Some(path) = Config::config_file() {
+        outln!("Config file: {}", path.display());
+    }
+    outln!(
+        "  default_port: {}",
+        config.default_port.as_deref().unwrap_or("-")
+    );
+    outln!(
+        "  default_baudrate: {}",
+        config
+            .default_baudrate
+            .map_or_else(|| "-".to_string(), |b| b.to_string())
+    );
+    outln!("  color: {}", if config.color { "on" } else { "off" });
+    outln!(
+        "  message_history: {}",
+        if config.message_history { "on" } else { "off" }
+    );
+
+    let mut timeouts: Vec<_> = config.contact_timeouts.iter().collect();
+    timeouts.sort_by_key(|(name, _)| name.to_lowercase());
+    for (name, timeout) in timeouts {
+        outln!("  contact_timeout {name}: {timeout}s");
+    }
+
+    for name in HOOK_NAMES {
+        if let Some(command) = config.hooks.get(name) {
+            outln!("  {name}: {command}");
+        }
+    }
+    if !config.hooks.is_empty() {
+        outln!(
+            "  hook_timeout: {}s, hook_concurrency: {}",
+            config.hooks.timeout_secs,
+            config.hooks.max_concurrent
+        );
+    }
+}
+
+impl<T: Transport> CommandContext<T> {
+    /// Executes a `config` command and applies persisted contact timeouts to the session.
+    pub async fn cmd_config(&self, action: &ConfigAction) -> Result<()> {
+        execute_config(&self.display, action)?;
+
+        match action {
+            ConfigAction::Set { key, .. } if key.eq_ignore_ascii_case("contact_timeout") => {
+                let config = Config::load()?;
+                let mut state = self.state.lock().await;
+                state.contact_timeouts.extend(config.contact_timeouts);
+            }
+            ConfigAction::Unset {
+                key,
+                contact: Some(contact),
+            } if key.eq_ignore_ascii_case("contact_timeout") => {
+                let mut state = self.state.lock().await;
+                state.contact_timeouts.remove(contact);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_switch() {
+        assert!(parse_switch("on").unwrap());
+        assert!(parse_switch("YES").unwrap());
+        assert!(!parse_switch("off").unwrap());
+        assert!(!parse_switch("0").unwrap());
+        assert!(parse_switch("maybe").is_err());
+    }
+
+    #[test]
+    fn test_single_value() {
+        let value = vec!["57600".to_string()];
+        assert_eq!(single_value("baud", &value).unwrap(), "57600");
+        assert!(single_value("baud", &[]).is_err());
+    }
+}
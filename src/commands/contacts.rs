@@ -1,12 +1,14 @@
 //! Contact-related commands.
 
 use meshcore::event::Event;
+use meshcore::transport::Transport;
 use meshcore::types::ContactType;
 
 use super::CommandContext;
 use crate::error::{CliError, Result};
+use crate::output::outln;
 
-impl CommandContext {
+impl<T: Transport> CommandContext<T> {
     /// Executes the `contacts` / `list` command.
     pub async fn cmd_contacts(&self) -> Result<()> {
         // First refresh contacts from device
@@ -45,17 +47,17 @@ impl CommandContext {
                 ContactType::Unknown => "Unknown",
             };
 
-            println!("  Type: {type_str}");
-            println!("  Flags: 0x{:02x}", contact.flags.as_byte());
+            outln!("  Type: {type_str}");
+            outln!("  Flags: 0x{:02x}", contact.flags.as_byte());
 
             match contact.out_path_len.cmp(&0) {
-                std::cmp::Ordering::Less => println!("  Path: flood"),
-                std::cmp::Ordering::Equal => println!("  Path: direct"),
+                std::cmp::Ordering::Less => outln!("  Path: flood"),
+                std::cmp::Ordering::Equal => outln!("  Path: direct"),
                 std::cmp::Ordering::Greater => {
                     let path_len = usize::try_from(contact.out_path_len).unwrap_or(0);
                     let byte_len = (path_len * 6).min(contact.out_path.len());
                     let path_hex = hex::encode(&contact.out_path[..byte_len]);
-                    println!("  Path: {} hops ({})", contact.out_path_len, path_hex);
+                    outln!("  Path: {} hops ({})", contact.out_path_len, path_hex);
                 }
             }
 
@@ -65,7 +67,7 @@ impl CommandContext {
                     .timestamp_opt(i64::from(contact.last_advert), 0)
                     .single()
                 {
-                    println!("  Last advert: {}", dt.format("%Y-%m-%d %H:%M:%S"));
+                    outln!("  Last advert: {}", dt.format("%Y-%m-%d %H:%M:%S"));
                 }
             }
 
@@ -75,7 +77,7 @@ impl CommandContext {
                     .timestamp_opt(i64::from(contact.last_modified), 0)
                     .single()
                 {
-                    println!("  Last modified: {}", dt.format("%Y-%m-%d %H:%M:%S"));
+                    outln!("  Last modified: {}", dt.format("%Y-%m-%d %H:%M:%S"));
                 }
             }
         }
@@ -83,6 +85,79 @@ impl CommandContext {
         Ok(())
     }
 
+    /// Resolves an optional contact argument, falling back to the current contact.
+    async fn contact_or_current(&self, name: Option<&str>) -> Result<meshcore::types::Contact> {
+        let name = match name {
+            Some(n) => n.to_string(),
+            None => self
+                .state
+                .lock()
+                .await
+                .current_contact
+                .clone()
+                .ok_or_else(|| {
+                    CliError::InvalidArgument(
+                        "No contact selected. Use 'to <contact>' first.".into(),
+                    )
+                })?,
+        };
+        self.get_contact(&name).await
+    }
+
+    /// Executes the `contact_name` command.
+    pub async fn cmd_contact_name(&self, name: Option<&str>) -> Result<()> {
+        let contact = self.contact_or_current(name).await?;
+        outln!("{}", contact.name);
+        Ok(())
+    }
+
+    /// Executes the `contact_key` command.
+    pub async fn cmd_contact_key(&self, name: Option<&str>) -> Result<()> {
+        let contact = self.contact_or_current(name).await?;
+        outln!("{}", contact.public_key.to_hex());
+        Ok(())
+    }
+
+    /// Executes the `contact_type` command.
+    pub async fn cmd_contact_type(&self, name: Option<&str>) -> Result<()> {
+        let contact = self.contact_or_current(name).await?;
+        let type_str = match contact.device_type {
+            ContactType::Node => "node",
+            ContactType::Repeater => "repeater",
+            ContactType::Room => "room",
+            ContactType::Unknown => "unknown",
+        };
+        outln!("{type_str}");
+        Ok(())
+    }
+
+    /// Executes the `contact_lastmod` command.
+    pub async fn cmd_contact_lastmod(&self, name: Option<&str>) -> Result<()> {
+        use chrono::{TimeZone, Utc};
+
+        let contact = self.contact_or_current(name).await?;
+        if contact.last_modified > 0 {
+            if let Some(dt) = Utc
+                .timestamp_opt(i64::from(contact.last_modified), 0)
+                .single()
+            {
+                outln!("{}", dt.format("%Y-%m-%d %H:%M:%S"));
+            } else {
+                outln!("{}", contact.last_modified);
+            }
+        } else {
+            outln!("never");
+        }
+        Ok(())
+    }
+
+    /// Executes the `dtrace` command (discover path, then show it).
+    pub async fn cmd_dtrace(&self, name: Option<&str>) -> Result<()> {
+        let contact = self.contact_or_current(name).await?;
+        self.cmd_disc_path(&contact.name).await?;
+        self.cmd_path(&contact.name).await
+    }
+
     /// Executes the `contact_timeout` command.
     pub async fn cmd_contact_timeout(&self, name: &str, timeout: u64) -> Result<()> {
         let contact = self.get_contact(name).await?;
@@ -111,8 +186,8 @@ impl CommandContext {
             }));
         } else {
             match contact.out_path_len.cmp(&0) {
-                std::cmp::Ordering::Less => println!("{}: flood", contact.name),
-                std::cmp::Ordering::Equal => println!("{}: direct", contact.name),
+                std::cmp::Ordering::Less => outln!("{}: flood", contact.name),
+                std::cmp::Ordering::Equal => outln!("{}: direct", contact.name),
                 std::cmp::Ordering::Greater => {
                     let path_len = usize::try_from(contact.out_path_len).unwrap_or(0);
                     // Path is stored as 6-byte prefixes
@@ -124,7 +199,7 @@ impl CommandContext {
                             path_parts.push(hex::encode(&contact.out_path[start..end]));
                         }
                     }
-                    println!(
+                    outln!(
                         "{}: {} hops [{}]",
                         contact.name,
                         contact.out_path_len,
@@ -317,7 +392,7 @@ impl CommandContext {
                 if self.display.is_json() {
                     self.display.print_json(&serde_json::json!({ "uri": uri }));
                 } else {
-                    println!("{uri}");
+                    outln!("{uri}");
                 }
             }
             Event::Error { message } => {
@@ -383,13 +458,13 @@ impl CommandContext {
                 .collect();
             self.display.print_json(&pending);
         } else if state.pending_contacts.is_empty() {
-            println!("No pending contacts");
+            outln!("No pending contacts");
         } else {
             for pending in state.pending_contacts.values() {
                 if let Some(name) = &pending.name {
-                    println!("{} ({})", name, pending.public_key);
+                    outln!("{} ({})", name, pending.public_key);
                 } else {
-                    println!("{}", pending.public_key);
+                    outln!("{}", pending.public_key);
                 }
             }
         }
@@ -4,12 +4,15 @@ use std::time::Duration;
 
 use meshcore::event::Event;
 use meshcore::protocol::StatsType;
+use meshcore::transport::Transport;
 
-use super::{CommandContext, current_timestamp};
-use crate::cli::StatsTypeArg;
+use super::{CommandContext, current_timestamp, scope_key};
+use crate::cli::{self, StatsTypeArg};
 use crate::error::{CliError, Result};
+use crate::output::outln;
+use crate::telemetry;
 
-impl CommandContext {
+impl<T: Transport> CommandContext<T> {
     /// Executes the `infos` command.
     pub async fn cmd_infos(&self) -> Result<()> {
         let event = self.commands().await.app_start().await?;
@@ -126,7 +129,7 @@ impl CommandContext {
     /// Executes the `wait_key` command - waits for user to press Enter.
     pub fn cmd_wait_key() {
         use std::io::{self, BufRead};
-        println!("Press Enter to continue...");
+        outln!("Press Enter to continue...");
         let stdin = io::stdin();
         let _ = stdin.lock().lines().next();
     }
@@ -176,7 +179,7 @@ impl CommandContext {
                 if self.display.is_json() {
                     self.display.print_json(&serde_json::json!({ "uri": uri }));
                 } else {
-                    println!("{uri}");
+                    outln!("{uri}");
                 }
             }
             Event::Error { message } => {
@@ -194,20 +197,20 @@ impl CommandContext {
     pub async fn cmd_get(&self, param: &str) -> Result<()> {
         match param.to_lowercase().as_str() {
             "help" => {
-                println!("Available parameters:");
-                println!("  time          - Current device time");
-                println!("  battery / bat - Battery status");
-                println!("  name          - Device name");
-                println!("  txpower / tx  - TX power");
-                println!("  radio         - Radio parameters");
-                println!("  coords        - Device coordinates");
-                println!("  telemetry     - Telemetry mode");
-                println!("  channels      - Channel list");
-                println!("  stats         - Device statistics");
-                println!("  stats_radio   - Radio statistics");
-                println!("  stats_packets - Packet statistics");
-                println!("  fstats        - Filesystem statistics");
-                println!("  vars / custom - Custom variables");
+                outln!("Available parameters:");
+                outln!("  time          - Current device time");
+                outln!("  battery / bat - Battery status");
+                outln!("  name          - Device name");
+                outln!("  txpower / tx  - TX power");
+                outln!("  radio         - Radio parameters");
+                outln!("  coords        - Device coordinates");
+                outln!("  telemetry     - Telemetry mode");
+                outln!("  channels      - Channel list");
+                outln!("  stats         - Device statistics");
+                outln!("  stats_radio   - Radio statistics");
+                outln!("  stats_packets - Packet statistics");
+                outln!("  fstats        - Filesystem statistics");
+                outln!("  vars / custom - Custom variables");
                 Ok(())
             }
             "time" | "clock" => self.cmd_clock(false).await,
@@ -231,27 +234,28 @@ impl CommandContext {
     pub async fn cmd_set(&self, param: &str, value: &str) -> Result<()> {
         match param.to_lowercase().as_str() {
             "help" => {
-                println!("Available parameters:");
-                println!("  name <value>              - Device name");
-                println!("  time <epoch>              - Device time");
-                println!("  txpower / tx <dBm>        - TX power");
-                println!("  coords <lat> <lon>        - Device coordinates");
-                println!("  lat <latitude>            - Latitude only");
-                println!("  lon <longitude>           - Longitude only");
-                println!("  pin <pin>                 - BLE PIN");
-                println!("  radio <f>,<bw>,<sf>,<cr>  - Radio parameters");
-                println!("  tuning <af>,<tx_delay>    - Tuning parameters");
-                println!("  manual_add_contacts on/off - Manual contact approval");
-                println!("  multi_acks on/off         - Multi-ACK mode");
-                println!("  telemetry_mode_base <m>   - Base telemetry (never/device/always)");
-                println!("  telemetry_mode_loc <m>    - Location telemetry");
-                println!("  telemetry_mode_env <m>    - Environment telemetry");
-                println!("  advert_loc_policy <p>     - Advert location (none/share)");
-                println!("  var <key> <value>         - Custom variable");
+                outln!("Available parameters:");
+                outln!("  name <value>              - Device name");
+                outln!("  time <epoch>              - Device time");
+                outln!("  txpower / tx <dBm>        - TX power");
+                outln!("  coords <lat> <lon>        - Device coordinates");
+                outln!("  lat <latitude>            - Latitude only");
+                outln!("  lon <longitude>           - Longitude only");
+                outln!("  pin <pin>                 - BLE PIN");
+                outln!("  radio <f>,<bw>,<sf>,<cr>  - Radio parameters");
+                outln!("  tuning <af>,<tx_delay>    - Tuning parameters");
+                outln!("  manual_add_contacts on/off - Manual contact approval");
+                outln!("  multi_acks on/off         - Multi-ACK mode");
+                outln!("  telemetry_mode_base <m>   - Base telemetry (never/device/always)");
+                outln!("  telemetry_mode_loc <m>    - Location telemetry");
+                outln!("  telemetry_mode_env <m>    - Environment telemetry");
+                outln!("  advert_loc_policy <p>     - Advert location (none/share)");
+                outln!("  var <key> <value>         - Custom variable");
                 Ok(())
             }
             "name" => {
                 self.commands().await.set_name(value).await?;
+                self.state.lock().await.device_name = Some(value.to_string());
                 self.display.print_ok("name set");
                 Ok(())
             }
@@ -499,10 +503,10 @@ impl CommandContext {
                     }
                     self.display.print_json(&serde_json::Value::Object(map));
                 } else if vars.is_empty() {
-                    println!("No custom variables set");
+                    outln!("No custom variables set");
                 } else {
                     for pair in vars.split(',') {
-                        println!("{pair}");
+                        outln!("{pair}");
                     }
                 }
             }
@@ -535,7 +539,7 @@ impl CommandContext {
                     self.display
                         .print_json(&serde_json::json!({ "private_key": hex }));
                 } else {
-                    println!("{hex}");
+                    outln!("{hex}");
                 }
             }
             Event::Disabled => {
@@ -573,25 +577,26 @@ impl CommandContext {
 
     /// Executes the `scope` command.
     pub async fn cmd_scope(&self, scope: &str) -> Result<()> {
+        self.set_scope(scope).await?;
+        self.display.print_ok(&format!("scope set to {scope}"));
+        Ok(())
+    }
+
+    /// Sets the flood scope (`*` clears it) without printing anything.
+    pub async fn set_scope(&self, scope: &str) -> Result<()> {
         if scope == "*" {
             self.commands().await.clear_flood_scope().await?;
             let mut state = self.state.lock().await;
             state.flood_scope = None;
         } else {
-            // Hash the scope topic
-            use sha2::{Digest, Sha256};
-            let mut hasher = Sha256::new();
-            hasher.update(scope.as_bytes());
-            let hash = hasher.finalize();
-            let mut key = [0u8; 16];
-            key.copy_from_slice(&hash[..16]);
-
-            self.commands().await.set_flood_scope(&key).await?;
+            self.commands()
+                .await
+                .set_flood_scope(&scope_key(scope))
+                .await?;
             let mut state = self.state.lock().await;
             state.flood_scope = Some(scope.to_string());
         }
 
-        self.display.print_ok(&format!("scope set to {scope}"));
         Ok(())
     }
 
@@ -611,27 +616,8 @@ impl CommandContext {
 
         match event {
             Event::TelemetryResponse(telemetry) => {
-                if self.display.is_json() {
-                    let readings: Vec<_> = telemetry
-                        .readings
-                        .iter()
-                        .map(|r| {
-                            serde_json::json!({
-                                "channel": r.channel,
-                                "type": r.lpp_type,
-                                "value": format!("{:?}", r.value),
-                            })
-                        })
-                        .collect();
-                    self.display.print_json(&serde_json::json!({
-                        "readings": readings,
-                    }));
-                } else {
-                    println!("Local telemetry:");
-                    for reading in &telemetry.readings {
-                        println!("  Channel {}: {:?}", reading.channel, reading.value);
-                    }
-                }
+                self.display
+                    .print_telemetry("Local telemetry", &telemetry::from_telemetry(&telemetry));
             }
             Event::Error { message } => {
                 return Err(CliError::Command(message));
@@ -644,177 +630,6 @@ impl CommandContext {
         Ok(())
     }
 
-    /// Executes the `script` command (interactive mode version).
-    /// Runs a script file containing commands.
-    pub async fn cmd_script(&self, filename: &str) -> Result<()> {
-        let content = std::fs::read_to_string(filename).map_err(|e| CliError::Script {
-            line: 0,
-            message: format!("Failed to read script: {e}"),
-        })?;
-
-        for (line_num, line) in content.lines().enumerate() {
-            let line = line.trim();
-            if line.is_empty() || line.starts_with('#') {
-                continue;
-            }
-
-            // Parse as interactive command
-            let parts: Vec<&str> = line.splitn(2, char::is_whitespace).collect();
-            let cmd = parts[0].to_lowercase();
-            let args = parts.get(1).unwrap_or(&"");
-
-            // Execute using the interactive mode command handler
-            if let Err(e) = self.execute_interactive_cmd(&cmd, args).await {
-                return Err(CliError::Script {
-                    line: line_num + 1,
-                    message: e.to_string(),
-                });
-            }
-        }
-
-        Ok(())
-    }
-
-    /// Executes a command in interactive mode style.
-    async fn execute_interactive_cmd(&self, cmd: &str, args: &str) -> Result<()> {
-        let args_vec: Vec<String> = if args.is_empty() {
-            Vec::new()
-        } else {
-            args.split_whitespace().map(String::from).collect()
-        };
-
-        match cmd {
-            "infos" | "i" => self.cmd_infos().await,
-            "ver" | "v" => self.cmd_ver().await,
-            "battery" => self.cmd_battery().await,
-            "clock" => self.cmd_clock(false).await,
-            "sync_time" | "st" => self.cmd_sync_time().await,
-            "reboot" => self.cmd_reboot().await,
-            "advert" | "a" => self.cmd_advert(false).await,
-            "floodadv" => self.cmd_advert(true).await,
-            "card" | "e" => self.cmd_card().await,
-            "self_telemetry" | "t" => self.cmd_self_telemetry().await,
-            "contacts" | "list" | "lc" => self.cmd_contacts().await,
-            "reload_contacts" | "rc" => self.cmd_reload_contacts().await,
-            "contact_info" | "ci" if !args.is_empty() => self.cmd_contact_info(args.trim()).await,
-            "path" if !args.is_empty() => self.cmd_path(args.trim()).await,
-            "disc_path" | "dp" if !args.is_empty() => self.cmd_disc_path(args.trim()).await,
-            "reset_path" | "rp" if !args.is_empty() => self.cmd_reset_path(args.trim()).await,
-            "pending_contacts" => self.cmd_pending_contacts().await,
-            "flush_pending" => self.cmd_flush_pending().await,
-            "add_pending" if !args.is_empty() => self.cmd_add_pending(args.trim()).await,
-            "change_path" | "cp" if args_vec.len() >= 2 => {
-                self.cmd_change_path(&args_vec[0], &args_vec[1]).await
-            }
-            "change_flags" | "cf" if args_vec.len() >= 2 => {
-                self.cmd_change_flags(&args_vec[0], &args_vec[1]).await
-            }
-            "share_contact" | "sc" if !args.is_empty() => self.cmd_share_contact(args.trim()).await,
-            "export_contact" | "ec" => {
-                let contact = if args.is_empty() {
-                    None
-                } else {
-                    Some(args.trim())
-                };
-                self.cmd_export_contact(contact).await
-            }
-            "import_contact" | "ic" if !args.is_empty() => {
-                self.cmd_import_contact(args.trim()).await
-            }
-            "remove_contact" if !args.is_empty() => self.cmd_remove_contact(args.trim()).await,
-            "msg" | "m" | "{" if args_vec.len() >= 2 => {
-                self.cmd_msg(&args_vec[0], &args_vec[1..], false, 30).await
-            }
-            "recv" | "r" => self.cmd_recv().await,
-            "sync_msgs" | "sm" => self.cmd_sync_msgs().await,
-            "wait_ack" | "wa" | "}" => {
-                let timeout = args_vec.first().and_then(|s| s.parse().ok()).unwrap_or(30);
-                self.cmd_wait_ack(timeout).await
-            }
-            "wait_msg" | "wm" => {
-                let timeout = args_vec.first().and_then(|s| s.parse().ok()).unwrap_or(30);
-                self.cmd_wait_msg(timeout).await
-            }
-            "trywait_msg" | "wmt" if !args.is_empty() => {
-                let timeout: u64 = args.trim().parse().unwrap_or(8);
-                self.cmd_trywait_msg(timeout).await
-            }
-            "chan" | "ch" if args_vec.len() >= 2 => {
-                let channel: u8 = args_vec[0].parse().unwrap_or(0);
-                self.cmd_chan(channel, &args_vec[1..]).await
-            }
-            "public" | "dch" if !args.is_empty() => self.cmd_public(&[args.to_string()]).await,
-            "login" | "l" if args_vec.len() >= 2 => {
-                self.cmd_login(&args_vec[0], &args_vec[1]).await
-            }
-            "logout" if !args.is_empty() => self.cmd_logout(args.trim()).await,
-            "cmd" | "c" | "[" if args_vec.len() >= 2 => {
-                self.cmd_cmd(&args_vec[0], &args_vec[1..], false, 30).await
-            }
-            "req_status" | "rs" if !args.is_empty() => self.cmd_req_status(args.trim()).await,
-            "wmt8" | "]" => self.cmd_wmt8().await,
-            "trace" | "tr" if !args.is_empty() => self.cmd_trace(args.trim()).await,
-            "req_binary" | "rb" if args_vec.len() >= 2 => {
-                self.cmd_req_binary(&args_vec[0], &args_vec[1]).await
-            }
-            "req_neighbours" | "rn" if !args.is_empty() => {
-                self.cmd_req_neighbours(args.trim()).await
-            }
-            "req_telemetry" | "rt" if !args.is_empty() => self.cmd_req_telemetry(args.trim()).await,
-            "req_mma" | "rm" if !args.is_empty() => self.cmd_req_mma(args.trim()).await,
-            "req_acl" if !args.is_empty() => self.cmd_req_acl(args.trim()).await,
-            "get_channels" | "gc" => self.cmd_get_channels().await,
-            "get_channel" if !args.is_empty() => self.cmd_get_channel(args.trim()).await,
-            "set_channel" if args_vec.len() >= 3 => {
-                let num: u8 = args_vec[0].parse().unwrap_or(0);
-                let key = args_vec.get(2).map(String::as_str);
-                self.cmd_set_channel(num, &args_vec[1], key).await
-            }
-            "add_channel" if !args.is_empty() => {
-                let key = args_vec.get(1).map(String::as_str);
-                self.cmd_add_channel(&args_vec[0], key).await
-            }
-            "remove_channel" if !args.is_empty() => self.cmd_remove_channel(args.trim()).await,
-            "scope" if !args.is_empty() => self.cmd_scope(args.trim()).await,
-            "node_discover" | "nd" => {
-                let filter: u8 = args.trim().parse().unwrap_or(0);
-                self.cmd_node_discover(filter).await
-            }
-            "contact_timeout" if args_vec.len() >= 2 => {
-                let timeout: u64 = args_vec[1].parse().unwrap_or(30);
-                self.cmd_contact_timeout(&args_vec[0], timeout).await
-            }
-            "time" if !args.is_empty() => {
-                let epoch: u32 = args.trim().parse().unwrap_or(0);
-                self.cmd_set_time(epoch).await
-            }
-            "get" if !args.is_empty() => self.cmd_get(args.trim()).await,
-            "set" if args_vec.len() >= 2 => {
-                self.cmd_set(&args_vec[0], &args_vec[1..].join(" ")).await
-            }
-            "stats" => {
-                let st = match args.trim() {
-                    "radio" => crate::cli::StatsTypeArg::Radio,
-                    "packets" => crate::cli::StatsTypeArg::Packets,
-                    _ => crate::cli::StatsTypeArg::Core,
-                };
-                self.cmd_stats(st).await
-            }
-            "sleep" | "s" => {
-                let secs: f64 = args.trim().parse().unwrap_or(1.0);
-                self.cmd_sleep(secs).await
-            }
-            "export_key" => self.cmd_export_key().await,
-            "import_key" if !args.is_empty() => self.cmd_import_key(args.trim()).await,
-            "get_vars" => self.cmd_get_vars().await,
-            "set_var" if args_vec.len() >= 2 => {
-                self.cmd_set_var(&args_vec[0], &args_vec[1..].join(" "))
-                    .await
-            }
-            _ => Err(CliError::Command(format!("Unknown command: {cmd}"))),
-        }
-    }
-
     /// Executes the `apply_to` command.
     pub async fn cmd_apply_to(&self, filter: &str, commands: &[String]) -> Result<()> {
         use meshcore::types::ContactType;
@@ -860,10 +675,10 @@ impl CommandContext {
                 min_hops = parsed;
                 max_hops = parsed;
             } else if let Some(val) = filter_part.strip_prefix("u<") {
-                let time_offset = super::parse_time_value(val);
+                let time_offset = super::parse_time_value(val)?;
                 upd_before = Some(now.saturating_sub(time_offset));
             } else if let Some(val) = filter_part.strip_prefix("u>") {
-                let time_offset = super::parse_time_value(val);
+                let time_offset = super::parse_time_value(val)?;
                 upd_after = Some(now.saturating_sub(time_offset));
             }
         }
@@ -910,46 +725,59 @@ impl CommandContext {
         let count = matching.len();
 
         for contact in matching {
-            println!("Applying to {}...", contact.name);
+            outln!("Applying to {}...", contact.name);
 
-            if cmd_line == "remove_contact" {
-                if let Err(e) = self.cmd_remove_contact(&contact.name).await {
-                    self.display
-                        .print_error(&format!("{}: {}", contact.name, e));
-                }
-            } else if cmd_line.starts_with("send ") || cmd_line.starts_with('"') {
-                let msg = if let Some(stripped) = cmd_line.strip_prefix("send ") {
-                    stripped
-                } else {
-                    cmd_line.trim_start_matches('"').trim_end_matches('"')
-                };
-                let message = vec![msg.to_string()];
-                if let Err(e) = self.cmd_msg(&contact.name, &message, false, 30).await {
-                    self.display
-                        .print_error(&format!("{}: {}", contact.name, e));
-                }
-            } else {
-                let ctype = matches!(
-                    contact.device_type,
-                    ContactType::Repeater | ContactType::Room
-                );
-                if ctype {
-                    let cmd_parts: Vec<String> =
-                        cmd_line.split_whitespace().map(String::from).collect();
-                    if let Err(e) = self.cmd_cmd(&contact.name, &cmd_parts, false, 30).await {
-                        self.display
-                            .print_error(&format!("{}: {}", contact.name, e));
-                    }
-                } else {
-                    self.display.print_warning(&format!(
-                        "Can't send '{}' to {} (not a repeater)",
-                        cmd_line, contact.name
-                    ));
-                }
+            if let Err(e) = self.apply_command(&contact, &cmd_line).await {
+                self.display
+                    .print_error(&format!("{}: {}", contact.name, e));
             }
         }
 
-        println!("{count} contacts matched filter");
+        outln!("{count} contacts matched filter");
         Ok(())
     }
+
+    /// Executes an `apply_to` command line in the context of a single contact.
+    ///
+    /// Commands taking a contact get it inserted as their first argument, `send <text>`
+    /// (or a quoted text) sends a message, and anything else is sent to repeaters and
+    /// rooms as a remote command.
+    async fn apply_command(
+        &self,
+        contact: &meshcore::types::Contact,
+        cmd_line: &str,
+    ) -> Result<()> {
+        use meshcore::types::ContactType;
+
+        if cmd_line.starts_with('"') {
+            let msg = cmd_line.trim_start_matches('"').trim_end_matches('"');
+            return self
+                .cmd_msg(&contact.name, &[msg.to_string()], false, 30)
+                .await;
+        }
+
+        let mut args =
+            cli::split_line(cmd_line).map_err(|e| CliError::InvalidArgument(e.to_string()))?;
+        let Some(first) = args.first() else {
+            return Ok(());
+        };
+
+        if first.eq_ignore_ascii_case("send") {
+            self.cmd_msg(&contact.name, &args[1..], false, 30).await
+        } else if cli::takes_contact(first) {
+            args.insert(1, contact.name.clone());
+            self.execute_args(args).await
+        } else if matches!(
+            contact.device_type,
+            ContactType::Repeater | ContactType::Room
+        ) {
+            self.cmd_cmd(&contact.name, &args, false, 30).await
+        } else {
+            self.display.print_warning(&format!(
+                "Can't send '{}' to {} (not a repeater)",
+                cmd_line, contact.name
+            ));
+            Ok(())
+        }
+    }
 }
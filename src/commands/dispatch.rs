@@ -0,0 +1,235 @@
+//! Command dispatch shared by the CLI, scripts, init files, `apply_to` and interactive mode.
+
+use meshcore::transport::Transport;
+
+use super::CommandContext;
+use crate::cli::{self, Command};
+use crate::error::{CliError, Result};
+use crate::output::out;
+
+impl<T: Transport> CommandContext<T> {
+    /// Parses and executes a single command line.
+    pub async fn execute_line(&self, line: &str) -> Result<()> {
+        let args = cli::split_line(line).map_err(|e| CliError::InvalidArgument(e.to_string()))?;
+        if args.is_empty() {
+            return Ok(());
+        }
+        self.execute_args(args).await
+    }
+
+    /// Parses and executes a command from already split arguments.
+    pub async fn execute_args(&self, args: Vec<String>) -> Result<()> {
+        match cli::parse_args(args) {
+            Ok(cmd) => self.execute(cmd).await,
+            Err(e) if !e.use_stderr() => {
+                // Help output requested with `help <cmd>` or `<cmd> --help`
+                out!("{}", e.render());
+                Ok(())
+            }
+            Err(e) => Err(CliError::Usage(
+                e.render().to_string().trim_end().to_string(),
+            )),
+        }
+    }
+
+    /// Executes a single command.
+    pub async fn execute(&self, cmd: Command) -> Result<()> {
+        match cmd {
+            // General commands
+            Command::Chat => Err(CliError::Command(
+                "chat can only be started from the command line".into(),
+            )),
+            Command::Daemon { .. } => Err(CliError::Command(
+                "daemon can only be started from the command line".into(),
+            )),
+            Command::Bot { .. } => Err(CliError::Command(
+                "bot can only be started from the command line".into(),
+            )),
+            Command::Mqtt { .. } => Err(CliError::Command(
+                "mqtt can only be started from the command line".into(),
+            )),
+            Command::Serve { .. } => Err(CliError::Command(
+                "serve can only be started from the command line".into(),
+            )),
+            Command::Web { .. } => Err(CliError::Command(
+                "web can only be started from the command line".into(),
+            )),
+            Command::Ircd { .. } => Err(CliError::Command(
+                "ircd can only be started from the command line".into(),
+            )),
+            Command::ChatTo { contact } => self.select_target(&contact).await,
+            Command::Script { filename } => Box::pin(self.cmd_script(&filename)).await,
+            Command::Infos => self.cmd_infos().await,
+            Command::SelfTelemetry => self.cmd_self_telemetry().await,
+            Command::Card => self.cmd_card().await,
+            Command::Ver => self.cmd_ver().await,
+            Command::Reboot => self.cmd_reboot().await,
+            Command::Sleep { secs } => self.cmd_sleep(secs).await,
+            Command::WaitKey => {
+                Self::cmd_wait_key();
+                Ok(())
+            }
+            Command::ApplyTo { filter, commands } => {
+                Box::pin(self.cmd_apply_to(&filter, &commands)).await
+            }
+
+            // Messaging commands
+            Command::Msg {
+                name,
+                message,
+                timeout,
+                retry: Some(attempts),
+                flood_after,
+                ..
+            } => {
+                self.cmd_msg_retry(&name, &message, attempts, flood_after, timeout)
+                    .await
+            }
+            Command::Msg {
+                name,
+                message,
+                timeout,
+                split: true,
+                ..
+            } => self.cmd_msg_split(&name, &message, timeout).await,
+            Command::Msg {
+                name,
+                message,
+                wait,
+                timeout,
+                retry: None,
+                ..
+            } => self.cmd_msg(&name, &message, wait, timeout).await,
+            Command::Send { message } => self.cmd_send(&message).await,
+            Command::WaitAck { timeout } => self.cmd_wait_ack(timeout).await,
+            Command::Chan {
+                channel,
+                message,
+                split,
+            } => {
+                let index = self.get_channel_index(&channel).await?;
+                if split {
+                    self.cmd_chan_split(index, &message).await
+                } else {
+                    self.cmd_chan(index, &message).await
+                }
+            }
+            Command::Public { message, split } => self.cmd_public(&message, split).await,
+            Command::Recv => self.cmd_recv().await,
+            Command::WaitMsg { timeout } => self.cmd_wait_msg(timeout).await,
+            Command::TrywaitMsg { timeout } => self.cmd_trywait_msg(timeout).await,
+            Command::SyncMsgs => self.cmd_sync_msgs().await,
+            Command::MsgsSubscribe => self.cmd_msgs_subscribe().await,
+            Command::History {
+                target,
+                since,
+                grep,
+            } => {
+                self.cmd_history(&target, since.as_deref(), grep.as_deref())
+                    .await
+            }
+            Command::GetChannels => self.cmd_get_channels().await,
+            Command::GetChannel { channel } => self.cmd_get_channel(&channel).await,
+            Command::SetChannel { number, name, key } => {
+                self.cmd_set_channel(number, &name, key.as_deref()).await
+            }
+            Command::RemoveChannel { channel } => self.cmd_remove_channel(&channel).await,
+            Command::AddChannel { name, key } => self.cmd_add_channel(&name, key.as_deref()).await,
+            Command::Scope { scope } => self.cmd_scope(&scope).await,
+
+            // Management commands
+            Command::Advert => self.cmd_advert(false).await,
+            Command::FloodAdv => self.cmd_advert(true).await,
+            Command::Get { param } => self.cmd_get(&param).await,
+            Command::Set { param, value } => self.cmd_set(&param, &value.join(" ")).await,
+            Command::Time { epoch } => self.cmd_set_time(epoch).await,
+            Command::Clock { sync } => self.cmd_clock(sync).await,
+            Command::SyncTime => self.cmd_sync_time().await,
+            Command::NodeDiscover { filter } => self.cmd_node_discover(filter).await,
+
+            // Contact commands
+            Command::Contacts | Command::List => self.cmd_contacts().await,
+            Command::ReloadContacts => self.cmd_reload_contacts().await,
+            Command::ContactInfo { contact } => self.cmd_contact_info(&contact).await,
+            Command::ContactName { contact } => self.cmd_contact_name(contact.as_deref()).await,
+            Command::ContactKey { contact } => self.cmd_contact_key(contact.as_deref()).await,
+            Command::ContactType { contact } => self.cmd_contact_type(contact.as_deref()).await,
+            Command::ContactLastmod { contact } => {
+                self.cmd_contact_lastmod(contact.as_deref()).await
+            }
+            Command::Dtrace { contact } => self.cmd_dtrace(contact.as_deref()).await,
+            Command::ContactTimeout { contact, timeout } => {
+                self.cmd_contact_timeout(&contact, timeout).await
+            }
+            Command::ShareContact { contact } => self.cmd_share_contact(&contact).await,
+            Command::ExportContact { contact } => self.cmd_export_contact(contact.as_deref()).await,
+            Command::ImportContact { uri } => self.cmd_import_contact(&uri).await,
+            Command::RemoveContact { contact } => self.cmd_remove_contact(&contact).await,
+            Command::Path { contact } => self.cmd_path(&contact).await,
+            Command::DiscPath { contact } => self.cmd_disc_path(&contact).await,
+            Command::ResetPath { contact } => self.cmd_reset_path(&contact).await,
+            Command::ChangePath { contact, path } => self.cmd_change_path(&contact, &path).await,
+            Command::ChangeFlags { contact, flags } => {
+                self.cmd_change_flags(&contact, &flags).await
+            }
+            Command::ReqTelemetry { contact } => self.cmd_req_telemetry(&contact).await,
+            Command::ReqMma { contact } => self.cmd_req_mma(&contact).await,
+            Command::ReqAcl { contact } => self.cmd_req_acl(&contact).await,
+            Command::PendingContacts => self.cmd_pending_contacts().await,
+            Command::AddPending { pending } => self.cmd_add_pending(&pending).await,
+            Command::FlushPending => self.cmd_flush_pending().await,
+
+            // Repeater commands
+            Command::Login { name, password } => self.cmd_login(&name, &password).await,
+            Command::Logout { name } => self.cmd_logout(&name).await,
+            Command::Cmd {
+                name,
+                command,
+                wait,
+                timeout,
+            } => self.cmd_cmd(&name, &command, wait, timeout).await,
+            Command::Wmt8 => self.cmd_wmt8().await,
+            Command::ReqStatus { name } => self.cmd_req_status(&name).await,
+            Command::ReqNeighbours { name } => self.cmd_req_neighbours(&name).await,
+            Command::ReqBinary { name, data } => self.cmd_req_binary(&name, &data).await,
+            Command::Trace { path } => self.cmd_trace(&path).await,
+
+            // Advanced commands
+            Command::Battery => self.cmd_battery().await,
+            Command::Stats { stats_type } => self.cmd_stats(stats_type).await,
+            Command::ExportKey => self.cmd_export_key().await,
+            Command::ImportKey { key } => self.cmd_import_key(&key).await,
+            Command::GetVars => self.cmd_get_vars().await,
+            Command::SetVar { key, value } => self.cmd_set_var(&key, &value.join(" ")).await,
+            Command::DecodeCapture { file } => crate::capture::decode(&self.display, &file),
+
+            // Configuration commands
+            Command::Config { action } => self.cmd_config(&action).await,
+        }
+    }
+
+    /// Executes the `script` command.
+    /// Runs a script file containing commands, stopping at the first failing line.
+    pub async fn cmd_script(&self, filename: &str) -> Result<()> {
+        let content = std::fs::read_to_string(filename).map_err(|e| CliError::Script {
+            line: 0,
+            message: format!("Failed to read script: {e}"),
+        })?;
+
+        for (line_num, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            self.execute_line(line)
+                .await
+                .map_err(|e| CliError::Script {
+                    line: line_num + 1,
+                    message: e.to_string(),
+                })?;
+        }
+
+        Ok(())
+    }
+}
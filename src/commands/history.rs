@@ -0,0 +1,153 @@
+//! Message history commands and background recording.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use meshcore::MeshCore;
+use meshcore::event::Event;
+use meshcore::transport::Transport;
+use tokio::sync::{Mutex, Notify};
+use tokio::task::JoinHandle;
+
+use super::supervisor::EventStream;
+use super::{CommandContext, current_timestamp, parse_time_value};
+use crate::error::{CliError, Result};
+use crate::history::{HistoryEntry, HistoryFilter, HistoryTarget, MessageStore};
+
+/// How long to keep draining already queued events when the recorder stops.
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Background task recording received messages and ACKs in the message store.
+pub struct Recorder {
+    stop: Arc<Notify>,
+    task: JoinHandle<()>,
+}
+
+impl Recorder {
+    /// Stops the recorder after recording the events that are already queued.
+    pub async fn stop(self) {
+        self.stop.notify_one();
+        let _ = self.task.await;
+    }
+}
+
+impl<T: Transport> CommandContext<T> {
+    /// Starts recording incoming messages and ACKs, if message history is enabled.
+    pub async fn start_recorder(&self) -> Option<Recorder> {
+        let store = self.store.clone()?;
+        let mut events = self.event_stream().await;
+        let client = self.client.clone();
+        let stop = Arc::new(Notify::new());
+        let stopped = stop.clone();
+
+        let task = tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    event = events.next() => match event {
+                        Some(event) => record_event(&store, &client, &event).await,
+                        None => break,
+                    },
+                    () = stopped.notified() => {
+                        drain_events(&store, &client, &mut events).await;
+                        break;
+                    }
+                }
+            }
+        });
+
+        Some(Recorder { stop, task })
+    }
+
+    /// Executes the `history` command.
+    pub async fn cmd_history(
+        &self,
+        target: &str,
+        since: Option<&str>,
+        grep: Option<&str>,
+    ) -> Result<()> {
+        let store = self.store.as_ref().ok_or_else(|| {
+            CliError::Command("Message history is disabled (config set message_history on)".into())
+        })?;
+
+        let target = if target.starts_with('#') {
+            HistoryTarget::Channel(self.get_channel_index(target).await?)
+        } else {
+            match self.get_contact(target).await {
+                Ok(contact) => HistoryTarget::Contact {
+                    name: contact.name,
+                    key: contact.public_key.to_hex(),
+                },
+                // Removed contacts can still be looked up by name
+                Err(_) => HistoryTarget::Contact {
+                    name: target.to_string(),
+                    key: String::new(),
+                },
+            }
+        };
+
+        let since = match since {
+            Some(s) => match parse_time_value(s)? {
+                0 => {
+                    return Err(CliError::InvalidArgument(format!(
+                        "Invalid time value: {s}. Use e.g. 2d, 12h or 30m"
+                    )));
+                }
+                secs => Some(current_timestamp().saturating_sub(secs)),
+            },
+            None => None,
+        };
+
+        let filter = HistoryFilter {
+            target,
+            since,
+            grep: grep.map(String::from),
+        };
+
+        let records = store.query(&filter)?;
+        self.display.print_history(&records);
+
+        Ok(())
+    }
+}
+
+/// Records an event in the message store.
+async fn record_event<T: Transport>(
+    store: &MessageStore,
+    client: &Arc<Mutex<MeshCore<T>>>,
+    event: &Event,
+) {
+    let entry = match event {
+        Event::ContactMessage(msg) => {
+            let contacts = client.lock().await.contacts().await;
+            let sender_name = super::lookup_sender_name(&contacts, &msg.sender_prefix);
+            HistoryEntry::received(
+                &sender_name,
+                &hex::encode(msg.sender_prefix),
+                &msg.text,
+                msg.signal.as_ref().map(|s| s.snr),
+            )
+        }
+        Event::ChannelMessage(msg) => HistoryEntry::channel_received(
+            msg.channel_index,
+            &msg.text,
+            msg.signal.as_ref().map(|s| s.snr),
+        ),
+        Event::Ack(ack) => HistoryEntry::ack(ack.code),
+        _ => return,
+    };
+
+    if let Err(e) = store.append(&entry) {
+        tracing::warn!("Failed to record message history: {e}");
+    }
+}
+
+/// Records the events that are already queued on the subscription.
+async fn drain_events<T: Transport>(
+    store: &MessageStore,
+    client: &Arc<Mutex<MeshCore<T>>>,
+    events: &mut EventStream<T>,
+) {
+    while let Ok(Some(event)) = tokio::time::timeout(DRAIN_TIMEOUT, events.next()).await {
+        record_event(store, client, &event).await;
+    }
+}
@@ -1,14 +1,24 @@
 //! Messaging-related commands.
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use meshcore::event::{Event, EventFilter};
+use meshcore::event::{Event, EventFilter, Subscription};
 use meshcore::protocol::PacketType;
+use meshcore::transport::Transport;
+use meshcore::types::ChannelMessage;
 
 use super::{CommandContext, current_timestamp};
 use crate::error::{CliError, Result};
+use crate::events::split_channel_sender;
+use crate::history::HistoryEntry;
+use crate::output::outln;
 
-impl CommandContext {
+/// Maximum text length of a message in bytes (the firmware's `MAX_TEXT_LEN`).
+///
+/// Channel messages go out as `<sender>: <text>`, so the prefix counts too.
+pub const MAX_TEXT_LEN: usize = 160;
+
+impl<T: Transport> CommandContext<T> {
     /// Executes the `msg` command.
     pub async fn cmd_msg(
         &self,
@@ -19,72 +29,349 @@ impl CommandContext {
     ) -> Result<()> {
         let contact = self.get_contact(name).await?;
         let text = message.join(" ");
+        check_length(&text, MAX_TEXT_LEN, "Message")?;
         let timestamp = current_timestamp();
 
+        // Subscribe before sending so a fast ACK isn't missed
+        let mut subscription = if wait {
+            Some(self.subscribe().await)
+        } else {
+            None
+        };
         let event = self
             .commands()
             .await
             .send_message(&contact.public_key, &text, 0, timestamp)
             .await?;
 
-        match event {
+        let expected_ack = match event {
             Event::MessageSent {
                 expected_ack,
                 timeout_ms,
             } => {
                 self.display.print_msg_sent(expected_ack, timeout_ms);
-                // Store expected ACK for wait_ack
+                self.record(&HistoryEntry::sent(
+                    &contact.name,
+                    &contact.public_key.to_hex(),
+                    &text,
+                    Some(expected_ack),
+                ));
+                // Remember the message so its ACK can be matched
                 let mut state = self.state.lock().await;
+                state.add_pending_ack(expected_ack, &contact.name, &text);
                 state.last_sender = Some(contact.name.clone());
+                Some(expected_ack)
             }
             Event::Error { message } => {
                 return Err(CliError::Command(message));
             }
-            _ => {}
+            _ => None,
+        };
+
+        // Wait for the ACK of this message if requested, not of any earlier one
+        if let Some(subscription) = &mut subscription {
+            let expected_ack = expected_ack
+                .ok_or_else(|| CliError::Command("Unexpected response to message".into()))?;
+            let timeout = Duration::from_secs(timeout_secs);
+            if wait_for_ack_code(subscription, &[expected_ack], timeout)
+                .await
+                .is_none()
+            {
+                return Err(CliError::Timeout("ACK".into()));
+            }
+            let pending = self.state.lock().await.take_pending_ack(expected_ack);
+            self.display.print_ack(expected_ack, pending.as_ref());
+        }
+
+        Ok(())
+    }
+
+    /// Executes the `msg --retry` command.
+    ///
+    /// Each attempt waits for the ACK for the firmware's suggested timeout. After
+    /// `flood_after` failed attempts over a direct path, the path is reset and the
+    /// remaining attempts go out as flood; with 0 every attempt is a flood.
+    pub async fn cmd_msg_retry(
+        &self,
+        name: &str,
+        message: &[String],
+        attempts: u32,
+        flood_after: u32,
+        timeout_secs: u64,
+    ) -> Result<()> {
+        let contact = self.get_contact(name).await?;
+        let text = message.join(" ");
+        check_length(&text, MAX_TEXT_LEN, "Message")?;
+        let attempts = attempts.max(1);
+
+        // Queued behind any other retried message still in flight
+        let _queue = self.send_queue.lock().await;
+
+        // Same timestamp for every attempt so the recipient can drop duplicates
+        let timestamp = current_timestamp();
+        let mut flood = contact.out_path_len < 0;
+        let mut expected = Vec::new();
+        let mut delivered = None;
+        let mut attempt = 0;
+
+        while attempt < attempts && delivered.is_none() {
+            attempt += 1;
+
+            if !flood && attempt > flood_after {
+                self.commands()
+                    .await
+                    .reset_path(&contact.public_key)
+                    .await?;
+                flood = true;
+                self.display.print_path_reset(&contact.name);
+            }
+
+            // Subscribe before sending so a fast ACK isn't missed
+            let mut subscription = self.subscribe().await;
+            let event = self
+                .commands()
+                .await
+                .send_message(
+                    &contact.public_key,
+                    &text,
+                    u8::try_from(attempt - 1).unwrap_or(u8::MAX),
+                    timestamp,
+                )
+                .await?;
+
+            let (expected_ack, timeout) = match event {
+                Event::MessageSent {
+                    expected_ack,
+                    timeout_ms,
+                } => (expected_ack, ack_timeout(timeout_ms, timeout_secs)),
+                Event::Error { message } => return Err(CliError::Command(message)),
+                _ => return Err(CliError::Command("Unexpected response to message".into())),
+            };
+            expected.push(expected_ack);
+            self.state
+                .lock()
+                .await
+                .add_pending_ack(expected_ack, &contact.name, &text);
+
+            let started = Instant::now();
+            // An ACK for an earlier attempt arriving late also counts
+            delivered = wait_for_ack_code(&mut subscription, &expected, timeout).await;
+            self.display.print_msg_attempt(
+                &contact.name,
+                attempt,
+                attempts,
+                flood,
+                expected_ack,
+                delivered.map(|_| started.elapsed()),
+            );
+        }
+
+        self.record(&HistoryEntry::sent(
+            &contact.name,
+            &contact.public_key.to_hex(),
+            &text,
+            delivered.or(expected.last().copied()),
+        ));
+        let mut state = self.state.lock().await;
+        for code in &expected {
+            state.take_pending_ack(*code);
+        }
+        state.last_sender = Some(contact.name.clone());
+        drop(state);
+
+        self.display
+            .print_delivery(&contact.name, delivered.is_some(), attempt, flood);
+        if delivered.is_none() {
+            return Err(CliError::Timeout(format!(
+                "ACK from {} after {attempt} attempts",
+                contact.name
+            )));
         }
 
-        // Wait for ACK if requested
-        if wait {
-            self.cmd_wait_ack(timeout_secs).await?;
+        Ok(())
+    }
+
+    /// Executes the `msg --split` command.
+    ///
+    /// Oversized text is sent as numbered parts, in order, each waiting for its ACK
+    /// (for the firmware's suggested timeout) before the next one goes out.
+    pub async fn cmd_msg_split(
+        &self,
+        name: &str,
+        message: &[String],
+        timeout_secs: u64,
+    ) -> Result<()> {
+        let contact = self.get_contact(name).await?;
+        let parts = split_message(&message.join(" "), MAX_TEXT_LEN);
+        let total = parts.len();
+
+        // Parts of other split or retried messages must not interleave
+        let _queue = self.send_queue.lock().await;
+
+        for (i, part) in parts.iter().enumerate() {
+            // Subscribe before sending so a fast ACK isn't missed
+            let mut subscription = self.subscribe().await;
+            let event = self
+                .commands()
+                .await
+                .send_message(&contact.public_key, part, 0, current_timestamp())
+                .await?;
+
+            let (expected_ack, timeout) = match event {
+                Event::MessageSent {
+                    expected_ack,
+                    timeout_ms,
+                } => {
+                    self.display.print_msg_sent(expected_ack, timeout_ms);
+                    (expected_ack, ack_timeout(timeout_ms, timeout_secs))
+                }
+                Event::Error { message } => return Err(CliError::Command(message)),
+                _ => return Err(CliError::Command("Unexpected response to message".into())),
+            };
+            self.record(&HistoryEntry::sent(
+                &contact.name,
+                &contact.public_key.to_hex(),
+                part,
+                Some(expected_ack),
+            ));
+            self.state
+                .lock()
+                .await
+                .add_pending_ack(expected_ack, &contact.name, part);
+
+            if wait_for_ack_code(&mut subscription, &[expected_ack], timeout)
+                .await
+                .is_none()
+            {
+                return Err(CliError::Timeout(format!(
+                    "ACK for part {}/{total} from {}",
+                    i + 1,
+                    contact.name
+                )));
+            }
+            let pending = self.state.lock().await.take_pending_ack(expected_ack);
+            self.display.print_ack(expected_ack, pending.as_ref());
         }
 
+        self.state.lock().await.last_sender = Some(contact.name.clone());
         Ok(())
     }
 
+    /// Prints a channel message with its channel name and sender.
+    async fn print_channel_message(&self, msg: &ChannelMessage) {
+        let channel_name = self
+            .state
+            .lock()
+            .await
+            .channels
+            .get(&msg.channel_index)
+            .cloned();
+        let (sender, text) = split_channel_sender(&msg.text);
+        self.display.print_channel_message(
+            msg.channel_index,
+            channel_name.as_deref(),
+            sender,
+            text,
+            msg.signal.as_ref().map(|s| s.snr),
+        );
+    }
+
+    /// Executes the `send` command (message to the current contact or channel).
+    pub async fn cmd_send(&self, message: &[String]) -> Result<()> {
+        let state = self.state.lock().await;
+        let (contact, channel) = (state.current_contact.clone(), state.current_channel);
+        drop(state);
+
+        if let Some(channel) = channel {
+            return self.cmd_chan(channel, message).await;
+        }
+        let contact = contact.ok_or_else(|| {
+            CliError::InvalidArgument("No contact selected. Use 'to <contact>' first.".into())
+        })?;
+        self.cmd_msg(&contact, message, false, 30).await
+    }
+
     /// Executes the `wait_ack` command.
+    ///
+    /// Only ACKs for messages sent in this session count; unrelated ACKs are ignored.
     pub async fn cmd_wait_ack(&self, timeout_secs: u64) -> Result<()> {
-        let filter = EventFilter::packet_types(vec![PacketType::Ack]);
+        if self.state.lock().await.pending_acks.is_empty() {
+            return Err(CliError::Command(
+                "No sent message is waiting for an ACK".into(),
+            ));
+        }
+
+        let mut subscription = self.subscribe().await;
         let timeout = Duration::from_secs(timeout_secs);
 
-        match self.wait_for_event(filter, timeout).await {
-            Ok(Event::Ack(ack)) => {
-                self.display.print_ack(ack.code);
-            }
-            Ok(_) => {
-                return Err(CliError::Timeout("ACK".into()));
+        let result = tokio::time::timeout(timeout, async {
+            while let Some(event) = subscription.recv().await {
+                if let Event::Ack(ack) = event
+                    && let Some(pending) = self.state.lock().await.take_pending_ack(ack.code)
+                {
+                    return Some((ack.code, pending));
+                }
             }
-            Err(e) => {
-                return Err(e);
+            None
+        })
+        .await;
+
+        match result {
+            Ok(Some((code, pending))) => {
+                self.display.print_ack(code, Some(&pending));
+                Ok(())
             }
+            Ok(None) | Err(_) => Err(CliError::Timeout("ACK".into())),
         }
-
-        Ok(())
     }
 
     /// Executes the `chan` command.
     pub async fn cmd_chan(&self, channel: u8, message: &[String]) -> Result<()> {
         let text = message.join(" ");
-        let timestamp = current_timestamp();
+        check_length(&text, self.channel_text_limit().await, "Channel message")?;
+        self.send_channel_text(channel, &text, "channel message sent")
+            .await
+    }
+
+    /// Executes the `chan --split` command: oversized text goes out as numbered parts.
+    pub async fn cmd_chan_split(&self, channel: u8, message: &[String]) -> Result<()> {
+        let parts = split_message(&message.join(" "), self.channel_text_limit().await);
+        let total = parts.len();
+
+        let _queue = self.send_queue.lock().await;
+        for (i, part) in parts.iter().enumerate() {
+            let status = if total > 1 {
+                format!("channel message part {}/{total} sent", i + 1)
+            } else {
+                "channel message sent".to_string()
+            };
+            self.send_channel_text(channel, part, &status).await?;
+        }
 
+        Ok(())
+    }
+
+    /// Executes the `public` command (send to channel 0).
+    pub async fn cmd_public(&self, message: &[String], split: bool) -> Result<()> {
+        if split {
+            self.cmd_chan_split(0, message).await
+        } else {
+            self.cmd_chan(0, message).await
+        }
+    }
+
+    /// Sends one channel message and records it.
+    async fn send_channel_text(&self, channel: u8, text: &str, status: &str) -> Result<()> {
         let event = self
             .commands()
             .await
-            .send_channel_message(channel, &text, timestamp)
+            .send_channel_message(channel, text, current_timestamp())
             .await?;
 
         match event {
             Event::Ok => {
-                self.display.print_ok("channel message sent");
+                self.display.print_ok(status);
+                self.record(&HistoryEntry::channel_sent(channel, text));
             }
             Event::Error { message } => {
                 return Err(CliError::Command(message));
@@ -95,9 +382,14 @@ impl CommandContext {
         Ok(())
     }
 
-    /// Executes the `public` command (send to channel 0).
-    pub async fn cmd_public(&self, message: &[String]) -> Result<()> {
-        self.cmd_chan(0, message).await
+    /// Returns the text limit of channel messages, minus the `<name>: ` prefix
+    /// the firmware adds.
+    pub async fn channel_text_limit(&self) -> usize {
+        let prefix = self
+            .current_device_name()
+            .await
+            .map_or(0, |name| name.len() + 2);
+        MAX_TEXT_LEN.saturating_sub(prefix)
     }
 
     /// Executes the `recv` command.
@@ -123,15 +415,7 @@ impl CommandContext {
                 state.last_sender = Some(sender_name);
             }
             Event::ChannelMessage(msg) => {
-                // Channel messages don't include sender information
-                let channel_str = format!("#{}", msg.channel_index);
-                self.display.print_message(
-                    &channel_str,
-                    &msg.text,
-                    false,
-                    msg.signal.as_ref().map(|s| s.snr),
-                    None, // v3 format doesn't include RSSI
-                );
+                self.print_channel_message(&msg).await;
             }
             Event::NoMoreMessages => {
                 self.display.print_no_more_messages();
@@ -194,14 +478,7 @@ impl CommandContext {
                     state.last_sender = Some(sender_name);
                 }
                 Event::ChannelMessage(msg) => {
-                    let channel_str = format!("#{}", msg.channel_index);
-                    self.display.print_message(
-                        &channel_str,
-                        &msg.text,
-                        false,
-                        msg.signal.as_ref().map(|s| s.snr),
-                        None,
-                    );
+                    self.print_channel_message(&msg).await;
                 }
                 Event::NoMoreMessages => {
                     self.display.print_no_more_messages();
@@ -236,14 +513,7 @@ impl CommandContext {
                     );
                 }
                 Event::ChannelMessage(msg) => {
-                    let channel_str = format!("#{}", msg.channel_index);
-                    self.display.print_message(
-                        &channel_str,
-                        &msg.text,
-                        false,
-                        msg.signal.as_ref().map(|s| s.snr),
-                        None,
-                    );
+                    self.print_channel_message(&msg).await;
                 }
                 Event::NoMoreMessages => {
                     break;
@@ -258,15 +528,31 @@ impl CommandContext {
         Ok(())
     }
 
+    /// Returns the events carried by an event, fetching queued messages from the device
+    /// when it is `MessagesWaiting`.
+    pub async fn resolve_waiting(&self, event: Event) -> Result<Vec<Event>> {
+        if !matches!(event, Event::MessagesWaiting) {
+            return Ok(vec![event]);
+        }
+
+        let mut messages = Vec::new();
+        while let event @ (Event::ContactMessage(_) | Event::ChannelMessage(_)) =
+            self.commands().await.get_message().await?
+        {
+            messages.push(event);
+        }
+        Ok(messages)
+    }
+
     /// Executes the `msgs_subscribe` command.
     pub async fn cmd_msgs_subscribe(&self) -> Result<()> {
-        let mut subscription = self.subscribe().await;
+        let mut events = self.event_stream().await;
 
-        println!("Subscribed to messages. Press Ctrl+C to stop.");
+        outln!("Subscribed to messages. Press Ctrl+C to stop.");
 
         loop {
             tokio::select! {
-                event = subscription.recv() => {
+                event = events.next() => {
                     match event {
                         Some(event) => {
                             self.handle_message_event(event).await?;
@@ -285,6 +571,8 @@ impl CommandContext {
 
     /// Handles a message event.
     async fn handle_message_event(&self, event: Event) -> Result<()> {
+        self.run_hooks(&event).await;
+
         match event {
             Event::ContactMessage(msg) => {
                 let contacts = self.client.lock().await.contacts().await;
@@ -302,21 +590,15 @@ impl CommandContext {
                 state.last_sender = Some(sender_name);
             }
             Event::ChannelMessage(msg) => {
-                let channel_str = format!("#{}", msg.channel_index);
-                self.display.print_message(
-                    &channel_str,
-                    &msg.text,
-                    false,
-                    msg.signal.as_ref().map(|s| s.snr),
-                    None,
-                );
+                self.print_channel_message(&msg).await;
             }
             Event::Ack(ack) => {
-                self.display.print_ack(ack.code);
+                let pending = self.state.lock().await.pending_acks.get(&ack.code).cloned();
+                self.display.print_ack(ack.code, pending.as_ref());
             }
             Event::Advertisement(key) => {
                 if !self.display.is_json() {
-                    println!("Advertisement from: {}", key.to_hex());
+                    outln!("Advertisement from: {}", key.to_hex());
                 }
 
                 // Add to pending if manual_add_contacts is enabled
@@ -325,7 +607,7 @@ impl CommandContext {
             }
             Event::NewContactAdvert(contact) => {
                 if !self.display.is_json() {
-                    println!(
+                    outln!(
                         "New contact: {} ({})",
                         contact.name,
                         contact.public_key.to_hex()
@@ -343,7 +625,7 @@ impl CommandContext {
             }
             Event::MessagesWaiting => {
                 if !self.display.is_json() {
-                    println!("Messages waiting on device");
+                    outln!("Messages waiting on device");
                 }
             }
             _ => {}
@@ -352,3 +634,162 @@ impl CommandContext {
         Ok(())
     }
 }
+
+/// Waits until an ACK with one of the expected codes arrives, ignoring all others.
+async fn wait_for_ack_code(
+    subscription: &mut Subscription,
+    expected: &[u32],
+    timeout: Duration,
+) -> Option<u32> {
+    tokio::time::timeout(timeout, async {
+        while let Some(event) = subscription.recv().await {
+            if let Event::Ack(ack) = event
+                && expected.contains(&ack.code)
+            {
+                return Some(ack.code);
+            }
+        }
+        None
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+/// Returns how long to wait for an ACK: the firmware's suggested timeout, or
+/// `fallback_secs` when it gives none.
+fn ack_timeout(timeout_ms: u32, fallback_secs: u64) -> Duration {
+    if timeout_ms > 0 {
+        Duration::from_millis(u64::from(timeout_ms))
+    } else {
+        Duration::from_secs(fallback_secs)
+    }
+}
+
+/// Refuses text longer than `limit` bytes (UTF-8).
+fn check_length(text: &str, limit: usize, what: &str) -> Result<()> {
+    if text.len() > limit {
+        return Err(CliError::InvalidArgument(format!(
+            "{what} is {} bytes, the limit is {limit} (use --split to send it in parts)",
+            text.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Splits text into parts of at most `limit` bytes, numbered `(1/3) ...`.
+///
+/// Parts are cut at whitespace where possible and never inside a UTF-8 character.
+/// Text that fits is returned unchanged, without numbering.
+#[must_use]
+pub fn split_message(text: &str, limit: usize) -> Vec<String> {
+    if text.len() <= limit {
+        return vec![text.to_string()];
+    }
+
+    // The prefix length depends on the number of parts, so grow it until it fits
+    let mut digits = 1;
+    loop {
+        let prefix_len = "(/) ".len() + 2 * digits;
+        let chunks = chunk_text(text, limit.saturating_sub(prefix_len).max(1));
+        let total = chunks.len();
+        if total.to_string().len() <= digits {
+            return chunks
+                .iter()
+                .enumerate()
+                .map(|(i, chunk)| format!("({}/{total}) {chunk}", i + 1))
+                .collect();
+        }
+        digits += 1;
+    }
+}
+
+/// Cuts text into chunks of at most `budget` bytes, preferring whitespace boundaries.
+fn chunk_text(text: &str, budget: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = text.trim();
+
+    while !rest.is_empty() {
+        if rest.len() <= budget {
+            chunks.push(rest);
+            break;
+        }
+
+        // Longest prefix within the budget that ends on a character boundary
+        let mut end = budget;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end == 0 {
+            // A single character wider than the budget; send it alone
+            end = rest.chars().next().map_or(rest.len(), char::len_utf8);
+        }
+
+        // Prefer cutting at the last whitespace, unless that leaves an empty chunk
+        let cut = if rest[end..].starts_with(char::is_whitespace) {
+            end
+        } else {
+            rest[..end]
+                .rfind(char::is_whitespace)
+                .filter(|&i| i > 0)
+                .unwrap_or(end)
+        };
+
+        chunks.push(rest[..cut].trim_end());
+        rest = rest[cut..].trim_start();
+    }
+
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ack_timeout() {
+        assert_eq!(ack_timeout(4500, 30), Duration::from_millis(4500));
+        assert_eq!(ack_timeout(0, 30), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_split_message_fits() {
+        assert_eq!(split_message("hello", 10), vec!["hello"]);
+        assert!(check_length("hello", 5, "Message").is_ok());
+        // "é" is two bytes
+        assert!(check_length("héllo", 5, "Message").is_err());
+    }
+
+    #[test]
+    fn test_split_message_parts() {
+        let text = "one two three four five six seven eight nine ten";
+        let parts = split_message(text, 20);
+        assert!(parts.iter().all(|p| p.len() <= 20));
+        assert_eq!(parts[0], "(1/4) one two three");
+        assert_eq!(parts[3], "(4/4) nine ten");
+
+        let rejoined: Vec<&str> = parts.iter().map(|p| &p[6..]).collect();
+        assert_eq!(rejoined.join(" "), text);
+    }
+
+    #[test]
+    fn test_split_message_utf8() {
+        let text = "ä".repeat(30);
+        let parts = split_message(&text, 16);
+        assert!(parts.iter().all(|p| p.len() <= 16));
+        let total: usize = parts
+            .iter()
+            .map(|p| p.chars().filter(|c| *c == 'ä').count())
+            .sum();
+        assert_eq!(total, 30);
+    }
+
+    #[test]
+    fn test_split_message_many_parts() {
+        let text = "x".repeat(200);
+        let parts = split_message(&text, 20);
+        assert_eq!(parts.len(), 17);
+        assert!(parts[0].starts_with("(1/17) "));
+        assert!(parts.iter().all(|p| p.len() <= 20));
+    }
+}
@@ -0,0 +1,625 @@
+//! Scriptable in-process companion radio for command tests.
+//!
+//! `MockTransport` speaks the companion protocol at the frame level: every command
+//! frame written by the client is answered by `MockRadio` with canned response frames,
+//! so the real `MeshCore` client and event parsing are exercised end to end.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use meshcore::MeshCore;
+use meshcore::transport::Transport;
+use tokio::sync::mpsc;
+
+use super::CommandContext;
+use crate::display::Display;
+
+// Command codes
+pub const CMD_APP_START: u8 = 1;
+pub const CMD_SEND_TXT_MSG: u8 = 2;
+pub const CMD_SEND_CHANNEL_TXT_MSG: u8 = 3;
+pub const CMD_GET_CONTACTS: u8 = 4;
+pub const CMD_GET_DEVICE_TIME: u8 = 5;
+pub const CMD_SET_DEVICE_TIME: u8 = 6;
+pub const CMD_SEND_SELF_ADVERT: u8 = 7;
+pub const CMD_SYNC_NEXT_MESSAGE: u8 = 10;
+pub const CMD_RESET_PATH: u8 = 13;
+pub const CMD_REMOVE_CONTACT: u8 = 15;
+pub const CMD_REBOOT: u8 = 19;
+pub const CMD_GET_BATT_AND_STORAGE: u8 = 20;
+pub const CMD_DEVICE_QUERY: u8 = 22;
+pub const CMD_SEND_LOGIN: u8 = 26;
+pub const CMD_GET_CHANNEL: u8 = 31;
+pub const CMD_SET_CHANNEL: u8 = 32;
+
+// Response and push codes
+pub const RESP_OK: u8 = 0;
+pub const RESP_ERR: u8 = 1;
+pub const RESP_CONTACTS_START: u8 = 2;
+pub const RESP_CONTACT: u8 = 3;
+pub const RESP_END_OF_CONTACTS: u8 = 4;
+pub const RESP_SELF_INFO: u8 = 5;
+pub const RESP_SENT: u8 = 6;
+pub const RESP_CURR_TIME: u8 = 9;
+pub const RESP_NO_MORE_MESSAGES: u8 = 10;
+pub const RESP_BATTERY: u8 = 12;
+pub const RESP_DEVICE_INFO: u8 = 13;
+pub const RESP_CONTACT_MSG_RECV_V3: u8 = 16;
+pub const RESP_CHANNEL_MSG_RECV_V3: u8 = 17;
+pub const RESP_CHANNEL_INFO: u8 = 18;
+pub const PUSH_ADVERT: u8 = 0x80;
+pub const PUSH_SEND_CONFIRMED: u8 = 0x82;
+pub const PUSH_MSG_WAITING: u8 = 0x83;
+
+// Error codes
+const ERR_UNSUPPORTED_CMD: u8 = 1;
+const ERR_NOT_FOUND: u8 = 2;
+
+/// Channel slots on the mock radio.
+const CHANNEL_SLOTS: u8 = 8;
+
+/// Contact type byte of a chat node.
+pub const ADV_TYPE_CHAT: u8 = 1;
+
+/// Contact type byte of a repeater.
+pub const ADV_TYPE_REPEATER: u8 = 2;
+
+/// A contact stored on the mock radio.
+#[derive(Debug, Clone)]
+pub struct MockContact {
+    /// Public key.
+    pub public_key: [u8; 32],
+    /// Advert type (`ADV_TYPE_*`).
+    pub contact_type: u8,
+    /// Outgoing path length (-1 for flood).
+    pub path_len: i8,
+    /// Contact name.
+    pub name: String,
+}
+
+/// Radio state shared between the transport and the test.
+#[derive(Debug)]
+struct Radio {
+    name: String,
+    public_key: [u8; 32],
+    time: u32,
+    battery_mv: u16,
+    contacts: Vec<MockContact>,
+    channels: BTreeMap<u8, (String, [u8; 16])>,
+    inbox: VecDeque<Vec<u8>>,
+    scripted: HashMap<u8, VecDeque<Vec<Vec<u8>>>>,
+    received: Vec<Vec<u8>>,
+    auto_ack: bool,
+    next_ack: u32,
+    link: Option<mpsc::UnboundedSender<Vec<u8>>>,
+}
+
+/// Handle to a scriptable mock companion radio.
+///
+/// Clones share the same radio, so a test keeps one handle while the client owns the
+/// transport.
+#[derive(Debug, Clone)]
+pub struct MockRadio {
+    radio: Arc<Mutex<Radio>>,
+}
+
+impl MockRadio {
+    /// Creates a radio with the given name, no contacts and a public channel in slot 0.
+    #[must_use]
+    pub fn new(name: &str) -> Self {
+        let mut channels = BTreeMap::new();
+        channels.insert(0, ("Public".to_string(), [0x8b; 16]));
+
+        Self {
+            radio: Arc::new(Mutex::new(Radio {
+                name: name.to_string(),
+                public_key: [0xaa; 32],
+                time: 1_700_000_000,
+                battery_mv: 4012,
+                contacts: Vec::new(),
+                channels,
+                inbox: VecDeque::new(),
+                scripted: HashMap::new(),
+                received: Vec::new(),
+                auto_ack: false,
+                next_ack: 0x1000_0001,
+                link: None,
+            })),
+        }
+    }
+
+    /// Adds a contact whose public key is filled with `key_byte`.
+    #[must_use]
+    pub fn with_contact(self, name: &str, key_byte: u8, contact_type: u8) -> Self {
+        self.lock().contacts.push(MockContact {
+            public_key: [key_byte; 32],
+            contact_type,
+            path_len: -1,
+            name: name.to_string(),
+        });
+        self
+    }
+
+    /// Gives the named contact a direct path of `path_len` hops.
+    #[must_use]
+    pub fn with_path(self, name: &str, path_len: i8) -> Self {
+        if let Some(contact) = self.lock().contacts.iter_mut().find(|c| c.name == name) {
+            contact.path_len = path_len;
+        }
+        self
+    }
+
+    /// Stores a channel in the given slot.
+    #[must_use]
+    pub fn with_channel(self, index: u8, name: &str, secret: [u8; 16]) -> Self {
+        self.lock()
+            .channels
+            .insert(index, (name.to_string(), secret));
+        self
+    }
+
+    /// Sets the device clock.
+    #[must_use]
+    pub fn with_time(self, time: u32) -> Self {
+        self.lock().time = time;
+        self
+    }
+
+    /// Confirms every sent message with an ACK shortly after the `SENT` response.
+    #[must_use]
+    pub fn with_auto_ack(self) -> Self {
+        self.lock().auto_ack = true;
+        self
+    }
+
+    /// Answers the next command with the given code with `frames` instead of the default.
+    pub fn script(&self, code: u8, frames: Vec<Vec<u8>>) {
+        self.lock()
+            .scripted
+            .entry(code)
+            .or_default()
+            .push_back(frames);
+    }
+
+    /// Queues a direct message from a contact and notifies the client.
+    pub fn queue_message(&self, from: &str, text: &str, snr: f32) {
+        let mut radio = self.lock();
+        let prefix = radio
+            .contacts
+            .iter()
+            .find(|c| c.name == from)
+            .map_or([0xee; 32], |c| c.public_key);
+        let frame = contact_message_frame(&prefix[..6], text, snr, radio.time);
+        radio.inbox.push_back(frame);
+        radio.emit(vec![PUSH_MSG_WAITING]);
+    }
+
+    /// Queues a channel message and notifies the client.
+    pub fn queue_channel_message(&self, channel: u8, text: &str, snr: f32) {
+        let mut radio = self.lock();
+        let frame = channel_message_frame(channel, text, snr, radio.time);
+        radio.inbox.push_back(frame);
+        radio.emit(vec![PUSH_MSG_WAITING]);
+    }
+
+    /// Emits an unsolicited frame (push notification) to the client.
+    pub fn push(&self, frame: Vec<u8>) {
+        self.lock().emit(frame);
+    }
+
+    /// Emits an ACK for a sent message.
+    pub fn ack(&self, code: u32) {
+        self.push(ack_frame(code, 1200));
+    }
+
+    /// Emits an advert from the contact with the given name.
+    pub fn advert(&self, name: &str) {
+        let radio = self.lock();
+        if let Some(key) = radio
+            .contacts
+            .iter()
+            .find(|c| c.name == name)
+            .map(|c| c.public_key)
+        {
+            let mut frame = vec![PUSH_ADVERT];
+            frame.extend_from_slice(&key);
+            radio.emit(frame);
+        }
+    }
+
+    /// Drops the link as if the serial cable was pulled.
+    pub fn drop_link(&self) {
+        self.lock().link = None;
+    }
+
+    /// Returns the codes of all commands received, in order.
+    #[must_use]
+    pub fn received_codes(&self) -> Vec<u8> {
+        self.lock().received.iter().map(|f| f[0]).collect()
+    }
+
+    /// Returns the last command frame with the given code.
+    #[must_use]
+    pub fn last_command(&self, code: u8) -> Option<Vec<u8>> {
+        self.lock()
+            .received
+            .iter()
+            .rev()
+            .find(|f| f.first() == Some(&code))
+            .cloned()
+    }
+
+    /// Returns the device clock.
+    #[must_use]
+    pub fn time(&self) -> u32 {
+        self.lock().time
+    }
+
+    /// Returns the names of the stored channels by slot.
+    #[must_use]
+    pub fn channel_names(&self) -> BTreeMap<u8, String> {
+        self.lock()
+            .channels
+            .iter()
+            .map(|(index, (name, _))| (*index, name.clone()))
+            .collect()
+    }
+
+    /// Returns the names of the stored contacts.
+    #[must_use]
+    pub fn contact_names(&self) -> Vec<String> {
+        self.lock()
+            .contacts
+            .iter()
+            .map(|c| c.name.clone())
+            .collect()
+    }
+
+    /// Handles a command frame and returns the response frames.
+    #[must_use]
+    pub fn handle(&self, frame: &[u8]) -> Vec<Vec<u8>> {
+        self.lock().handle(frame)
+    }
+
+    /// Creates a transport connected to this radio.
+    #[must_use]
+    pub fn transport(&self) -> MockTransport {
+        MockTransport {
+            radio: self.clone(),
+            connected: false,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Radio> {
+        self.radio
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+impl Radio {
+    /// Sends a frame to the client, if connected.
+    fn emit(&self, frame: Vec<u8>) {
+        if let Some(link) = &self.link {
+            let _ = link.send(frame);
+        }
+    }
+
+    /// Answers a command frame.
+    fn handle(&mut self, frame: &[u8]) -> Vec<Vec<u8>> {
+        let Some(&code) = frame.first() else {
+            return vec![err_frame(ERR_UNSUPPORTED_CMD)];
+        };
+        self.received.push(frame.to_vec());
+
+        if let Some(frames) = self.scripted.get_mut(&code).and_then(VecDeque::pop_front) {
+            return frames;
+        }
+
+        match code {
+            CMD_APP_START => vec![self.self_info_frame()],
+            CMD_SEND_TXT_MSG => self.send_message(frame),
+            CMD_SEND_CHANNEL_TXT_MSG => match frame.get(2) {
+                Some(index) if self.channels.contains_key(index) => vec![vec![RESP_OK]],
+                _ => vec![err_frame(ERR_NOT_FOUND)],
+            },
+            CMD_GET_CONTACTS => self.contacts_frames(),
+            CMD_GET_DEVICE_TIME => vec![u32_frame(RESP_CURR_TIME, self.time)],
+            CMD_SET_DEVICE_TIME => {
+                if let Some(time) = read_u32(frame, 1) {
+                    self.time = time;
+                }
+                vec![vec![RESP_OK]]
+            }
+            CMD_SEND_SELF_ADVERT => vec![vec![RESP_OK]],
+            CMD_SYNC_NEXT_MESSAGE => {
+                vec![
+                    self.inbox
+                        .pop_front()
+                        .unwrap_or_else(|| vec![RESP_NO_MORE_MESSAGES]),
+                ]
+            }
+            CMD_RESET_PATH => self.with_contact_key(frame, |contact| contact.path_len = -1),
+            CMD_REMOVE_CONTACT => {
+                let before = self.contacts.len();
+                self.contacts
+                    .retain(|c| frame.get(1..33) != Some(&c.public_key[..]));
+                if self.contacts.len() < before {
+                    vec![vec![RESP_OK]]
+                } else {
+                    vec![err_frame(ERR_NOT_FOUND)]
+                }
+            }
+            CMD_REBOOT => Vec::new(),
+            CMD_GET_BATT_AND_STORAGE => {
+                let mut frame = vec![RESP_BATTERY];
+                frame.extend_from_slice(&self.battery_mv.to_le_bytes());
+                frame.extend_from_slice(&128u32.to_le_bytes());
+                frame.extend_from_slice(&1024u32.to_le_bytes());
+                vec![frame]
+            }
+            CMD_DEVICE_QUERY => vec![device_info_frame()],
+            CMD_GET_CHANNEL => match frame.get(1) {
+                Some(&index) if index < CHANNEL_SLOTS => vec![self.channel_frame(index)],
+                _ => vec![err_frame(ERR_NOT_FOUND)],
+            },
+            CMD_SET_CHANNEL => self.set_channel(frame),
+            _ => vec![err_frame(ERR_UNSUPPORTED_CMD)],
+        }
+    }
+
+    /// Answers `send_txt_msg` with `SENT` and an ACK if auto-ACK is on.
+    fn send_message(&mut self, frame: &[u8]) -> Vec<Vec<u8>> {
+        let Some(prefix) = frame.get(7..13) else {
+            return vec![err_frame(ERR_NOT_FOUND)];
+        };
+        let Some(contact) = self.contacts.iter().find(|c| c.public_key[..6] == *prefix) else {
+            return vec![err_frame(ERR_NOT_FOUND)];
+        };
+
+        let flood = contact.path_len < 0;
+        let code = self.next_ack;
+        self.next_ack += 1;
+
+        let sent = sent_frame(flood, code, 5000);
+
+        // ACK a little later, like a radio would, so waiters subscribe in time
+        if self.auto_ack
+            && let Some(link) = self.link.clone()
+        {
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let _ = link.send(ack_frame(code, 800));
+            });
+        }
+        vec![sent]
+    }
+
+    /// Applies `update` to the contact whose key follows the command code.
+    fn with_contact_key(
+        &mut self,
+        frame: &[u8],
+        update: impl FnOnce(&mut MockContact),
+    ) -> Vec<Vec<u8>> {
+        match self
+            .contacts
+            .iter_mut()
+            .find(|c| frame.get(1..33) == Some(&c.public_key[..]))
+        {
+            Some(contact) => {
+                update(contact);
+                vec![vec![RESP_OK]]
+            }
+            None => vec![err_frame(ERR_NOT_FOUND)],
+        }
+    }
+
+    /// Stores or clears (empty name) a channel slot.
+    fn set_channel(&mut self, frame: &[u8]) -> Vec<Vec<u8>> {
+        let (Some(&index), Some(name), Some(secret)) =
+            (frame.get(1), frame.get(2..34), frame.get(34..50))
+        else {
+            return vec![err_frame(ERR_UNSUPPORTED_CMD)];
+        };
+
+        let name = String::from_utf8_lossy(name)
+            .trim_end_matches('\0')
+            .to_string();
+        if name.is_empty() {
+            self.channels.remove(&index);
+        } else {
+            let mut key = [0u8; 16];
+            key.copy_from_slice(secret);
+            self.channels.insert(index, (name, key));
+        }
+        vec![vec![RESP_OK]]
+    }
+
+    fn self_info_frame(&self) -> Vec<u8> {
+        let mut frame = vec![RESP_SELF_INFO, ADV_TYPE_CHAT, 20, 22];
+        frame.extend_from_slice(&self.public_key);
+        frame.extend_from_slice(&52_520_008i32.to_le_bytes());
+        frame.extend_from_slice(&13_404_954i32.to_le_bytes());
+        frame.extend_from_slice(&[0, 0, 0, 0]);
+        frame.extend_from_slice(&869_525u32.to_le_bytes());
+        frame.extend_from_slice(&250_000u32.to_le_bytes());
+        frame.extend_from_slice(&[11, 5]);
+        frame.extend_from_slice(self.name.as_bytes());
+        frame
+    }
+
+    fn contacts_frames(&self) -> Vec<Vec<u8>> {
+        let count = u32::try_from(self.contacts.len()).unwrap_or(u32::MAX);
+        let mut frames = vec![u32_frame(RESP_CONTACTS_START, count)];
+
+        for contact in &self.contacts {
+            let mut frame = vec![RESP_CONTACT];
+            frame.extend_from_slice(&contact.public_key);
+            frame.push(contact.contact_type);
+            frame.push(0);
+            frame.extend_from_slice(&contact.path_len.to_le_bytes());
+            frame.extend_from_slice(&[0u8; 64]);
+            frame.extend_from_slice(&padded::<32>(&contact.name));
+            frame.extend_from_slice(&(self.time - 3600).to_le_bytes());
+            frame.extend_from_slice(&0i32.to_le_bytes());
+            frame.extend_from_slice(&0i32.to_le_bytes());
+            frame.extend_from_slice(&(self.time - 3600).to_le_bytes());
+            frames.push(frame);
+        }
+
+        frames.push(u32_frame(RESP_END_OF_CONTACTS, self.time - 3600));
+        frames
+    }
+
+    fn channel_frame(&self, index: u8) -> Vec<u8> {
+        let (name, secret) = self
+            .channels
+            .get(&index)
+            .map_or((String::new(), [0u8; 16]), |(name, secret)| {
+                (name.clone(), *secret)
+            });
+
+        let mut frame = vec![RESP_CHANNEL_INFO, index];
+        frame.extend_from_slice(&padded::<32>(&name));
+        frame.extend_from_slice(&secret);
+        frame
+    }
+}
+
+/// Transport that delivers frames to and from a `MockRadio` in process.
+#[derive(Debug)]
+pub struct MockTransport {
+    radio: MockRadio,
+    connected: bool,
+}
+
+impl Transport for MockTransport {
+    async fn connect(
+        &mut self,
+    ) -> std::result::Result<mpsc::UnboundedReceiver<Vec<u8>>, meshcore::Error> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.radio.lock().link = Some(tx);
+        self.connected = true;
+        Ok(rx)
+    }
+
+    async fn disconnect(&mut self) -> std::result::Result<(), meshcore::Error> {
+        self.radio.lock().link = None;
+        self.connected = false;
+        Ok(())
+    }
+
+    async fn send(&mut self, frame: &[u8]) -> std::result::Result<(), meshcore::Error> {
+        let mut radio = self.radio.lock();
+        for response in radio.handle(frame) {
+            radio.emit(response);
+        }
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected && self.radio.lock().link.is_some()
+    }
+}
+
+/// Connects a client to the radio and wraps it in a command context.
+pub async fn context(radio: &MockRadio, json: bool) -> CommandContext<MockTransport> {
+    let mut client = MeshCore::new(radio.transport());
+    let self_info = client
+        .connect()
+        .await
+        .expect("mock radio answers app_start");
+    client
+        .get_contacts()
+        .await
+        .expect("mock radio lists contacts");
+
+    CommandContext::new(client, Display::new(json, false), Some(self_info.name))
+}
+
+/// Builds a `SEND_CONFIRMED` push frame.
+#[must_use]
+pub fn ack_frame(code: u32, round_trip_ms: u32) -> Vec<u8> {
+    let mut frame = vec![PUSH_SEND_CONFIRMED];
+    frame.extend_from_slice(&code.to_le_bytes());
+    frame.extend_from_slice(&round_trip_ms.to_le_bytes());
+    frame
+}
+
+/// Builds a `SENT` response frame.
+#[must_use]
+pub fn sent_frame(flood: bool, code: u32, timeout_ms: u32) -> Vec<u8> {
+    let mut frame = vec![RESP_SENT, u8::from(flood)];
+    frame.extend_from_slice(&code.to_le_bytes());
+    frame.extend_from_slice(&timeout_ms.to_le_bytes());
+    frame
+}
+
+/// Builds an error response frame.
+#[must_use]
+pub fn err_frame(err_code: u8) -> Vec<u8> {
+    vec![RESP_ERR, err_code]
+}
+
+/// Builds a `CONTACT_MSG_RECV_V3` frame.
+#[must_use]
+pub fn contact_message_frame(prefix: &[u8], text: &str, snr: f32, timestamp: u32) -> Vec<u8> {
+    let mut frame = vec![RESP_CONTACT_MSG_RECV_V3, snr_byte(snr), 0, 0];
+    frame.extend_from_slice(prefix);
+    frame.extend_from_slice(&[0xff, 0]);
+    frame.extend_from_slice(&timestamp.to_le_bytes());
+    frame.extend_from_slice(text.as_bytes());
+    frame
+}
+
+/// Builds a `CHANNEL_MSG_RECV_V3` frame.
+#[must_use]
+pub fn channel_message_frame(channel: u8, text: &str, snr: f32, timestamp: u32) -> Vec<u8> {
+    let mut frame = vec![
+        RESP_CHANNEL_MSG_RECV_V3,
+        snr_byte(snr),
+        0,
+        0,
+        channel,
+        0xff,
+        0,
+    ];
+    frame.extend_from_slice(&timestamp.to_le_bytes());
+    frame.extend_from_slice(text.as_bytes());
+    frame
+}
+
+fn device_info_frame() -> Vec<u8> {
+    let mut frame = vec![RESP_DEVICE_INFO, 8, 175, CHANNEL_SLOTS];
+    frame.extend_from_slice(&123_456u32.to_le_bytes());
+    frame.extend_from_slice(&padded::<12>("01-Jun-2025"));
+    frame.extend_from_slice(&padded::<40>("Mock Radio"));
+    frame.extend_from_slice(&padded::<20>("v1.7.0"));
+    frame
+}
+
+fn u32_frame(code: u8, value: u32) -> Vec<u8> {
+    let mut frame = vec![code];
+    frame.extend_from_slice(&value.to_le_bytes());
+    frame
+}
+
+fn read_u32(frame: &[u8], offset: usize) -> Option<u32> {
+    let bytes = frame.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// Encodes an SNR in quarter dB steps, as the firmware does.
+#[allow(clippy::cast_possible_truncation)]
+fn snr_byte(snr: f32) -> u8 {
+    ((snr * 4.0).round() as i8).to_le_bytes()[0]
+}
+
+/// NUL-pads (or truncates) a string to a fixed-size field.
+fn padded<const N: usize>(s: &str) -> [u8; N] {
+    let mut field = [0u8; N];
+    let len = s.len().min(N);
+    field[..len].copy_from_slice(&s.as_bytes()[..len]);
+    field
+}
@@ -1,60 +1,138 @@
 //! Command implementations.
 
 pub mod channels;
+pub mod config;
 pub mod contacts;
 pub mod device;
+pub mod dispatch;
+pub mod history;
 pub mod messaging;
+#[cfg(test)]
+pub mod mock;
 pub mod repeater;
+pub mod supervisor;
+#[cfg(test)]
+mod tests;
 
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 
 use meshcore::MeshCore;
 use meshcore::event::{Event, EventFilter, Subscription};
-use meshcore::transport::serial::SerialTransport;
-use tokio::sync::Mutex;
+use meshcore::transport::Transport;
+use tokio::sync::{Mutex, watch};
 
 use crate::config::SessionState;
 use crate::display::Display;
 use crate::error::{CliError, Result};
+use crate::history::{HistoryEntry, MessageStore};
+use crate::hooks::Hooks;
+use supervisor::LinkState;
 
 /// Command context shared between command handlers.
-pub struct CommandContext {
+///
+/// Generic over the transport so every command works the same over serial and TCP.
+pub struct CommandContext<T: Transport> {
     /// The `MeshCore` client (wrapped for interior mutability).
-    pub client: Arc<Mutex<MeshCore<SerialTransport>>>,
+    pub client: Arc<Mutex<MeshCore<T>>>,
     /// Display configuration.
     pub display: Display,
     /// Session state.
     pub state: Arc<Mutex<SessionState>>,
     /// Device name (from initial connection).
     pub device_name: Option<String>,
+    /// Local message history (disabled if `None`).
+    pub store: Option<MessageStore>,
+    /// Outgoing queue: retried messages are delivered one at a time, in order.
+    pub send_queue: Arc<Mutex<()>>,
+    /// Link state published by the connection supervisor.
+    pub link: Arc<watch::Sender<LinkState>>,
+    /// Event hooks (disabled if `None`).
+    pub hooks: Option<Arc<Hooks>>,
 }
 
-impl CommandContext {
+impl<T: Transport> CommandContext<T> {
     /// Creates a new command context.
-    pub fn new(
-        client: MeshCore<SerialTransport>,
-        display: Display,
-        device_name: Option<String>,
-    ) -> Self {
+    pub fn new(client: MeshCore<T>, display: Display, device_name: Option<String>) -> Self {
         Self {
             client: Arc::new(Mutex::new(client)),
             display,
             state: Arc::new(Mutex::new(SessionState::new())),
             device_name,
+            store: None,
+            send_queue: Arc::new(Mutex::new(())),
+            link: Arc::new(watch::channel(LinkState::Unsupervised).0),
+            hooks: None,
+        }
+    }
+
+    /// Creates a context for the same device and session with a different display.
+    #[must_use]
+    pub fn with_display(&self, display: Display) -> Self {
+        Self {
+            client: self.client.clone(),
+            display,
+            state: self.state.clone(),
+            device_name: self.device_name.clone(),
+            store: self.store.clone(),
+            send_queue: self.send_queue.clone(),
+            link: self.link.clone(),
+            hooks: self.hooks.clone(),
         }
     }
 
+    /// Sets the message store used to record history.
+    #[must_use]
+    pub fn with_store(mut self, store: Option<MessageStore>) -> Self {
+        self.store = store;
+        self
+    }
+
+    /// Sets the hooks run on incoming events.
+    #[must_use]
+    pub fn with_hooks(mut self, hooks: Option<Arc<Hooks>>) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// Starts the configured hook for an event, if any.
+    pub async fn run_hooks(&self, event: &Event) {
+        let Some(hooks) = &self.hooks else {
+            return;
+        };
+        let contacts = self.client.lock().await.contacts().await;
+        let channels = self.state.lock().await.channels.clone();
+        hooks.dispatch(event, &contacts, &channels);
+    }
+
+    /// Records an entry in the message history, if enabled.
+    pub fn record(&self, entry: &HistoryEntry) {
+        if let Some(store) = &self.store
+            && let Err(e) = store.append(entry)
+        {
+            tracing::warn!("Failed to record message history: {e}");
+        }
+    }
+
+    /// Returns the device name, following `set name` and reconnects.
+    pub async fn current_device_name(&self) -> Option<String> {
+        let state = self.state.lock().await;
+        state
+            .device_name
+            .clone()
+            .or_else(|| self.device_name.clone())
+    }
+
     /// Gets the command handler.
     pub async fn commands(
         &self,
-    ) -> impl std::ops::Deref<Target = meshcore::commands::CommandHandler<SerialTransport>> + '_
-    {
-        struct CommandsGuard<'a> {
-            guard: tokio::sync::MutexGuard<'a, MeshCore<SerialTransport>>,
+    ) -> impl std::ops::Deref<Target = meshcore::commands::CommandHandler<T>> + '_ {
+        struct CommandsGuard<'a, T: Transport> {
+            guard: tokio::sync::MutexGuard<'a, MeshCore<T>>,
         }
-        impl std::ops::Deref for CommandsGuard<'_> {
-            type Target = meshcore::commands::CommandHandler<SerialTransport>;
+        impl<T: Transport> std::ops::Deref for CommandsGuard<'_, T> {
+            type Target = meshcore::commands::CommandHandler<T>;
             fn deref(&self) -> &Self::Target {
                 self.guard.commands()
             }
@@ -94,9 +172,25 @@ impl CommandContext {
         Err(CliError::ContactNotFound(name_or_key.to_string()))
     }
 
-    /// Gets a channel by number or name.
-    pub fn get_channel_index(channel: &str) -> Result<u8> {
-        parse_channel_index(channel)
+    /// Makes a contact or a channel (`#name`, `ch:3`) the current target.
+    pub async fn select_target(&self, target: &str) -> Result<()> {
+        let channel = target
+            .strip_prefix("ch:")
+            .or_else(|| target.starts_with('#').then_some(target));
+
+        if let Some(channel) = channel {
+            let index = self.get_channel_index(channel).await?;
+            // Make sure the prompt can show the channel name
+            if !self.state.lock().await.channels.contains_key(&index) {
+                self.refresh_channels().await?;
+            }
+            self.state.lock().await.set_channel(Some(index));
+        } else {
+            let contact = self.get_contact(target).await?;
+            self.state.lock().await.set_contact(Some(contact.name));
+        }
+
+        Ok(())
     }
 
     /// Waits for an event with timeout.
@@ -133,39 +227,75 @@ pub fn current_timestamp() -> u32 {
         .unwrap_or(0)
 }
 
+/// Number of channel slots on the device.
+pub const CHANNEL_SLOTS: u8 = 8;
+
 /// Parses a channel index from a string (number or name).
-fn parse_channel_index(channel: &str) -> Result<u8> {
+///
+/// Names are matched case-insensitively against `channels` (slot → name), ignoring a
+/// leading `#`; `#3` falls back to slot 3 when no channel has that name.
+pub fn parse_channel_index(channel: &str, channels: &HashMap<u8, String>) -> Result<u8> {
     // Try parsing as number first
     if let Ok(index) = channel.parse::<u8>() {
         return Ok(index);
     }
 
-    // Otherwise, return an error (channel name lookup not implemented)
+    let name = channel.trim_start_matches('#');
+    let found = channels
+        .iter()
+        .filter(|(_, n)| n.trim_start_matches('#').eq_ignore_ascii_case(name))
+        .map(|(index, _)| *index)
+        .min();
+    if let Some(index) = found {
+        return Ok(index);
+    }
+
+    if channel.starts_with('#')
+        && let Ok(index) = name.parse::<u8>()
+    {
+        return Ok(index);
+    }
+
     Err(CliError::ChannelNotFound(channel.to_string()))
 }
 
+/// Derives the 16-byte flood scope key from a scope topic.
+#[must_use]
+pub fn scope_key(scope: &str) -> [u8; 16] {
+    use sha2::{Digest, Sha256};
+    let hash = Sha256::digest(scope.as_bytes());
+    let mut key = [0u8; 16];
+    key.copy_from_slice(&hash[..16]);
+    key
+}
+
 /// Parses a time value string into seconds.
 ///
 /// Supports suffixes: `d` (days), `h` (hours), `m` (minutes), `s` (seconds).
-/// Without suffix, the value is treated as seconds.
-#[must_use]
-pub fn parse_time_value(s: &str) -> u32 {
+/// Without suffix, the value is treated as seconds. Values that aren't numbers
+/// are 0; values too large for a `u32` are an error.
+pub fn parse_time_value(s: &str) -> Result<u32> {
     let s = s.trim();
     if s.is_empty() {
-        return 0;
+        return Ok(0);
     }
 
-    if let Some(num_str) = s.strip_suffix('d') {
-        num_str.parse::<u32>().unwrap_or(0) * 86400
+    let (num_str, unit) = if let Some(num_str) = s.strip_suffix('d') {
+        (num_str, 86400)
     } else if let Some(num_str) = s.strip_suffix('h') {
-        num_str.parse::<u32>().unwrap_or(0) * 3600
+        (num_str, 3600)
     } else if let Some(num_str) = s.strip_suffix('m') {
-        num_str.parse::<u32>().unwrap_or(0) * 60
+        (num_str, 60)
     } else if let Some(num_str) = s.strip_suffix('s') {
-        num_str.parse::<u32>().unwrap_or(0)
+        (num_str, 1)
     } else {
-        s.parse::<u32>().unwrap_or(0)
-    }
+        (s, 1)
+    };
+    num_str
+        .parse::<u32>()
+        .unwrap_or(0)
+        .checked_mul(unit)
+        .ok_or_else(|| CliError::InvalidArgument(format!("Time value too large: {s}")))
 }
 
 /// Looks up a contact name from a public key prefix.
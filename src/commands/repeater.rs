@@ -4,11 +4,15 @@ use std::time::Duration;
 
 use meshcore::event::{Event, EventFilter};
 use meshcore::protocol::PacketType;
+use meshcore::transport::Transport;
 
 use super::{CommandContext, current_timestamp};
 use crate::error::{CliError, Result};
+use crate::events::status_to_json;
+use crate::output::outln;
+use crate::telemetry;
 
-impl CommandContext {
+impl<T: Transport> CommandContext<T> {
     /// Executes the `login` command.
     pub async fn cmd_login(&self, name: &str, password: &str) -> Result<()> {
         let contact = self.get_contact(name).await?;
@@ -36,16 +40,23 @@ impl CommandContext {
 
                 match self.wait_for_event(filter, timeout).await {
                     Ok(Event::LoginSuccess) => {
+                        let supervised = self.is_supervised();
                         let mut state = self.state.lock().await;
                         state.set_logged_in(&contact.name, true);
+                        // Only a supervisor replays logins, so only then is the password kept
+                        if supervised {
+                            state
+                                .login_passwords
+                                .insert(contact.name.clone(), password.to_string());
+                        }
                         self.display.print_ok("Login success");
                     }
                     Ok(Event::LoginFailed) => {
-                        self.display.print_error("Login failed");
+                        return Err(CliError::Command("Login failed".into()));
                     }
                     Ok(_) => {}
                     Err(_) => {
-                        self.display.print_warning("Login response timeout");
+                        return Err(CliError::Timeout("login response".into()));
                     }
                 }
             }
@@ -78,6 +89,7 @@ impl CommandContext {
 
         let mut state = self.state.lock().await;
         state.set_logged_in(&contact.name, false);
+        state.login_passwords.remove(&contact.name);
 
         self.display
             .print_ok(&format!("Logged out of {}", contact.name));
@@ -108,6 +120,10 @@ impl CommandContext {
                 timeout_ms,
             } => {
                 self.display.print_msg_sent(expected_ack, timeout_ms);
+                self.state
+                    .lock()
+                    .await
+                    .add_pending_ack(expected_ack, &contact.name, &cmd_text);
             }
             Event::Error { message } => {
                 return Err(CliError::Command(message));
@@ -152,59 +168,44 @@ impl CommandContext {
                 match self.wait_for_event(filter, timeout).await {
                     Ok(Event::StatusResponse(status)) => {
                         if self.display.is_json() {
-                            self.display.print_json(&serde_json::json!({
-                                "pubkey_prefix": hex::encode(status.pubkey_prefix),
-                                "battery_mv": status.battery_mv,
-                                "tx_queue_len": status.tx_queue_len,
-                                "noise_floor": status.noise_floor,
-                                "last_rssi": status.last_rssi,
-                                "packets_received": status.packets_received,
-                                "packets_sent": status.packets_sent,
-                                "airtime_secs": status.airtime_secs,
-                                "uptime_secs": status.uptime_secs,
-                                "sent_flood": status.sent_flood,
-                                "sent_direct": status.sent_direct,
-                                "recv_flood": status.recv_flood,
-                                "recv_direct": status.recv_direct,
-                                "full_events": status.full_events,
-                                "last_snr": status.last_snr,
-                                "direct_dups": status.direct_dups,
-                                "flood_dups": status.flood_dups,
-                                "rx_airtime_secs": status.rx_airtime_secs,
-                            }));
+                            self.display.print_json(&status_to_json(&status));
                         } else {
                             let voltage = f64::from(status.battery_mv) / 1000.0;
                             let uptime_hours = status.uptime_secs / 3600;
                             let uptime_mins = (status.uptime_secs % 3600) / 60;
 
-                            println!("Status for {}:", contact.name);
-                            println!("  Battery: {voltage:.2}V");
-                            println!("  Uptime: {uptime_hours}h {uptime_mins}m");
-                            println!("  TX Queue: {}", status.tx_queue_len);
-                            println!("  Noise Floor: {} dBm", status.noise_floor);
-                            println!("  Last RSSI: {} dBm", status.last_rssi);
-                            println!("  Last SNR: {:.2} dB", status.last_snr);
-                            println!(
+                            outln!("Status for {}:", contact.name);
+                            outln!("  Battery: {voltage:.2}V");
+                            outln!("  Uptime: {uptime_hours}h {uptime_mins}m");
+                            outln!("  TX Queue: {}", status.tx_queue_len);
+                            outln!("  Noise Floor: {} dBm", status.noise_floor);
+                            outln!("  Last RSSI: {} dBm", status.last_rssi);
+                            outln!("  Last SNR: {:.2} dB", status.last_snr);
+                            outln!(
                                 "  Packets: {} sent, {} received",
-                                status.packets_sent, status.packets_received
+                                status.packets_sent,
+                                status.packets_received
                             );
-                            println!(
+                            outln!(
                                 "  Flood: {} sent, {} received",
-                                status.sent_flood, status.recv_flood
+                                status.sent_flood,
+                                status.recv_flood
                             );
-                            println!(
+                            outln!(
                                 "  Direct: {} sent, {} received",
-                                status.sent_direct, status.recv_direct
+                                status.sent_direct,
+                                status.recv_direct
                             );
-                            println!(
+                            outln!(
                                 "  Airtime: {}s TX, {}s RX",
-                                status.airtime_secs, status.rx_airtime_secs
+                                status.airtime_secs,
+                                status.rx_airtime_secs
                             );
                         }
                     }
                     Ok(_) => {}
                     Err(_) => {
-                        self.display.print_warning("Status response timeout");
+                        return Err(CliError::Timeout("status response".into()));
                     }
                 }
             }
@@ -247,8 +248,7 @@ impl CommandContext {
                         // Format: [neighbours_count: u16 LE][results_count: u16 LE][entries...]
                         // Each entry: [pubkey_prefix: 6 bytes][secs_ago: i32 LE][snr: i8]
                         if data.len() < 4 {
-                            self.display.print_error("Invalid neighbours response");
-                            return Ok(());
+                            return Err(CliError::Command("Invalid neighbours response".into()));
                         }
 
                         let neighbours_count = i16::from_le_bytes([data[0], data[1]]);
@@ -284,7 +284,26 @@ impl CommandContext {
                             neighbours.push((pubkey_prefix, secs_ago, snr));
                         }
 
-                        if self.display.is_json() {
+                        if self.display.is_tabular() {
+                            let known_contacts = self.client.lock().await.contacts().await;
+                            let records: Vec<_> = neighbours
+                                .iter()
+                                .map(|(pk, secs, snr)| {
+                                    let name = known_contacts
+                                        .values()
+                                        .find(|c| c.public_key.to_hex().starts_with(pk))
+                                        .map(|c| c.name.clone());
+                                    serde_json::json!({
+                                        "name": name,
+                                        "pubkey": pk,
+                                        "secs_ago": secs,
+                                        "snr": snr,
+                                    })
+                                })
+                                .collect();
+                            self.display
+                                .print_table(&["name", "pubkey", "secs_ago", "snr"], &records);
+                        } else if self.display.is_json() {
                             let neighbour_list: Vec<_> = neighbours
                                 .iter()
                                 .map(|(pk, secs, snr)| {
@@ -301,9 +320,11 @@ impl CommandContext {
                                 "neighbours": neighbour_list,
                             }));
                         } else {
-                            println!(
+                            outln!(
                                 "Got {} neighbours out of {} from {}:",
-                                results_count, neighbours_count, contact.name
+                                results_count,
+                                neighbours_count,
+                                contact.name
                             );
 
                             // Get known contacts for name lookup
@@ -319,13 +340,13 @@ impl CommandContext {
                                 // Format time ago
                                 let time_str = Self::format_time_ago(*secs_ago);
 
-                                println!("  {name:<20} {time_str}, {snr:.1} dB SNR");
+                                outln!("  {name:<20} {time_str}, {snr:.1} dB SNR");
                             }
                         }
                     }
                     Ok(_) => {}
                     Err(_) => {
-                        self.display.print_warning("Neighbours response timeout");
+                        return Err(CliError::Timeout("neighbours response".into()));
                     }
                 }
             }
@@ -385,19 +406,27 @@ impl CommandContext {
                     Ok(Event::BinaryResponse(data)) => {
                         // BinaryResponse format: skip(1) + tag(4) + lpp_data = 5 bytes header
                         if data.len() > 5 {
-                            let lpp_data = &data[5..];
-                            let telemetry = meshcore::types::Telemetry::parse_lpp(lpp_data);
-                            self.print_telemetry(&contact.name, &telemetry);
+                            let readings = telemetry::decode(&data[5..]);
+                            self.display.print_telemetry(
+                                &format!("Telemetry from {}", contact.name),
+                                &readings,
+                            );
                         } else {
-                            self.display.print_warning("Invalid telemetry response");
+                            return Err(CliError::Command(format!(
+                                "Invalid telemetry response ({} bytes)",
+                                data.len()
+                            )));
                         }
                     }
-                    Ok(Event::TelemetryResponse(telemetry)) => {
-                        self.print_telemetry(&contact.name, &telemetry);
+                    Ok(Event::TelemetryResponse(response)) => {
+                        self.display.print_telemetry(
+                            &format!("Telemetry from {}", contact.name),
+                            &telemetry::from_telemetry(&response),
+                        );
                     }
                     Ok(_) => {}
                     Err(_) => {
-                        self.display.print_warning("Telemetry response timeout");
+                        return Err(CliError::Timeout("telemetry response".into()));
                     }
                 }
             }
@@ -562,13 +591,13 @@ impl CommandContext {
                                 "length": data.len(),
                             }));
                         } else {
-                            println!("Binary response ({} bytes):", data.len());
-                            println!("{}", hex::encode(&data));
+                            outln!("Binary response ({} bytes):", data.len());
+                            outln!("{}", hex::encode(&data));
                         }
                     }
                     Ok(_) => {}
                     Err(_) => {
-                        self.display.print_warning("Binary response timeout");
+                        return Err(CliError::Timeout("binary response".into()));
                     }
                 }
             }
@@ -580,67 +609,47 @@ impl CommandContext {
 
         Ok(())
     }
-
-    /// Prints telemetry data in appropriate format (JSON or human-readable).
-    fn print_telemetry(&self, name: &str, telemetry: &meshcore::types::Telemetry) {
-        if self.display.is_json() {
-            let readings: Vec<_> = telemetry
-                .readings
-                .iter()
-                .map(|r| {
-                    serde_json::json!({
-                        "channel": r.channel,
-                        "type": r.lpp_type,
-                        "value": format!("{:?}", r.value),
-                    })
-                })
-                .collect();
-            self.display
-                .print_json(&serde_json::json!({"readings": readings}));
-        } else {
-            println!("Telemetry from {name}:");
-            for reading in &telemetry.readings {
-                println!("  Channel {}: {:?}", reading.channel, reading.value);
-            }
-        }
-    }
 }
 
 #[cfg(test)]
 mod tests {
+    use meshcore::transport::serial::SerialTransport;
+
     use super::CommandContext;
 
+    type Ctx = CommandContext<SerialTransport>;
+
     #[test]
     fn test_format_time_ago_seconds() {
-        assert_eq!(CommandContext::format_time_ago(0), "0s ago");
-        assert_eq!(CommandContext::format_time_ago(30), "30s ago");
-        assert_eq!(CommandContext::format_time_ago(59), "59s ago");
+        assert_eq!(Ctx::format_time_ago(0), "0s ago");
+        assert_eq!(Ctx::format_time_ago(30), "30s ago");
+        assert_eq!(Ctx::format_time_ago(59), "59s ago");
     }
 
     #[test]
     fn test_format_time_ago_minutes() {
-        assert_eq!(CommandContext::format_time_ago(60), "1m ago");
-        assert_eq!(CommandContext::format_time_ago(120), "2m ago");
-        assert_eq!(CommandContext::format_time_ago(3599), "59m ago");
+        assert_eq!(Ctx::format_time_ago(60), "1m ago");
+        assert_eq!(Ctx::format_time_ago(120), "2m ago");
+        assert_eq!(Ctx::format_time_ago(3599), "59m ago");
     }
 
     #[test]
     fn test_format_time_ago_hours() {
-        assert_eq!(CommandContext::format_time_ago(3600), "1h ago");
-        assert_eq!(CommandContext::format_time_ago(7200), "2h ago");
-        assert_eq!(CommandContext::format_time_ago(86399), "23h ago");
+        assert_eq!(Ctx::format_time_ago(3600), "1h ago");
+        assert_eq!(Ctx::format_time_ago(7200), "2h ago");
+        assert_eq!(Ctx::format_time_ago(86399), "23h ago");
     }
 
     #[test]
     fn test_format_time_ago_days() {
-        assert_eq!(CommandContext::format_time_ago(86_400), "1d ago");
-        assert_eq!(CommandContext::format_time_ago(172_800), "2d ago");
-        assert_eq!(CommandContext::format_time_ago(604_800), "7d ago");
+        assert_eq!(Ctx::format_time_ago(86_400), "1d ago");
+        assert_eq!(Ctx::format_time_ago(172_800), "2d ago");
+        assert_eq!(Ctx::format_time_ago(604_800), "7d ago");
     }
 
     #[test]
     fn test_format_time_ago_negative() {
-        assert_eq!(CommandContext::format_time_ago(-1), "unknown");
-        assert_eq!(CommandContext::format_time_ago(-100), "unknown");
+        assert_eq!(Ctx::format_time_ago(-1), "unknown");
+        assert_eq!(Ctx::format_time_ago(-100), "unknown");
     }
 }
@@ -0,0 +1,225 @@
+//! Connection supervision: reconnects after the transport is lost and restores the session.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use meshcore::MeshCore;
+use meshcore::event::{Event, Subscription};
+use meshcore::transport::Transport;
+use tokio::sync::{Mutex, watch};
+use tokio::task::JoinHandle;
+
+use super::{CommandContext, scope_key};
+use crate::error::Result;
+
+/// Delay before the first reconnect attempt (gives a rebooting radio time to come back).
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest delay between reconnect attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// State of the device link as seen by event consumers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    /// No supervisor is running; a closed subscription ends the event stream.
+    Unsupervised,
+    /// Connected; the number counts reconnects since the supervisor started.
+    Connected(u32),
+    /// The transport was lost and the supervisor is reconnecting.
+    Reconnecting,
+    /// The supervisor stopped; event streams end.
+    Closed,
+}
+
+/// Background task that reconnects to the device when the transport is lost.
+pub struct Supervisor {
+    link: Arc<watch::Sender<LinkState>>,
+    task: JoinHandle<()>,
+}
+
+impl Supervisor {
+    /// Stops supervising the connection and ends all event streams waiting for it.
+    pub fn stop(self) {
+        self.task.abort();
+        self.link.send_replace(LinkState::Closed);
+    }
+}
+
+/// Device events that survive reconnects.
+///
+/// When the subscription closes because the transport was lost, waits for the
+/// supervisor to reconnect and subscribes again.
+pub struct EventStream<T: Transport> {
+    subscription: Subscription,
+    client: Arc<Mutex<MeshCore<T>>>,
+    link: watch::Receiver<LinkState>,
+    generation: u32,
+}
+
+impl<T: Transport> EventStream<T> {
+    /// Receives the next event, or `None` once the connection is gone for good.
+    pub async fn next(&mut self) -> Option<Event> {
+        loop {
+            if let Some(event) = self.subscription.recv().await {
+                return Some(event);
+            }
+
+            let generation = self.generation;
+            let state = *self
+                .link
+                .wait_for(|state| match state {
+                    LinkState::Connected(g) => *g > generation,
+                    LinkState::Reconnecting => false,
+                    LinkState::Unsupervised | LinkState::Closed => true,
+                })
+                .await
+                .ok()?;
+
+            let LinkState::Connected(generation) = state else {
+                return None;
+            };
+            self.generation = generation;
+            self.subscription = self.client.lock().await.subscribe();
+        }
+    }
+}
+
+impl<T: Transport> CommandContext<T> {
+    /// Returns true while a supervisor is watching the connection.
+    #[must_use]
+    pub fn is_supervised(&self) -> bool {
+        !matches!(*self.link.borrow(), LinkState::Unsupervised)
+    }
+
+    /// Gets a stream of events that resumes after the supervisor reconnects.
+    pub async fn event_stream(&self) -> EventStream<T> {
+        let link = self.link.subscribe();
+        let generation = match *link.borrow() {
+            LinkState::Connected(generation) => generation,
+            _ => 0,
+        };
+
+        EventStream {
+            subscription: self.subscribe().await,
+            client: self.client.clone(),
+            link,
+            generation,
+        }
+    }
+
+    /// Starts watching the connection and reconnecting with backoff when it drops.
+    pub async fn start_supervisor(&self) -> Supervisor {
+        let ctx = self.with_display(self.display.clone());
+        let mut subscription = self.subscribe().await;
+        self.link.send_replace(LinkState::Connected(0));
+
+        let task = tokio::spawn(async move {
+            let mut generation = 0;
+            loop {
+                // The subscription closes when the transport is lost
+                while subscription.recv().await.is_some() {}
+
+                ctx.link.send_replace(LinkState::Reconnecting);
+                ctx.display
+                    .print_warning("Connection to device lost, reconnecting...");
+                ctx.reconnect_with_backoff().await;
+
+                generation += 1;
+                subscription = ctx.subscribe().await;
+                ctx.link.send_replace(LinkState::Connected(generation));
+                ctx.restore_session().await;
+            }
+        });
+
+        Supervisor {
+            link: self.link.clone(),
+            task,
+        }
+    }
+
+    /// Reconnects until it succeeds, doubling the delay after each failed attempt.
+    async fn reconnect_with_backoff(&self) {
+        let mut delay = INITIAL_BACKOFF;
+        let mut attempt = 1;
+
+        loop {
+            tokio::time::sleep(delay).await;
+
+            match self.reconnect().await {
+                Ok(name) => {
+                    self.display.print_ok(&format!("Reconnected to {name}"));
+                    self.state.lock().await.device_name = Some(name);
+                    return;
+                }
+                Err(e) => {
+                    delay = (delay * 2).min(MAX_BACKOFF);
+                    self.display.print_warning(&format!(
+                        "Reconnect attempt {attempt} failed: {e} (retrying in {}s)",
+                        delay.as_secs()
+                    ));
+                }
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Reopens the transport and reloads contacts, returning the device name.
+    ///
+    /// `connect` performs the `app_start` handshake, so the device is ready afterwards.
+    async fn reconnect(&self) -> Result<String> {
+        let mut client = self.client.lock().await;
+        if let Err(e) = client.disconnect().await {
+            tracing::debug!("Disconnect before reconnect failed: {e}");
+        }
+
+        let self_info = client.connect().await?;
+        if let Err(e) = client.get_contacts().await {
+            tracing::debug!("Failed to reload contacts: {e}");
+        }
+
+        Ok(self_info.name)
+    }
+
+    /// Re-applies the flood scope, current contact and repeater logins after a reconnect.
+    async fn restore_session(&self) {
+        let state = self.state.lock().await;
+        let scope = state.flood_scope.clone();
+        let contact = state.current_contact.clone();
+        let logins: Vec<(String, String)> = state
+            .login_passwords
+            .iter()
+            .filter(|(name, _)| state.is_logged_in(name))
+            .map(|(name, password)| (name.clone(), password.clone()))
+            .collect();
+        drop(state);
+
+        if let Some(scope) = scope {
+            match self
+                .commands()
+                .await
+                .set_flood_scope(&scope_key(&scope))
+                .await
+            {
+                Ok(_) => self.display.print_ok(&format!("Restored scope {scope}")),
+                Err(e) => self
+                    .display
+                    .print_warning(&format!("Failed to restore scope {scope}: {e}")),
+            }
+        }
+
+        if let Some(contact) = contact
+            && self.get_contact(&contact).await.is_err()
+        {
+            self.display
+                .print_warning(&format!("Current contact {contact} is no longer known"));
+        }
+
+        for (name, password) in logins {
+            self.display.print_ok(&format!("Logging back into {name}"));
+            if let Err(e) = self.cmd_login(&name, &password).await {
+                self.display
+                    .print_warning(&format!("Failed to log back into {name}: {e}"));
+            }
+        }
+    }
+}
@@ -0,0 +1,581 @@
+//! End-to-end tests of the command handlers against the mock companion radio.
+
+use serde_json::Value;
+
+use super::mock::{self, ADV_TYPE_CHAT, ADV_TYPE_REPEATER, MockRadio};
+use crate::error::CliError;
+use crate::output::capture;
+
+/// A radio with two contacts and a hashtag channel.
+fn radio() -> MockRadio {
+    MockRadio::new("Base")
+        .with_contact("Alice", 0x11, ADV_TYPE_CHAT)
+        .with_contact("Hilltop", 0x22, ADV_TYPE_REPEATER)
+        .with_channel(2, "#rust", [0x42; 16])
+}
+
+/// Parses captured JSON output (a single value).
+fn json(output: &str) -> Value {
+    serde_json::from_str(output).unwrap_or_else(|e| panic!("invalid JSON ({e}): {output}"))
+}
+
+/// Parses captured JSON output made of several pretty-printed values.
+fn json_values(output: &str) -> Vec<Value> {
+    serde_json::Deserializer::from_str(output)
+        .into_iter::<Value>()
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|e| panic!("invalid JSON ({e}): {output}"))
+}
+
+#[test]
+fn test_mock_radio_frames() {
+    let radio = radio();
+
+    let self_info = radio.handle(&[mock::CMD_APP_START, 3, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(self_info.len(), 1);
+    assert_eq!(self_info[0][0], mock::RESP_SELF_INFO);
+    assert!(self_info[0].ends_with(b"Base"));
+
+    let contacts = radio.handle(&[mock::CMD_GET_CONTACTS]);
+    assert_eq!(contacts.len(), 4);
+    assert_eq!(contacts[0], vec![mock::RESP_CONTACTS_START, 2, 0, 0, 0]);
+
+    let empty_slot = radio.handle(&[mock::CMD_GET_CHANNEL, 5]);
+    assert_eq!(&empty_slot[0][..3], &[mock::RESP_CHANNEL_INFO, 5, 0]);
+
+    assert_eq!(radio.handle(&[0x7f]), vec![mock::err_frame(1)]);
+    assert_eq!(
+        radio.received_codes(),
+        vec![
+            mock::CMD_APP_START,
+            mock::CMD_GET_CONTACTS,
+            mock::CMD_GET_CHANNEL,
+            0x7f
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_infos() {
+    let radio = radio();
+
+    let ctx = mock::context(&radio, false).await;
+    let (result, output) = capture(ctx.cmd_infos()).await;
+    result.unwrap();
+    assert!(output.contains("Name: Base"));
+    assert!(output.contains(&format!("Public Key: {}", "aa".repeat(32))));
+
+    let ctx = mock::context(&radio, true).await;
+    let (result, output) = capture(ctx.cmd_infos()).await;
+    result.unwrap();
+    let info = json(&output);
+    assert_eq!(info["name"], "Base");
+    assert_eq!(info["radio_sf"], 11);
+}
+
+#[tokio::test]
+async fn test_ver() {
+    let radio = radio();
+
+    let ctx = mock::context(&radio, false).await;
+    let (result, output) = capture(ctx.cmd_ver()).await;
+    result.unwrap();
+    assert!(output.contains("Firmware Version: 8"));
+    assert!(output.contains("Model: Mock Radio"));
+
+    let ctx = mock::context(&radio, true).await;
+    let (result, output) = capture(ctx.cmd_ver()).await;
+    result.unwrap();
+    assert_eq!(json(&output)["max_channels"], 8);
+}
+
+#[tokio::test]
+async fn test_battery() {
+    let radio = radio();
+
+    let ctx = mock::context(&radio, false).await;
+    let (result, output) = capture(ctx.cmd_battery()).await;
+    result.unwrap();
+    assert!(output.contains("Battery: 4.01V (4012 mV)"));
+    assert!(output.contains("Storage: 128 / 1024 KB"));
+
+    let ctx = mock::context(&radio, true).await;
+    let (result, output) = capture(ctx.cmd_battery()).await;
+    result.unwrap();
+    assert_eq!(json(&output)["millivolts"], 4012);
+}
+
+#[tokio::test]
+async fn test_clock_and_set_time() {
+    let radio = radio().with_time(1_700_000_000);
+    let ctx = mock::context(&radio, true).await;
+
+    let (result, output) = capture(ctx.cmd_clock(false)).await;
+    result.unwrap();
+    assert_eq!(json(&output)["time"], 1_700_000_000);
+
+    let (result, output) = capture(ctx.cmd_set_time(1_750_000_000)).await;
+    result.unwrap();
+    assert!(json(&output)["ok"].is_string());
+    assert_eq!(radio.time(), 1_750_000_000);
+
+    let (result, _) = capture(ctx.cmd_sync_time()).await;
+    result.unwrap();
+    assert!(radio.time().abs_diff(super::current_timestamp()) <= 1);
+}
+
+#[tokio::test]
+async fn test_advert() {
+    let radio = radio();
+    let ctx = mock::context(&radio, false).await;
+
+    let (result, _) = capture(ctx.cmd_advert(true)).await;
+    result.unwrap();
+    assert!(radio.last_command(mock::CMD_SEND_SELF_ADVERT).is_some());
+}
+
+#[tokio::test]
+async fn test_contacts() {
+    let radio = radio();
+
+    let ctx = mock::context(&radio, false).await;
+    let (result, output) = capture(ctx.cmd_contacts()).await;
+    result.unwrap();
+    assert!(output.contains("Alice (Node)"));
+    assert!(output.contains("Hilltop (Repeater)"));
+    assert!(output.contains("Total: 2 contacts"));
+
+    let ctx = mock::context(&radio, true).await;
+    let (result, output) = capture(ctx.cmd_contacts()).await;
+    result.unwrap();
+    let contacts = json(&output);
+    assert_eq!(contacts.as_array().map(Vec::len), Some(2));
+}
+
+#[tokio::test]
+async fn test_remove_contact_and_reset_path() {
+    let radio = radio();
+    let ctx = mock::context(&radio, false).await;
+
+    let (result, _) = capture(ctx.cmd_reset_path("Alice")).await;
+    result.unwrap();
+    let reset = radio.last_command(mock::CMD_RESET_PATH).unwrap();
+    assert_eq!(&reset[1..33], &[0x11; 32]);
+
+    let (result, _) = capture(ctx.cmd_remove_contact("Hilltop")).await;
+    result.unwrap();
+    assert_eq!(radio.contact_names(), vec!["Alice"]);
+
+    let (result, _) = capture(ctx.cmd_remove_contact("Nobody")).await;
+    assert!(matches!(result, Err(CliError::ContactNotFound(_))));
+}
+
+#[tokio::test]
+async fn test_msg_registers_pending_ack() {
+    let radio = radio();
+    let ctx = mock::context(&radio, true).await;
+
+    let message = vec!["hello".to_string(), "there".to_string()];
+    let (result, output) = capture(ctx.cmd_msg("alice", &message, false, 5)).await;
+    result.unwrap();
+    assert_eq!(json(&output)["expected_ack"], "10000001");
+
+    let sent = radio.last_command(mock::CMD_SEND_TXT_MSG).unwrap();
+    assert_eq!(&sent[7..13], &[0x11; 6]);
+    assert!(sent.ends_with(b"hello there"));
+
+    let state = ctx.state.lock().await;
+    assert_eq!(state.pending_acks[&0x1000_0001].recipient, "Alice");
+    assert_eq!(state.last_sender.as_deref(), Some("Alice"));
+}
+
+#[tokio::test]
+async fn test_msg_unknown_contact() {
+    let radio = radio();
+    let ctx = mock::context(&radio, false).await;
+
+    let (result, _) = capture(ctx.cmd_msg("Bob", &["hi".to_string()], false, 5)).await;
+    assert!(matches!(result, Err(CliError::ContactNotFound(_))));
+    assert!(radio.last_command(mock::CMD_SEND_TXT_MSG).is_none());
+}
+
+#[tokio::test]
+async fn test_msg_wait_for_ack() {
+    let radio = radio().with_auto_ack();
+
+    let ctx = mock::context(&radio, false).await;
+    let (result, output) = capture(ctx.cmd_msg("Alice", &["hi".to_string()], true, 5)).await;
+    result.unwrap();
+    assert!(output.contains("ACK from Alice for 'hi'"));
+
+    let ctx = mock::context(&radio, true).await;
+    let (result, output) = capture(ctx.cmd_msg("Alice", &["hi".to_string()], true, 5)).await;
+    result.unwrap();
+    let values = json_values(&output);
+    assert_eq!(values.len(), 2);
+    assert_eq!(values[1]["recipient"], "Alice");
+    assert_eq!(values[1]["text"], "hi");
+    assert!(ctx.state.lock().await.pending_acks.is_empty());
+}
+
+#[tokio::test]
+async fn test_msg_wait_for_own_ack() {
+    let radio = radio();
+    let ctx = mock::context(&radio, true).await;
+
+    let (result, _) = capture(ctx.cmd_msg("Alice", &["first".to_string()], false, 5)).await;
+    result.unwrap();
+
+    // The ACK for the earlier message comes first, this one's right with SENT
+    radio.script(
+        mock::CMD_SEND_TXT_MSG,
+        vec![
+            mock::sent_frame(false, 0x1000_0002, 5000),
+            mock::ack_frame(0x1000_0001, 800),
+            mock::ack_frame(0x1000_0002, 900),
+        ],
+    );
+    let (result, output) = capture(ctx.cmd_msg("Alice", &["second".to_string()], true, 2)).await;
+    result.unwrap();
+    let values = json_values(&output);
+    assert_eq!(values[1]["code"], "10000002");
+    assert_eq!(values[1]["text"], "second");
+
+    let state = ctx.state.lock().await;
+    assert!(state.pending_acks.contains_key(&0x1000_0001));
+    assert!(!state.pending_acks.contains_key(&0x1000_0002));
+    drop(state);
+
+    // Only an unrelated ACK arrives
+    radio.script(
+        mock::CMD_SEND_TXT_MSG,
+        vec![
+            mock::sent_frame(false, 0x1000_0003, 5000),
+            mock::ack_frame(0x1000_0001, 800),
+        ],
+    );
+    let (result, _) = capture(ctx.cmd_msg("Alice", &["third".to_string()], true, 1)).await;
+    assert!(matches!(result, Err(CliError::Timeout(_))));
+}
+
+#[tokio::test]
+async fn test_msg_retry_flood_after() {
+    let direct = radio().with_path("Alice", 2).with_auto_ack();
+    let ctx = mock::context(&direct, true).await;
+    let (result, _) = capture(ctx.cmd_msg_retry("Alice", &["hi".to_string()], 3, 1, 5)).await;
+    result.unwrap();
+    assert!(!direct.received_codes().contains(&mock::CMD_RESET_PATH));
+
+    // With 0 even the first attempt goes out as flood
+    let direct = radio().with_path("Alice", 2).with_auto_ack();
+    let ctx = mock::context(&direct, true).await;
+    let (result, _) = capture(ctx.cmd_msg_retry("Alice", &["hi".to_string()], 3, 0, 5)).await;
+    result.unwrap();
+    let codes = direct.received_codes();
+    let reset = codes.iter().position(|&c| c == mock::CMD_RESET_PATH);
+    let sent = codes.iter().position(|&c| c == mock::CMD_SEND_TXT_MSG);
+    assert!(reset.is_some() && reset < sent, "{codes:?}");
+}
+
+#[tokio::test]
+async fn test_wait_ack_ignores_unrelated_acks() {
+    let radio = radio();
+    let ctx = mock::context(&radio, true).await;
+
+    let (result, _) = capture(ctx.cmd_wait_ack(1)).await;
+    assert!(matches!(result, Err(CliError::Command(_))));
+
+    let (result, _) = capture(ctx.cmd_msg("Alice", &["hi".to_string()], false, 5)).await;
+    result.unwrap();
+
+    let acks = radio.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        acks.ack(0xdead_beef);
+        acks.ack(0x1000_0001);
+    });
+
+    let (result, output) = capture(ctx.cmd_wait_ack(2)).await;
+    result.unwrap();
+    assert_eq!(json(&output)["code"], "10000001");
+
+    let (result, _) = capture(ctx.cmd_wait_ack(1)).await;
+    assert!(matches!(result, Err(CliError::Command(_))));
+}
+
+#[tokio::test]
+async fn test_wait_ack_timeout() {
+    let radio = radio();
+    let ctx = mock::context(&radio, false).await;
+
+    let (result, _) = capture(ctx.cmd_msg("Alice", &["hi".to_string()], false, 5)).await;
+    result.unwrap();
+
+    let (result, _) = capture(ctx.cmd_wait_ack(1)).await;
+    assert!(matches!(result, Err(CliError::Timeout(_))));
+}
+
+#[tokio::test]
+async fn test_login_timeout() {
+    let radio = radio();
+    radio.script(mock::CMD_SEND_LOGIN, vec![mock::sent_frame(true, 7, 5000)]);
+    let ctx = mock::context(&radio, false).await;
+    ctx.state
+        .lock()
+        .await
+        .contact_timeouts
+        .insert("Hilltop".into(), 1);
+
+    let (result, _) = capture(ctx.cmd_login("Hilltop", "secret")).await;
+    let error = result.unwrap_err();
+    assert!(matches!(error, CliError::Timeout(_)));
+    assert_eq!(error.exit_code(), 6);
+}
+
+#[tokio::test]
+async fn test_chan() {
+    let radio = radio();
+
+    let ctx = mock::context(&radio, false).await;
+    let (result, output) = capture(ctx.cmd_chan(2, &["hi".to_string(), "all".to_string()])).await;
+    result.unwrap();
+    assert!(output.contains("channel message sent"));
+    let sent = radio.last_command(mock::CMD_SEND_CHANNEL_TXT_MSG).unwrap();
+    assert_eq!(sent[2], 2);
+    assert!(sent.ends_with(b"hi all"));
+
+    let ctx = mock::context(&radio, true).await;
+    let (result, output) = capture(ctx.cmd_public(&["hello".to_string()], false)).await;
+    result.unwrap();
+    assert_eq!(json(&output)["ok"], "channel message sent");
+
+    let (result, _) = capture(ctx.cmd_chan(6, &["hi".to_string()])).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_message_length() {
+    let radio = radio().with_auto_ack();
+    let ctx = mock::context(&radio, false).await;
+    let long = vec!["x".repeat(150), "y".repeat(150)];
+
+    // "Base: " leaves 154 bytes for channel text
+    let (result, _) = capture(ctx.cmd_chan(2, &["z".repeat(155)])).await;
+    assert!(matches!(result, Err(CliError::InvalidArgument(_))));
+    let (result, _) = capture(ctx.cmd_msg("Alice", &long, false, 5)).await;
+    assert!(matches!(result, Err(CliError::InvalidArgument(_))));
+    assert!(radio.last_command(mock::CMD_SEND_TXT_MSG).is_none());
+
+    let (result, output) = capture(ctx.cmd_msg_split("Alice", &long, 5)).await;
+    result.unwrap();
+    assert_eq!(output.matches("ACK").count(), 2);
+    let sent = radio.last_command(mock::CMD_SEND_TXT_MSG).unwrap();
+    assert!(sent.ends_with(format!("(2/2) {}", "y".repeat(150)).as_bytes()));
+
+    let (result, output) = capture(ctx.cmd_chan_split(2, &long)).await;
+    result.unwrap();
+    assert!(output.contains("part 2/2"));
+
+    // The prefix follows the current name, e.g. after `set name`
+    assert_eq!(ctx.channel_text_limit().await, 154);
+    ctx.state.lock().await.device_name = Some("Basecamp".into());
+    assert_eq!(ctx.channel_text_limit().await, 150);
+}
+
+#[tokio::test]
+async fn test_get_channels() {
+    let radio = radio();
+
+    let ctx = mock::context(&radio, false).await;
+    let (result, output) = capture(ctx.cmd_get_channels()).await;
+    result.unwrap();
+    assert!(output.contains("Channel 0: Public"));
+    assert!(output.contains("Channel 2: #rust"));
+    assert!(output.contains(&"42".repeat(16)));
+    assert_eq!(output.lines().count(), 2);
+    assert_eq!(ctx.state.lock().await.channels.len(), 2);
+
+    let ctx = mock::context(&radio, true).await;
+    let (result, output) = capture(ctx.cmd_get_channels()).await;
+    result.unwrap();
+    let channels = json(&output);
+    assert_eq!(channels.as_array().map(Vec::len), Some(2));
+    assert_eq!(channels[1]["name"], "#rust");
+
+    let (result, output) = capture(ctx.cmd_get_channel("rust")).await;
+    result.unwrap();
+    assert_eq!(json(&output)["index"], 2);
+}
+
+#[tokio::test]
+async fn test_set_add_remove_channel() {
+    let radio = radio();
+    let ctx = mock::context(&radio, false).await;
+
+    let (result, _) = capture(ctx.cmd_set_channel(3, "#ops", None)).await;
+    result.unwrap();
+    let (result, output) = capture(ctx.cmd_add_channel("#mesh", None)).await;
+    result.unwrap();
+    assert!(output.contains("channel added at slot 1: '#mesh'"));
+
+    let (result, _) = capture(ctx.cmd_remove_channel("#rust")).await;
+    result.unwrap();
+
+    let channels: Vec<(u8, String)> = radio.channel_names().into_iter().collect();
+    assert_eq!(
+        channels,
+        vec![
+            (0, "Public".to_string()),
+            (1, "#mesh".to_string()),
+            (3, "#ops".to_string())
+        ]
+    );
+
+    let (result, _) = capture(ctx.cmd_remove_channel("#rust")).await;
+    assert!(matches!(result, Err(CliError::ChannelNotFound(_))));
+}
+
+#[tokio::test]
+async fn test_recv() {
+    let radio = radio();
+    radio.queue_message("Alice", "ping", 6.5);
+
+    let ctx = mock::context(&radio, false).await;
+    let (result, output) = capture(ctx.cmd_recv()).await;
+    result.unwrap();
+    assert_eq!(output, "Alice [6.50]: ping\n");
+    assert_eq!(ctx.state.lock().await.last_sender.as_deref(), Some("Alice"));
+
+    let (result, output) = capture(ctx.cmd_recv()).await;
+    result.unwrap();
+    assert_eq!(output, "No more messages\n");
+
+    let ctx = mock::context(&radio, true).await;
+    let (result, output) = capture(ctx.cmd_recv()).await;
+    result.unwrap();
+    assert_eq!(json(&output)["no_more_messages"], true);
+}
+
+#[tokio::test]
+async fn test_sync_msgs() {
+    let radio = radio();
+    radio.queue_message("Alice", "first", 5.0);
+    radio.queue_channel_message(2, "Carol: second", -2.25);
+    radio.queue_message("Hilltop", "third", 1.0);
+
+    let ctx = mock::context(&radio, false).await;
+    let (result, output) = capture(ctx.cmd_sync_msgs()).await;
+    result.unwrap();
+    assert_eq!(
+        output,
+        "Alice [5.00]: first\n#rust Carol [-2.25]: second\nHilltop [1.00]: third\n"
+    );
+
+    radio.queue_channel_message(0, "Dave: hi", 0.0);
+    let ctx = mock::context(&radio, true).await;
+    let (result, output) = capture(ctx.cmd_sync_msgs()).await;
+    result.unwrap();
+    let message = json(&output);
+    assert_eq!(message["channel_name"], "Public");
+    assert_eq!(message["sender"], "Dave");
+    assert_eq!(message["text"], "hi");
+}
+
+#[tokio::test]
+async fn test_wait_msg() {
+    let radio = radio();
+    let ctx = mock::context(&radio, true).await;
+
+    let incoming = radio.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        incoming.push(mock::contact_message_frame(
+            &[0x11; 6],
+            "are you there?",
+            3.0,
+            0,
+        ));
+    });
+
+    let (result, output) = capture(ctx.cmd_wait_msg(2)).await;
+    result.unwrap();
+    let message = json(&output);
+    assert_eq!(message["sender"], "Alice");
+    assert_eq!(message["text"], "are you there?");
+
+    let (result, _) = capture(ctx.cmd_wait_msg(1)).await;
+    assert!(matches!(result, Err(CliError::Timeout(_))));
+}
+
+#[tokio::test]
+async fn test_trywait_msg() {
+    let radio = radio();
+    let ctx = mock::context(&radio, false).await;
+
+    let incoming = radio.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        incoming.queue_channel_message(2, "Erin: anyone?", 4.0);
+    });
+
+    let (result, output) = capture(ctx.cmd_trywait_msg(2)).await;
+    result.unwrap();
+    assert_eq!(output, "#rust Erin [4.00]: anyone?\n");
+
+    let (result, output) = capture(ctx.cmd_trywait_msg(1)).await;
+    result.unwrap();
+    assert!(output.is_empty());
+}
+
+#[tokio::test]
+async fn test_device_error_is_reported() {
+    let radio = radio();
+    let ctx = mock::context(&radio, false).await;
+
+    radio.script(mock::CMD_GET_BATT_AND_STORAGE, vec![mock::err_frame(1)]);
+    let (result, _) = capture(ctx.cmd_battery()).await;
+    assert!(result.is_err());
+
+    let (result, _) = capture(ctx.cmd_battery()).await;
+    result.unwrap();
+}
+
+#[tokio::test]
+async fn test_execute_line() {
+    let radio = radio();
+    let ctx = mock::context(&radio, false).await;
+
+    let (result, output) = capture(ctx.execute_line("public hello mesh")).await;
+    result.unwrap();
+    assert!(output.contains("channel message sent"));
+    assert!(
+        radio
+            .last_command(mock::CMD_SEND_CHANNEL_TXT_MSG)
+            .unwrap()
+            .ends_with(b"hello mesh")
+    );
+
+    let (result, _) = capture(ctx.execute_line("msg Nobody hi")).await;
+    assert!(matches!(result, Err(CliError::ContactNotFound(_))));
+}
+
+#[tokio::test]
+async fn test_event_stream_survives_reconnect() {
+    let radio = radio();
+    let ctx = mock::context(&radio, false).await;
+    let supervisor = ctx.start_supervisor().await;
+    let mut events = ctx.event_stream().await;
+
+    radio.drop_link();
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+    radio.advert("Alice");
+
+    let event = tokio::time::timeout(std::time::Duration::from_secs(5), events.next())
+        .await
+        .expect("event after reconnect");
+    assert!(event.is_some());
+    // The reconnect performed a new app_start handshake
+    assert!(radio.received_codes()[1..].contains(&mock::CMD_APP_START));
+
+    supervisor.stop();
+}
@@ -4,12 +4,13 @@
 
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
 
-use crate::error::Result;
+use crate::error::{CliError, Result};
 
 /// Configuration directory name (compatible with Python CLI).
 const CONFIG_DIR: &str = "meshcore";
@@ -20,8 +21,23 @@ const HISTORY_FILE: &str = "history";
 /// Init script file name.
 const INIT_FILE: &str = "init";
 
-/// CLI configuration.
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+/// Message history directory name.
+const MESSAGES_DIR: &str = "messages";
+
+/// Daemon socket file name.
+const SOCKET_FILE: &str = "daemon.sock";
+
+/// Config file name.
+const CONFIG_FILE: &str = "config.toml";
+
+/// Profile directory name.
+const PROFILES_DIR: &str = "profiles";
+
+/// Default baud rate when neither the CLI nor the config file sets one.
+pub const DEFAULT_BAUDRATE: u32 = 115_200;
+
+/// CLI configuration, persisted in `config.toml`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     /// Default serial port.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -39,19 +55,113 @@ pub struct Config {
     #[serde(default = "default_true")]
     pub color: bool,
 
-    /// Channel echoes enabled.
-    #[serde(default)]
-    pub channel_echoes: bool,
-
-    /// Auto-update contacts.
+    /// Record messages and ACKs in the local message history.
     #[serde(default = "default_true")]
-    pub auto_update_contacts: bool,
+    pub message_history: bool,
+
+    /// Programs run on incoming events.
+    #[serde(default, skip_serializing_if = "HooksConfig::is_empty")]
+    pub hooks: HooksConfig,
 }
 
 fn default_true() -> bool {
     true
 }
 
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            default_port: None,
+            default_baudrate: None,
+            contact_timeouts: HashMap::new(),
+            color: true,
+            message_history: true,
+            hooks: HooksConfig::default(),
+        }
+    }
+}
+
+/// Hook names, in the order they are shown.
+pub const HOOK_NAMES: [&str; 5] = [
+    "on_message",
+    "on_channel",
+    "on_advert",
+    "on_ack",
+    "on_login",
+];
+
+/// External programs run on incoming events, with the event as JSON on stdin.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    /// Run for private messages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_message: Option<String>,
+    /// Run for channel messages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_channel: Option<String>,
+    /// Run for adverts and new contacts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_advert: Option<String>,
+    /// Run for ACKs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_ack: Option<String>,
+    /// Run for login results.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_login: Option<String>,
+    /// Seconds before a hook is killed.
+    pub timeout_secs: u64,
+    /// Hooks allowed to run at the same time; events beyond that are dropped.
+    pub max_concurrent: usize,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            on_message: None,
+            on_channel: None,
+            on_advert: None,
+            on_ack: None,
+            on_login: None,
+            timeout_secs: 10,
+            max_concurrent: 4,
+        }
+    }
+}
+
+impl HooksConfig {
+    /// Returns true if no hook is configured.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        HOOK_NAMES.iter().all(|name| self.get(name).is_none())
+    }
+
+    /// Returns the command of a hook by name.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&str> {
+        match name {
+            "on_message" => self.on_message.as_deref(),
+            "on_channel" => self.on_channel.as_deref(),
+            "on_advert" => self.on_advert.as_deref(),
+            "on_ack" => self.on_ack.as_deref(),
+            "on_login" => self.on_login.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Returns the command slot of a hook by name, for `config set/unset`.
+    pub fn slot_mut(&mut self, name: &str) -> Option<&mut Option<String>> {
+        match name {
+            "on_message" => Some(&mut self.on_message),
+            "on_channel" => Some(&mut self.on_channel),
+            "on_advert" => Some(&mut self.on_advert),
+            "on_ack" => Some(&mut self.on_ack),
+            "on_login" => Some(&mut self.on_login),
+            _ => None,
+        }
+    }
+}
+
 impl Config {
     /// Gets the configuration directory path.
     #[must_use]
@@ -66,6 +176,24 @@ impl Config {
         Self::config_dir().map(|p| p.join(HISTORY_FILE))
     }
 
+    /// Gets the message history directory path.
+    #[must_use]
+    pub fn messages_dir() -> Option<PathBuf> {
+        Self::config_dir().map(|p| p.join(MESSAGES_DIR))
+    }
+
+    /// Gets the default daemon socket path.
+    #[must_use]
+    pub fn socket_file() -> Option<PathBuf> {
+        Self::config_dir().map(|p| p.join(SOCKET_FILE))
+    }
+
+    /// Gets the config file path.
+    #[must_use]
+    pub fn config_file() -> Option<PathBuf> {
+        Self::config_dir().map(|p| p.join(CONFIG_FILE))
+    }
+
     /// Gets the init script path.
     #[must_use]
     pub fn init_file() -> Option<PathBuf> {
@@ -78,6 +206,62 @@ impl Config {
         Self::config_dir().map(|p| p.join(format!("{device_name}.init")))
     }
 
+    /// Gets the profile directory path.
+    #[must_use]
+    pub fn profiles_dir() -> Option<PathBuf> {
+        Self::config_dir().map(|p| p.join(PROFILES_DIR))
+    }
+
+    /// Gets the path of a profile file.
+    #[must_use]
+    pub fn profile_file(profile: &str) -> Option<PathBuf> {
+        Self::profiles_dir().map(|p| p.join(format!("{profile}.toml")))
+    }
+
+    /// Gets the path of a profile's init script.
+    #[must_use]
+    pub fn profile_init_file(profile: &str) -> Option<PathBuf> {
+        Self::profiles_dir().map(|p| p.join(format!("{profile}.init")))
+    }
+
+    /// Loads the config file, falling back to defaults if it doesn't exist.
+    pub fn load() -> Result<Self> {
+        match Self::config_file() {
+            Some(path) => Self::load_from(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Loads a config from the given path, falling back to defaults if it doesn't exist.
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(path)
+            .map_err(|e| CliError::Config(format!("{}: {e}", path.display())))?;
+        toml::from_str(&content).map_err(|e| CliError::Config(format!("{}: {e}", path.display())))
+    }
+
+    /// Saves the config file, creating the config directory if needed.
+    pub fn save(&self) -> Result<()> {
+        let path = Self::config_file()
+            .ok_or_else(|| CliError::Config("No config directory available".into()))?;
+        self.save_to(&path)
+    }
+
+    /// Saves the config to the given path.
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let content = toml::to_string_pretty(self)
+            .map_err(|e| CliError::Config(format!("Failed to serialize config: {e}")))?;
+        fs::write(path, content)?;
+        Ok(())
+    }
+
     /// Reads script lines from a file path.
     fn read_script_from_path(path: Option<PathBuf>) -> Result<Vec<String>> {
         let path = match path {
@@ -103,6 +287,82 @@ impl Config {
     pub fn read_device_init_script(device_name: &str) -> Result<Vec<String>> {
         Self::read_script_from_path(Self::device_init_file(device_name))
     }
+
+    /// Reads profile-specific init script lines.
+    pub fn read_profile_init_script(profile: &str) -> Result<Vec<String>> {
+        Self::read_script_from_path(Self::profile_init_file(profile))
+    }
+}
+
+/// Named device profile, stored in `profiles/<name>.toml`.
+///
+/// Profile settings take precedence over `config.toml`, CLI flags over both.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    /// Profile name (the file stem).
+    #[serde(skip)]
+    pub name: String,
+
+    /// Serial port of the radio.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<String>,
+
+    /// TCP address of the radio.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tcp: Option<String>,
+
+    /// Baud rate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baudrate: Option<u32>,
+
+    /// Flood scope applied after connecting.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+
+    /// Contact-specific timeouts, added to those from `config.toml`.
+    #[serde(default)]
+    pub contact_timeouts: HashMap<String, u64>,
+}
+
+impl Profile {
+    /// Loads a profile by name from the profile directory.
+    pub fn load(name: &str) -> Result<Self> {
+        // The name becomes a file name, so it must not leave the profile directory
+        if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+            return Err(CliError::InvalidArgument(format!(
+                "Invalid profile name: {name}"
+            )));
+        }
+        let path = Config::profile_file(name)
+            .ok_or_else(|| CliError::Config("No config directory available".into()))?;
+        Self::load_from(name, &path)
+    }
+
+    /// Loads a profile from the given path, failing if it doesn't exist.
+    pub fn load_from(name: &str, path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Err(CliError::Config(format!(
+                "Unknown profile '{name}' ({} not found)",
+                path.display()
+            )));
+        }
+
+        let content = fs::read_to_string(path)
+            .map_err(|e| CliError::Config(format!("{}: {e}", path.display())))?;
+        let profile: Self = toml::from_str(&content)
+            .map_err(|e| CliError::Config(format!("{}: {e}", path.display())))?;
+        if profile.port.is_some() && profile.tcp.is_some() {
+            return Err(CliError::Config(format!(
+                "{}: set either port or tcp, not both",
+                path.display()
+            )));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            ..profile
+        })
+    }
 }
 
 /// Runtime state that persists during a session.
@@ -117,12 +377,24 @@ pub struct SessionState {
     /// Previous contact (for `to ..`).
     pub previous_contact: Option<String>,
 
+    /// Current target channel (for `to #chan`), used instead of `current_contact`.
+    pub current_channel: Option<u8>,
+
+    /// Previous channel (for `to ..`).
+    pub previous_channel: Option<u8>,
+
     /// Last message sender (for `to !`).
     pub last_sender: Option<String>,
 
     /// Logged-in repeaters.
     pub logged_in: HashMap<String, bool>,
 
+    /// Passwords of logged-in repeaters, used to log back in after a reconnect.
+    ///
+    /// They are held in plaintext in memory for the session, only while a supervisor
+    /// can reconnect (never with `--no-reconnect`), and dropped on `logout`.
+    pub login_passwords: HashMap<String, String>,
+
     /// Pending contacts (for manual contact adding).
     pub pending_contacts: HashMap<String, PendingContact>,
 
@@ -131,6 +403,28 @@ pub struct SessionState {
 
     /// Contact-specific timeouts (overrides config).
     pub contact_timeouts: HashMap<String, u64>,
+
+    /// Channel names by slot, read from the device for name lookup.
+    pub channels: HashMap<u8, String>,
+
+    /// Sent messages awaiting an ACK, keyed by expected ACK code.
+    pub pending_acks: HashMap<u32, PendingAck>,
+}
+
+/// How long a sent message is remembered while waiting for its ACK.
+const PENDING_ACK_TTL: Duration = Duration::from_secs(600);
+
+/// A sent message waiting for its ACK.
+#[derive(Debug, Clone)]
+pub struct PendingAck {
+    /// Recipient name.
+    pub recipient: String,
+
+    /// Message text.
+    pub text: String,
+
+    /// When the message was sent.
+    pub sent_at: Instant,
 }
 
 /// A pending contact waiting for manual approval.
@@ -155,15 +449,34 @@ impl SessionState {
 
     /// Sets the current contact (updates previous).
     pub fn set_contact(&mut self, contact: Option<String>) {
-        if self.current_contact != contact {
+        self.set_target(contact, None);
+    }
+
+    /// Sets the current channel (updates previous).
+    pub fn set_channel(&mut self, channel: Option<u8>) {
+        self.set_target(None, channel);
+    }
+
+    /// Replaces the current contact and channel, remembering them as previous.
+    fn set_target(&mut self, contact: Option<String>, channel: Option<u8>) {
+        if self.current_contact != contact || self.current_channel != channel {
             self.previous_contact = self.current_contact.take();
+            self.previous_channel = self.current_channel.take();
             self.current_contact = contact;
+            self.current_channel = channel;
         }
     }
 
-    /// Swaps current and previous contact (for `to ..`).
+    /// Swaps current and previous target (for `to ..`).
     pub fn swap_contacts(&mut self) {
         std::mem::swap(&mut self.current_contact, &mut self.previous_contact);
+        std::mem::swap(&mut self.current_channel, &mut self.previous_channel);
+    }
+
+    /// Returns a display label for a channel, e.g. `#rust` or `#3` if its name is unknown.
+    #[must_use]
+    pub fn channel_label(&self, index: u8) -> String {
+        crate::events::channel_label(index, self.channels.get(&index).map(String::as_str))
     }
 
     /// Checks if logged into a repeater.
@@ -207,6 +520,25 @@ impl SessionState {
         self.pending_contacts.clear();
     }
 
+    /// Remembers a sent message until its ACK arrives, dropping stale entries.
+    pub fn add_pending_ack(&mut self, code: u32, recipient: &str, text: &str) {
+        self.pending_acks
+            .retain(|_, pending| pending.sent_at.elapsed() < PENDING_ACK_TTL);
+        self.pending_acks.insert(
+            code,
+            PendingAck {
+                recipient: recipient.to_string(),
+                text: text.to_string(),
+                sent_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Removes and returns the sent message an ACK code belongs to.
+    pub fn take_pending_ack(&mut self, code: u32) -> Option<PendingAck> {
+        self.pending_acks.remove(&code)
+    }
+
     /// Gets timeout for a contact.
     #[must_use]
     pub fn get_timeout(&self, contact: &str, default: u64) -> u64 {
@@ -236,6 +568,94 @@ mod tests {
         assert!(path.unwrap().to_string_lossy().ends_with("history"));
     }
 
+    #[test]
+    fn test_config_file() {
+        let path = Config::config_file();
+        assert!(path.is_some());
+        assert!(path.unwrap().to_string_lossy().ends_with("config.toml"));
+    }
+
+    #[test]
+    fn test_config_defaults() {
+        let config = Config::default();
+        assert!(config.color);
+        assert!(config.message_history);
+        assert!(config.default_port.is_none());
+
+        let parsed: Config = toml::from_str("").unwrap();
+        assert!(parsed.color);
+
+        // Settings that are no longer used don't break older files
+        let parsed: Config = toml::from_str("channel_echoes = true\ncolor = false\n").unwrap();
+        assert!(!parsed.color);
+    }
+
+    #[test]
+    fn test_hooks_config() {
+        let parsed: Config =
+            toml::from_str("[hooks]\non_message = \"notify-send meshcore\"\ntimeout_secs = 3\n")
+                .unwrap();
+        assert_eq!(parsed.hooks.get("on_message"), Some("notify-send meshcore"));
+        assert_eq!(parsed.hooks.get("on_ack"), None);
+        assert_eq!(parsed.hooks.timeout_secs, 3);
+        assert_eq!(parsed.hooks.max_concurrent, 4);
+        assert!(!parsed.hooks.is_empty());
+
+        let serialized = toml::to_string(&Config::default()).unwrap();
+        assert!(!serialized.contains("hooks"));
+    }
+
+    #[test]
+    fn test_config_load_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::load_from(&dir.path().join("config.toml")).unwrap();
+        assert!(config.default_port.is_none());
+        assert!(config.contact_timeouts.is_empty());
+    }
+
+    #[test]
+    fn test_config_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("meshcore").join("config.toml");
+
+        let mut config = Config {
+            default_port: Some("/dev/ttyUSB0".into()),
+            default_baudrate: Some(57_600),
+            color: false,
+            ..Config::default()
+        };
+        config.contact_timeouts.insert("Repeater1".into(), 60);
+        config.save_to(&path).unwrap();
+
+        let loaded = Config::load_from(&path).unwrap();
+        assert_eq!(loaded.default_port.as_deref(), Some("/dev/ttyUSB0"));
+        assert_eq!(loaded.default_baudrate, Some(57_600));
+        assert!(!loaded.color);
+        assert_eq!(loaded.contact_timeouts.get("Repeater1"), Some(&60));
+    }
+
+    #[test]
+    fn test_config_load_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "color = \"maybe\"").unwrap();
+        assert!(Config::load_from(&path).is_err());
+
+        fs::write(&path, "default_port = \"/dev/ttyUSB0").unwrap();
+        assert!(matches!(
+            Config::load_from(&path),
+            Err(CliError::Config(message)) if message.contains("config.toml")
+        ));
+
+        // Unreadable files are config errors too
+        let unreadable = dir.path().join("config.d");
+        fs::create_dir(&unreadable).unwrap();
+        assert!(matches!(
+            Config::load_from(&unreadable),
+            Err(CliError::Config(_))
+        ));
+    }
+
     #[test]
     fn test_init_file() {
         let path = Config::init_file();
@@ -250,6 +670,51 @@ mod tests {
         assert!(path.unwrap().to_string_lossy().ends_with("mydevice.init"));
     }
 
+    #[test]
+    fn test_profile_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("base.toml");
+        fs::write(
+            &path,
+            "port = \"/dev/ttyUSB1\"\nbaudrate = 57600\nscope = \"#europe\"\n\n[contact_timeouts]\nHilltop = 90\n",
+        )
+        .unwrap();
+
+        let profile = Profile::load_from("base", &path).unwrap();
+        assert_eq!(profile.name, "base");
+        assert_eq!(profile.port.as_deref(), Some("/dev/ttyUSB1"));
+        assert_eq!(profile.baudrate, Some(57_600));
+        assert_eq!(profile.scope.as_deref(), Some("#europe"));
+        assert_eq!(profile.contact_timeouts.get("Hilltop"), Some(&90));
+
+        fs::write(&path, "port = \"/dev/ttyUSB1\"\ntcp = \"10.0.0.2:5000\"\n").unwrap();
+        assert!(Profile::load_from("base", &path).is_err());
+        assert!(Profile::load_from("mobile", &dir.path().join("mobile.toml")).is_err());
+
+        // Unreadable profiles are config errors, like the config file
+        fs::create_dir(dir.path().join("dir.toml")).unwrap();
+        assert!(matches!(
+            Profile::load_from("dir", &dir.path().join("dir.toml")),
+            Err(CliError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn test_profile_name_stays_in_profile_dir() {
+        for name in ["../x", "a/b", "a\\b", "..", ""] {
+            assert!(
+                matches!(Profile::load(name), Err(CliError::InvalidArgument(_))),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_profile_init_file() {
+        let path = Config::profile_init_file("base").unwrap();
+        assert!(path.ends_with("profiles/base.init"));
+    }
+
     #[test]
     fn test_session_state_new() {
         let state = SessionState::new();
@@ -281,6 +746,26 @@ mod tests {
         assert_eq!(state.previous_contact, Some("Bob".to_string()));
     }
 
+    #[test]
+    fn test_session_state_set_channel() {
+        let mut state = SessionState::new();
+        state.set_contact(Some("Alice".to_string()));
+        state.set_channel(Some(2));
+        assert!(state.current_contact.is_none());
+        assert_eq!(state.current_channel, Some(2));
+
+        state.swap_contacts();
+        assert_eq!(state.current_contact, Some("Alice".to_string()));
+        assert!(state.current_channel.is_none());
+        assert_eq!(state.previous_channel, Some(2));
+
+        state.channels.insert(2, "#rust".to_string());
+        state.channels.insert(0, "Public".to_string());
+        assert_eq!(state.channel_label(2), "#rust");
+        assert_eq!(state.channel_label(0), "#Public");
+        assert_eq!(state.channel_label(5), "#5");
+    }
+
     #[test]
     fn test_session_state_logged_in() {
         let mut state = SessionState::new();
@@ -302,6 +787,18 @@ mod tests {
         assert_eq!(state.get_timeout("contact1", 30), 60);
     }
 
+    #[test]
+    fn test_session_state_pending_acks() {
+        let mut state = SessionState::new();
+        state.add_pending_ack(0x1234_5678, "Bob", "hi");
+
+        assert!(state.take_pending_ack(0xdead_beef).is_none());
+        let pending = state.take_pending_ack(0x1234_5678).unwrap();
+        assert_eq!(pending.recipient, "Bob");
+        assert_eq!(pending.text, "hi");
+        assert!(state.take_pending_ack(0x1234_5678).is_none());
+    }
+
     #[test]
     fn test_session_state_pending() {
         let mut state = SessionState::new();
@@ -0,0 +1,656 @@
+//! Daemon mode: shares one device between several clients over a Unix socket.
+//!
+//! The protocol is newline-delimited JSON-RPC 2.0. Supported methods:
+//!
+//! - `execute` with `{"line": "msg Bob hi"}` or `{"command": {...}}`, plus an optional
+//!   `format` (`human`, `json`, `ndjson`, `csv` or `table`), `json` and `color`. Returns `{"output": "..."}` with everything the
+//!   command printed.
+//! - `subscribe` streams device events as `event` notifications.
+
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::path::Path;
+use std::sync::Arc;
+
+use meshcore::transport::Transport;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::cli::{self, Command};
+use crate::commands::CommandContext;
+use crate::display::{Display, OutputMode};
+use crate::error::{CliError, Result};
+use crate::events::event_to_json;
+use crate::output;
+
+/// JSON-RPC error code for invalid JSON.
+const PARSE_ERROR: i64 = -32700;
+
+/// JSON-RPC error code for unknown methods.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// JSON-RPC error code for invalid parameters.
+const INVALID_PARAMS: i64 = -32602;
+
+/// JSON-RPC error code for failed commands.
+const COMMAND_FAILED: i64 = -32000;
+
+/// A JSON-RPC request.
+#[derive(Debug, Deserialize)]
+struct Request {
+    /// Request id (absent for notifications).
+    #[serde(default)]
+    id: Option<Value>,
+    /// Method name.
+    method: String,
+    /// Method parameters.
+    #[serde(default)]
+    params: Value,
+}
+
+/// Parameters of the `execute` method.
+#[derive(Debug, Serialize, Deserialize)]
+struct ExecuteParams {
+    /// Parsed command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    command: Option<Command>,
+    /// Command line in the interactive/script syntax.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    line: Option<String>,
+    /// Output mode (overrides `json`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    format: Option<OutputMode>,
+    /// JSON output.
+    #[serde(default)]
+    json: bool,
+    /// Colored output.
+    #[serde(default)]
+    color: bool,
+}
+
+/// A JSON-RPC error object.
+#[derive(Debug, Deserialize)]
+struct RpcError {
+    message: String,
+    #[serde(default)]
+    data: Option<Value>,
+}
+
+/// A JSON-RPC response or notification received by the client.
+#[derive(Debug, Deserialize)]
+struct Message {
+    #[serde(default)]
+    id: Option<Value>,
+    #[serde(default)]
+    result: Option<Value>,
+    #[serde(default)]
+    error: Option<RpcError>,
+    #[serde(default)]
+    method: Option<String>,
+    #[serde(default)]
+    params: Option<Value>,
+}
+
+/// Builds a JSON-RPC success response.
+fn response(id: &Value, result: &Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+/// Builds a JSON-RPC error response.
+fn error_response(id: &Value, code: i64, message: &str, data: Option<Value>) -> Value {
+    let mut error = json!({ "code": code, "message": message });
+    if let Some(data) = data {
+        error["data"] = data;
+    }
+    json!({ "jsonrpc": "2.0", "id": id, "error": error })
+}
+
+/// Runs the daemon until Ctrl+C.
+pub async fn run<T: Transport>(ctx: &CommandContext<T>, socket: &Path) -> Result<()> {
+    prepare_socket(socket).await?;
+    let listener = bind_private(socket)?;
+
+    ctx.display
+        .print_ok(&format!("daemon listening on {}", socket.display()));
+
+    let shared = Arc::new(ctx.with_display(ctx.display.clone()));
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle_connection(shared.clone(), stream));
+                }
+                Err(e) => tracing::warn!("Failed to accept connection: {e}"),
+            },
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    let _ = std::fs::remove_file(socket);
+    Ok(())
+}
+
+/// Makes sure `socket` can be bound: refuses a live daemon or a non-socket file,
+/// and removes a stale socket.
+async fn prepare_socket(socket: &Path) -> Result<()> {
+    let metadata = match std::fs::symlink_metadata(socket) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(CliError::Daemon(format!(
+            "{} exists and is not a socket",
+            socket.display()
+        )));
+    }
+    if UnixStream::connect(socket).await.is_ok() {
+        return Err(CliError::Daemon(format!(
+            "Another daemon is already listening on {}",
+            socket.display()
+        )));
+    }
+    std::fs::remove_file(socket)?;
+    Ok(())
+}
+
+/// Binds `socket` so that it is never reachable by other users.
+///
+/// The socket is created in a fresh 0700 directory, restricted to 0600 and
+/// only then moved into place.
+fn bind_private(socket: &Path) -> Result<UnixListener> {
+    let bind_error =
+        |e: std::io::Error| CliError::Daemon(format!("Failed to bind {}: {e}", socket.display()));
+    let dir = socket
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    std::fs::create_dir_all(dir)?;
+
+    let private = dir.join(format!(".meshcore-daemon-{}", std::process::id()));
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&private)
+        .map_err(bind_error)?;
+    let staged = private.join("socket");
+    let bound = UnixListener::bind(&staged)
+        .and_then(|listener| {
+            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))?;
+            std::fs::rename(&staged, socket)?;
+            Ok(listener)
+        })
+        .map_err(bind_error);
+    let _ = std::fs::remove_file(&staged);
+    let _ = std::fs::remove_dir(&private);
+    bound
+}
+
+/// Serves requests from one client until it disconnects.
+async fn handle_connection<T: Transport>(ctx: Arc<CommandContext<T>>, stream: UnixStream) {
+    let (reader, mut writer) = stream.into_split();
+
+    // Responses and event notifications share the socket, so a single task writes
+    let (tx, mut rx) = mpsc::unbounded_channel::<Value>();
+    let writer_task = tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            let mut line = message.to_string();
+            line.push('\n');
+            if writer.write_all(line.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut events: Option<JoinHandle<()>> = None;
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let request: Request = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                let _ = tx.send(error_response(
+                    &Value::Null,
+                    PARSE_ERROR,
+                    &e.to_string(),
+                    None,
+                ));
+                continue;
+            }
+        };
+
+        let reply = match request.method.as_str() {
+            "execute" => execute(&ctx, request.params).await,
+            "subscribe" => {
+                if events.is_none() {
+                    events = Some(forward_events(&ctx, tx.clone()).await);
+                }
+                Ok(json!({ "subscribed": true }))
+            }
+            method => Err((METHOD_NOT_FOUND, format!("Unknown method: {method}"), None)),
+        };
+
+        // Notifications don't get a response
+        let Some(id) = request.id else {
+            continue;
+        };
+        let message = match reply {
+            Ok(result) => response(&id, &result),
+            Err((code, message, data)) => error_response(&id, code, &message, data),
+        };
+        if tx.send(message).is_err() {
+            break;
+        }
+    }
+
+    if let Some(events) = events {
+        events.abort();
+    }
+    drop(tx);
+    let _ = writer_task.await;
+}
+
+/// Result of a JSON-RPC method: the result value, or error code, message and data.
+type MethodResult = std::result::Result<Value, (i64, String, Option<Value>)>;
+
+/// Handles the `execute` method.
+async fn execute<T: Transport>(ctx: &CommandContext<T>, params: Value) -> MethodResult {
+    let params: ExecuteParams =
+        serde_json::from_value(params).map_err(|e| (INVALID_PARAMS, e.to_string(), None))?;
+
+    let command = match (params.command, params.line) {
+        (Some(command), _) => command,
+        (None, Some(line)) => {
+            let args = cli::split_line(&line).map_err(|e| (INVALID_PARAMS, e.to_string(), None))?;
+            match cli::parse_args(args) {
+                Ok(command) => command,
+                // Help output requested with `help <cmd>` or `<cmd> --help`
+                Err(e) if !e.use_stderr() => {
+                    return Ok(json!({ "output": e.render().to_string() }));
+                }
+                Err(e) => {
+                    let usage = e.render().to_string().trim_end().to_string();
+                    return Err((INVALID_PARAMS, usage, None));
+                }
+            }
+        }
+        (None, None) => {
+            return Err((INVALID_PARAMS, "Missing command or line".into(), None));
+        }
+    };
+
+    // Commands that need the daemon's own terminal can't be proxied
+    match command {
+        Command::Chat
+        | Command::ChatTo { .. }
+        | Command::WaitKey
+        | Command::Daemon { .. }
+        | Command::Bot { .. }
+        | Command::Mqtt { .. }
+        | Command::Serve { .. }
+        | Command::Web { .. }
+        | Command::Ircd { .. } => {
+            return Err((
+                COMMAND_FAILED,
+                "Command is not available through the daemon".into(),
+                None,
+            ));
+        }
+        Command::MsgsSubscribe => {
+            return Err((
+                COMMAND_FAILED,
+                "Use the subscribe method to stream messages".into(),
+                None,
+            ));
+        }
+        _ => {}
+    }
+
+    let mode = params.format.unwrap_or(if params.json {
+        OutputMode::Json
+    } else {
+        OutputMode::Human
+    });
+    let request_ctx = ctx.with_display(
+        Display::new(false, params.color)
+            .with_mode(mode)
+            .with_profile(ctx.display.profile.clone()),
+    );
+    let (result, output) = output::capture(request_ctx.execute(command)).await;
+
+    match result {
+        Ok(()) => Ok(json!({ "output": output })),
+        Err(e) => Err((
+            COMMAND_FAILED,
+            e.to_string(),
+            Some(json!({ "output": output, "kind": e.kind() })),
+        )),
+    }
+}
+
+/// Starts forwarding device events to a subscribed client.
+async fn forward_events<T: Transport>(
+    ctx: &CommandContext<T>,
+    tx: mpsc::UnboundedSender<Value>,
+) -> JoinHandle<()> {
+    let mut events = ctx.event_stream().await;
+    let client = ctx.client.clone();
+    let state = ctx.state.clone();
+
+    tokio::spawn(async move {
+        while let Some(event) = events.next().await {
+            let contacts = client.lock().await.contacts().await;
+            let channels = state.lock().await.channels.clone();
+            let Some(params) = event_to_json(&event, &contacts, &channels) else {
+                continue;
+            };
+            let notification = json!({ "jsonrpc": "2.0", "method": "event", "params": params });
+            if tx.send(notification).is_err() {
+                break;
+            }
+        }
+    })
+}
+
+/// Connects to a daemon socket.
+async fn connect(socket: &Path) -> Result<UnixStream> {
+    UnixStream::connect(socket).await.map_err(|e| {
+        CliError::Daemon(format!(
+            "Failed to connect to daemon at {}: {e}",
+            socket.display()
+        ))
+    })
+}
+
+/// Sends a request on a daemon connection.
+async fn send_request(stream: &mut UnixStream, method: &str, params: &Value) -> Result<()> {
+    let mut line =
+        json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }).to_string();
+    line.push('\n');
+    stream
+        .write_all(line.as_bytes())
+        .await
+        .map_err(|e| CliError::Daemon(format!("Failed to send request: {e}")))
+}
+
+/// Executes a command through a running daemon and prints its output.
+pub async fn execute_remote(socket: &Path, command: Command, display: &Display) -> Result<()> {
+    if matches!(command, Command::MsgsSubscribe) {
+        return subscribe_remote(socket, display).await;
+    }
+
+    let mut stream = connect(socket).await?;
+    let params = ExecuteParams {
+        command: Some(command),
+        line: None,
+        format: Some(display.mode),
+        json: display.is_json(),
+        color: display.color,
+    };
+    send_request(&mut stream, "execute", &serde_json::to_value(params)?).await?;
+
+    let mut lines = BufReader::new(stream).lines();
+    while let Some(line) = lines.next_line().await? {
+        let message: Message = serde_json::from_str(&line)?;
+        if message.id.is_none() {
+            continue;
+        }
+
+        if let Some(error) = message.error {
+            let data = error.data.unwrap_or_default();
+            if let Some(output) = data.get("output").and_then(Value::as_str) {
+                print!("{output}");
+            }
+            let kind = data
+                .get("kind")
+                .and_then(Value::as_str)
+                .unwrap_or("command");
+            return Err(CliError::Remote {
+                kind: kind.to_string(),
+                message: error.message,
+            });
+        }
+
+        if let Some(output) = message
+            .result
+            .as_ref()
+            .and_then(|r| r.get("output"))
+            .and_then(Value::as_str)
+        {
+            print!("{output}");
+        }
+        return Ok(());
+    }
+
+    Err(CliError::Daemon("Daemon closed the connection".into()))
+}
+
+/// Streams messages from a running daemon until Ctrl+C.
+async fn subscribe_remote(socket: &Path, display: &Display) -> Result<()> {
+    let mut stream = connect(socket).await?;
+    send_request(&mut stream, "subscribe", &Value::Null).await?;
+
+    println!("Subscribed to messages. Press Ctrl+C to stop.");
+
+    let mut lines = BufReader::new(stream).lines();
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let Some(line) = line? else {
+                    return Err(CliError::Daemon("Daemon closed the connection".into()));
+                };
+                let message: Message = serde_json::from_str(&line)?;
+                if message.method.as_deref() == Some("event")
+                    && let Some(event) = message.params
+                {
+                    print_event(display, &event);
+                }
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints a streamed event the same way `msgs_subscribe` does locally.
+fn print_event(display: &Display, event: &Value) {
+    let text = |key: &str| event.get(key).and_then(Value::as_str).unwrap_or_default();
+    #[allow(clippy::cast_possible_truncation)]
+    let snr = event.get("snr").and_then(Value::as_f64).map(|s| s as f32);
+
+    match text("type") {
+        "contact_message" => display.print_message(
+            text("sender"),
+            text("text"),
+            event.get("is_command").and_then(Value::as_bool) == Some(true),
+            snr,
+            None,
+        ),
+        "channel_message" => {
+            let channel = event
+                .get("channel_index")
+                .and_then(Value::as_u64)
+                .and_then(|c| u8::try_from(c).ok())
+                .unwrap_or_default();
+            display.print_channel_message(
+                channel,
+                event.get("channel_name").and_then(Value::as_str),
+                event.get("sender").and_then(Value::as_str),
+                text("text"),
+                snr,
+            );
+        }
+        "ack" => display.print_ack(
+            u32::from_str_radix(text("code"), 16).unwrap_or_default(),
+            None,
+        ),
+        "login_success" => display.print_ok("Login success"),
+        "login_failed" => display.print_error("Login failed"),
+        _ if display.is_json() => display.print_json(event),
+        "advert" => println!("Advertisement from: {}", text("public_key")),
+        "new_contact" => println!("New contact: {} ({})", text("name"), text("public_key")),
+        "messages_waiting" => println!("Messages waiting on device"),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::mock::{self, MockRadio};
+
+    /// Sends a request line and reads the response line.
+    async fn call(
+        writer: &mut tokio::net::unix::OwnedWriteHalf,
+        lines: &mut tokio::io::Lines<BufReader<tokio::net::unix::OwnedReadHalf>>,
+        request: &str,
+    ) -> Value {
+        writer
+            .write_all(format!("{request}\n").as_bytes())
+            .await
+            .unwrap();
+        let line = lines.next_line().await.unwrap().expect("a response");
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_execute_over_socket() {
+        let radio = MockRadio::new("Base");
+        let ctx = Arc::new(mock::context(&radio, false).await);
+        let (client, server) = UnixStream::pair().unwrap();
+        tokio::spawn(handle_connection(ctx, server));
+
+        let (reader, mut writer) = client.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        let reply = call(
+            &mut writer,
+            &mut lines,
+            r#"{"jsonrpc":"2.0","id":1,"method":"execute","params":{"line":"infos","json":true}}"#,
+        )
+        .await;
+        assert_eq!(reply["id"], 1);
+        let info: Value =
+            serde_json::from_str(reply["result"]["output"].as_str().unwrap()).unwrap();
+        assert_eq!(info["name"], "Base");
+
+        let reply = call(
+            &mut writer,
+            &mut lines,
+            r#"{"jsonrpc":"2.0","id":5,"method":"execute","params":{"line":"get_channels","format":"csv"}}"#,
+        )
+        .await;
+        let output = reply["result"]["output"].as_str().unwrap();
+        assert!(output.starts_with("index,name,secret\n"), "{output}");
+
+        let reply = call(
+            &mut writer,
+            &mut lines,
+            r#"{"jsonrpc":"2.0","id":2,"method":"execute","params":{"line":"msg Nobody hi"}}"#,
+        )
+        .await;
+        assert_eq!(reply["error"]["code"], COMMAND_FAILED);
+        assert_eq!(reply["error"]["data"]["kind"], "contact_not_found");
+
+        let reply = call(
+            &mut writer,
+            &mut lines,
+            r#"{"jsonrpc":"2.0","id":3,"method":"execute","params":{"line":"chat"}}"#,
+        )
+        .await;
+        assert_eq!(reply["error"]["code"], COMMAND_FAILED);
+
+        let reply = call(
+            &mut writer,
+            &mut lines,
+            r#"{"jsonrpc":"2.0","id":4,"method":"reboot_now"}"#,
+        )
+        .await;
+        assert_eq!(reply["error"]["code"], METHOD_NOT_FOUND);
+
+        let reply = call(&mut writer, &mut lines, "{not json").await;
+        assert_eq!(reply["id"], Value::Null);
+        assert_eq!(reply["error"]["code"], PARSE_ERROR);
+    }
+
+    #[test]
+    fn test_execute_params_format() {
+        let params = ExecuteParams {
+            command: None,
+            line: Some("contacts".into()),
+            format: Some(OutputMode::Table),
+            json: false,
+            color: false,
+        };
+        assert_eq!(serde_json::to_value(&params).unwrap()["format"], "table");
+
+        // Older clients only send the json flag
+        let params: ExecuteParams =
+            serde_json::from_value(json!({ "line": "contacts", "json": true })).unwrap();
+        assert_eq!(params.format, None);
+        assert!(params.json);
+    }
+
+    #[tokio::test]
+    async fn test_run_refuses_live_daemon() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("daemon.sock");
+        let _live = UnixListener::bind(&socket).unwrap();
+
+        let ctx = mock::context(&MockRadio::new("Base"), false).await;
+        let result = run(&ctx, &socket).await;
+        assert!(matches!(result, Err(CliError::Daemon(message)) if message.contains("already")));
+        assert!(socket.exists());
+    }
+
+    #[tokio::test]
+    async fn test_prepare_socket() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let socket = dir.path().join("live.sock");
+        let _live = UnixListener::bind(&socket).unwrap();
+        assert!(matches!(
+            prepare_socket(&socket).await,
+            Err(CliError::Daemon(_))
+        ));
+
+        let stale = dir.path().join("stale.sock");
+        drop(std::os::unix::net::UnixListener::bind(&stale).unwrap());
+        prepare_socket(&stale).await.unwrap();
+        assert!(!stale.exists());
+
+        let notes = dir.path().join("notes.txt");
+        std::fs::write(&notes, "keep me").unwrap();
+        assert!(matches!(
+            prepare_socket(&notes).await,
+            Err(CliError::Daemon(message)) if message.contains("not a socket")
+        ));
+        assert_eq!(std::fs::read_to_string(&notes).unwrap(), "keep me");
+
+        prepare_socket(&dir.path().join("missing.sock"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bind_private() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("daemon.sock");
+
+        let listener = bind_private(&socket).unwrap();
+        let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // The staging directory is gone
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let (accepted, connected) = tokio::join!(listener.accept(), UnixStream::connect(&socket));
+        accepted.unwrap();
+        connected.unwrap();
+    }
+}
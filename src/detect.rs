@@ -0,0 +1,216 @@
+//! Serial port auto-detection.
+//!
+//! Probes serial ports with an `app_start` handshake to find companion radios, so
+//! devices can be selected by name instead of by tty path.
+
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::time::Duration;
+
+use meshcore::MeshCore;
+use meshcore::transport::serial::SerialConfig;
+use tokio::task::JoinSet;
+
+use crate::error::{CliError, Result};
+
+/// Port value (`-s auto`) that requests auto-detection.
+pub const AUTO_PORT: &str = "auto";
+
+/// How long a port gets to answer the handshake.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A companion radio found on a serial port.
+#[derive(Debug, Clone)]
+pub struct DetectedDevice {
+    /// Serial port path.
+    pub port: String,
+    /// Device name from `SelfInfo`.
+    pub name: String,
+    /// Device public key (hex).
+    pub public_key: String,
+}
+
+/// Probes all serial ports in parallel, returning the radios that answered, sorted by port.
+pub async fn probe_ports(baudrate: u32) -> Result<Vec<DetectedDevice>> {
+    let ports = meshcore::transport::serial::list_ports()
+        .map_err(|e| CliError::Serial(format!("Failed to list ports: {e}")))?;
+
+    let mut probes = JoinSet::new();
+    for port in ports {
+        probes.spawn(probe_port(port, baudrate));
+    }
+
+    let mut devices = Vec::new();
+    while let Some(result) = probes.join_next().await {
+        if let Ok(Some(device)) = result {
+            devices.push(device);
+        }
+    }
+    devices.sort_by(|a, b| a.port.cmp(&b.port));
+
+    Ok(devices)
+}
+
+/// Opens a port and performs the handshake, returning the radio's identity.
+async fn probe_port(port: String, baudrate: u32) -> Option<DetectedDevice> {
+    let mut client = MeshCore::with_serial_config(SerialConfig::new(&port).baud_rate(baudrate));
+
+    let self_info = match tokio::time::timeout(PROBE_TIMEOUT, client.connect()).await {
+        Ok(Ok(self_info)) => self_info,
+        Ok(Err(e)) => {
+            tracing::debug!("No MeshCore device on {port}: {e}");
+            return None;
+        }
+        Err(_) => {
+            tracing::debug!("No answer from {port}");
+            return None;
+        }
+    };
+    let _ = client.disconnect().await;
+
+    Some(DetectedDevice {
+        port,
+        name: self_info.name,
+        public_key: self_info.public_key.to_hex(),
+    })
+}
+
+/// Outcome of choosing among the detected radios.
+#[derive(Debug)]
+enum Selection {
+    /// The radio to use.
+    Device(DetectedDevice),
+    /// Several radios the user has to pick from.
+    Choose(Vec<DetectedDevice>),
+}
+
+/// Finds the radio to connect to.
+///
+/// With a name, the device with that name (case-insensitive) is used. Otherwise a single
+/// radio is used directly, and with several the user picks one if `interactive` is set.
+pub async fn find_device(
+    baudrate: u32,
+    name: Option<&str>,
+    interactive: bool,
+) -> Result<DetectedDevice> {
+    let devices = probe_ports(baudrate).await?;
+    match select_device(devices, name, interactive && io::stdin().is_terminal())? {
+        Selection::Device(device) => Ok(device),
+        Selection::Choose(devices) => choose_device(devices),
+    }
+}
+
+/// Picks a radio among the detected ones, see [`find_device`].
+fn select_device(
+    mut devices: Vec<DetectedDevice>,
+    name: Option<&str>,
+    can_choose: bool,
+) -> Result<Selection> {
+    if let Some(name) = name {
+        return devices
+            .into_iter()
+            .find(|d| d.name.eq_ignore_ascii_case(name))
+            .map(Selection::Device)
+            .ok_or_else(|| CliError::Serial(format!("No device named '{name}' found")));
+    }
+
+    match devices.len() {
+        0 => Err(CliError::Serial(
+            "No MeshCore device found. Use -s <port> or -t <host:port>".into(),
+        )),
+        1 => Ok(Selection::Device(devices.swap_remove(0))),
+        _ if can_choose => Ok(Selection::Choose(devices)),
+        _ => Err(CliError::Serial(format!(
+            "Several devices found ({}), use --device-name to pick one",
+            describe_devices(&devices)
+        ))),
+    }
+}
+
+/// Asks the user to pick one of several devices.
+fn choose_device(mut devices: Vec<DetectedDevice>) -> Result<DetectedDevice> {
+    println!("Several MeshCore devices found:");
+    for (i, device) in devices.iter().enumerate() {
+        println!("  {}) {} ({})", i + 1, device.name, device.port);
+    }
+
+    loop {
+        print!("Select device [1-{}]: ", devices.len());
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line)? == 0 {
+            return Err(CliError::Serial("No device selected".into()));
+        }
+
+        match line.trim().parse::<usize>() {
+            Ok(n) if (1..=devices.len()).contains(&n) => return Ok(devices.swap_remove(n - 1)),
+            _ => println!("Invalid selection"),
+        }
+    }
+}
+
+/// Formats devices as `Name (port), ...`.
+fn describe_devices(devices: &[DetectedDevice]) -> String {
+    devices
+        .iter()
+        .map(|d| format!("{} ({})", d.name, d.port))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(port: &str, name: &str) -> DetectedDevice {
+        DetectedDevice {
+            port: port.into(),
+            name: name.into(),
+            public_key: "aa".repeat(32),
+        }
+    }
+
+    fn devices() -> Vec<DetectedDevice> {
+        vec![
+            device("/dev/ttyACM0", "Base"),
+            device("/dev/ttyUSB0", "Rover"),
+        ]
+    }
+
+    #[test]
+    fn test_select_device_by_name() {
+        let Ok(Selection::Device(found)) = select_device(devices(), Some("rover"), false) else {
+            panic!("expected Rover");
+        };
+        assert_eq!(found.port, "/dev/ttyUSB0");
+
+        assert!(matches!(
+            select_device(devices(), Some("Nowhere"), true),
+            Err(CliError::Serial(message)) if message.contains("Nowhere")
+        ));
+    }
+
+    #[test]
+    fn test_select_device_without_name() {
+        assert!(matches!(
+            select_device(Vec::new(), None, true),
+            Err(CliError::Serial(_))
+        ));
+
+        let single = vec![device("/dev/ttyACM0", "Base")];
+        assert!(matches!(
+            select_device(single, None, false),
+            Ok(Selection::Device(found)) if found.name == "Base"
+        ));
+
+        assert!(matches!(
+            select_device(devices(), None, true),
+            Ok(Selection::Choose(found)) if found.len() == 2
+        ));
+        assert!(matches!(
+            select_device(devices(), None, false),
+            Err(CliError::Serial(message))
+                if message.contains("Base (/dev/ttyACM0), Rover (/dev/ttyUSB0)")
+        ));
+    }
+}
@@ -2,24 +2,38 @@
 //!
 //! Handles JSON vs human-readable output formatting.
 
-use std::io;
+use std::time::Duration;
 
-use chrono::{DateTime, TimeZone, Utc};
-use crossterm::ExecutableCommand;
-use crossterm::style::{Color, Print, ResetColor, SetForegroundColor};
+use chrono::{DateTime, Local, TimeZone, Utc};
+use crossterm::style::{Color, Stylize};
 use meshcore::event::StatsData;
 use meshcore::types::{BatteryStatus, Channel, Contact, ContactType, DeviceInfo, SelfInfo};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 
+use crate::capture::{CaptureRecord, Direction};
+use crate::config::PendingAck;
+use crate::error::CliError;
+use crate::events::channel_label;
+use crate::history::{EntryKind, HistoryRecord};
+use crate::output::{out, outln};
+use crate::telemetry::Reading;
+
 /// Output mode for the CLI.
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum OutputMode {
     /// Human-readable output.
     #[default]
     Human,
-    /// JSON output.
+    /// JSON output (pretty-printed).
     Json,
+    /// Newline-delimited JSON: one compact object per record or event.
+    Ndjson,
+    /// CSV for list-shaped output, human-readable otherwise.
+    Csv,
+    /// Aligned table for list-shaped output, human-readable otherwise.
+    Table,
 }
 
 /// Display configuration.
@@ -29,6 +43,8 @@ pub struct Display {
     pub mode: OutputMode,
     /// Color enabled.
     pub color: bool,
+    /// Active device profile, added to JSON objects.
+    pub profile: Option<String>,
 }
 
 impl Default for Display {
@@ -36,6 +52,7 @@ impl Default for Display {
         Self {
             mode: OutputMode::Human,
             color: true,
+            profile: None,
         }
     }
 }
@@ -51,22 +68,128 @@ impl Display {
                 OutputMode::Human
             },
             color,
+            profile: None,
         }
     }
 
-    /// Returns true if JSON output is enabled.
+    /// Sets the output mode.
+    #[must_use]
+    pub const fn with_mode(mut self, mode: OutputMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the active profile shown in JSON output.
+    #[must_use]
+    pub fn with_profile(mut self, profile: Option<String>) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Returns true if JSON output (pretty or NDJSON) is enabled.
     #[must_use]
     pub const fn is_json(&self) -> bool {
-        matches!(self.mode, OutputMode::Json)
+        matches!(self.mode, OutputMode::Json | OutputMode::Ndjson)
+    }
+
+    /// Returns true if list-shaped output is printed as CSV or a table.
+    #[must_use]
+    pub const fn is_tabular(&self) -> bool {
+        matches!(self.mode, OutputMode::Csv | OutputMode::Table)
+    }
+
+    /// Returns true for any machine-oriented mode (everything but human).
+    #[must_use]
+    pub const fn is_machine(&self) -> bool {
+        !matches!(self.mode, OutputMode::Human)
     }
 
     /// Prints a JSON value. Only prints if JSON mode is enabled.
+    ///
+    /// With an active profile, objects get a `profile` field. In NDJSON mode each value
+    /// is printed on one line, and arrays are printed one element per line.
     pub fn print_json<T: Serialize>(&self, value: &T) {
-        if self.is_json() {
-            if let Ok(json) = serde_json::to_string_pretty(value) {
-                println!("{json}");
+        if !self.is_json() {
+            return;
+        }
+
+        let Ok(value) = serde_json::to_value(value) else {
+            return;
+        };
+
+        match (self.mode, value) {
+            (OutputMode::Ndjson, Value::Array(items)) => {
+                for item in items {
+                    self.write_json(item);
+                }
+            }
+            (_, value) => self.write_json(value),
+        }
+    }
+
+    /// Writes one JSON value in the current mode.
+    fn write_json(&self, mut value: Value) {
+        if let (Some(profile), Value::Object(map)) = (&self.profile, &mut value) {
+            map.insert("profile".into(), json!(profile));
+        }
+
+        let json = if self.mode == OutputMode::Ndjson {
+            serde_json::to_string(&value)
+        } else {
+            serde_json::to_string_pretty(&value)
+        };
+        if let Ok(json) = json {
+            outln!("{json}");
+        }
+    }
+
+    /// Prints a single record as JSON, or as a one-row CSV/table.
+    fn print_record(&self, columns: &[&str], record: Value) {
+        if self.is_tabular() {
+            self.print_table(columns, &[record]);
+        } else {
+            self.print_json(&record);
+        }
+    }
+
+    /// Prints records as CSV or an aligned table, with the given columns in order.
+    pub fn print_table(&self, columns: &[&str], records: &[Value]) {
+        let rows: Vec<Vec<String>> = records
+            .iter()
+            .map(|record| {
+                columns
+                    .iter()
+                    .map(|column| match &record[*column] {
+                        Value::Null => String::new(),
+                        Value::String(s) => s.clone(),
+                        value => value.to_string(),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        if self.mode == OutputMode::Csv {
+            let header: Vec<String> = columns.iter().map(|c| csv_field(c)).collect();
+            outln!("{}", header.join(","));
+            for row in &rows {
+                let fields: Vec<String> = row.iter().map(|f| csv_field(f)).collect();
+                outln!("{}", fields.join(","));
+            }
+            return;
+        }
+
+        let mut widths: Vec<usize> = columns.iter().map(|c| c.chars().count()).collect();
+        for row in &rows {
+            for (width, field) in widths.iter_mut().zip(row) {
+                *width = (*width).max(field.chars().count());
             }
         }
+
+        let header: Vec<String> = columns.iter().map(|c| c.to_uppercase()).collect();
+        outln!("{}", align_row(&header, &widths));
+        for row in &rows {
+            outln!("{}", align_row(row, &widths));
+        }
     }
 
     /// Prints a success message.
@@ -87,6 +210,15 @@ impl Display {
         }
     }
 
+    /// Prints a command failure, structured with its kind in JSON mode.
+    pub fn print_cli_error(&self, error: &CliError) {
+        if self.is_json() {
+            self.print_json(&error.to_json());
+        } else {
+            self.print_colored(&format!("Error: {error}"), Color::Red);
+        }
+    }
+
     /// Prints a warning message.
     pub fn print_warning(&self, message: &str) {
         if self.is_json() {
@@ -99,13 +231,9 @@ impl Display {
     /// Prints colored text.
     fn print_colored(&self, text: &str, color: Color) {
         if self.color {
-            let mut stdout = io::stdout();
-            let _ = stdout.execute(SetForegroundColor(color));
-            let _ = stdout.execute(Print(text));
-            let _ = stdout.execute(ResetColor);
-            println!();
+            outln!("{}", text.with(color));
         } else {
-            println!("{text}");
+            outln!("{text}");
         }
     }
 
@@ -132,25 +260,27 @@ impl Display {
                 "name": info.name,
             }));
         } else {
-            println!("Name: {}", info.name);
-            println!("Public Key: {}", info.public_key.to_hex());
-            println!("TX Power: {} / {} dBm", info.tx_power, info.max_tx_power);
+            outln!("Name: {}", info.name);
+            outln!("Public Key: {}", info.public_key.to_hex());
+            outln!("TX Power: {} / {} dBm", info.tx_power, info.max_tx_power);
             if let (Some(lat), Some(lon)) = (info.latitude, info.longitude) {
-                println!("Location: {lat:.6}, {lon:.6}");
+                outln!("Location: {lat:.6}, {lon:.6}");
             }
-            println!(
+            outln!(
                 "Radio: {:.3} MHz, {:.1} kHz BW, SF{}, CR 4/{}",
                 info.radio.frequency_mhz,
                 info.radio.bandwidth_khz,
                 info.radio.spreading_factor,
                 info.radio.coding_rate
             );
-            println!("Multi-ACKs: {}", info.multi_acks);
-            println!(
+            outln!("Multi-ACKs: {}", info.multi_acks);
+            outln!(
                 "Telemetry: base={}, loc={}, env={}",
-                info.telemetry_mode.base, info.telemetry_mode.loc, info.telemetry_mode.env
+                info.telemetry_mode.base,
+                info.telemetry_mode.loc,
+                info.telemetry_mode.env
             );
-            println!("Manual add contacts: {}", info.manual_add_contacts);
+            outln!("Manual add contacts: {}", info.manual_add_contacts);
         }
     }
 
@@ -167,24 +297,24 @@ impl Display {
                 "version": info.version,
             }));
         } else {
-            println!("Firmware Version: {}", info.firmware_version);
+            outln!("Firmware Version: {}", info.firmware_version);
             if let Some(version) = &info.version {
-                println!("Version: {version}");
+                outln!("Version: {version}");
             }
             if let Some(model) = &info.model {
-                println!("Model: {model}");
+                outln!("Model: {model}");
             }
             if let Some(build) = &info.build {
-                println!("Build: {build}");
+                outln!("Build: {build}");
             }
             if let Some(max_contacts) = info.max_contacts {
-                println!("Max Contacts: {max_contacts}");
+                outln!("Max Contacts: {max_contacts}");
             }
             if let Some(max_channels) = info.max_channels {
-                println!("Max Channels: {max_channels}");
+                outln!("Max Channels: {max_channels}");
             }
             if let Some(pin) = info.ble_pin {
-                println!("BLE PIN: {pin:06}");
+                outln!("BLE PIN: {pin:06}");
             }
         }
     }
@@ -200,14 +330,14 @@ impl Display {
             }));
         } else {
             let voltage = f64::from(battery.millivolts) / 1000.0;
-            println!("Battery: {voltage:.2}V ({} mV)", battery.millivolts);
+            outln!("Battery: {voltage:.2}V ({} mV)", battery.millivolts);
             if let (Some(used), Some(total)) = (battery.used_kb, battery.total_kb) {
                 let percent = if total > 0 {
                     (f64::from(used) / f64::from(total)) * 100.0
                 } else {
                     0.0
                 };
-                println!("Storage: {used} / {total} KB ({percent:.1}% used)");
+                outln!("Storage: {used} / {total} KB ({percent:.1}% used)");
             }
         }
     }
@@ -221,7 +351,7 @@ impl Display {
                 .timestamp_opt(i64::from(timestamp), 0)
                 .single()
                 .unwrap_or_else(Utc::now);
-            println!(
+            outln!(
                 "Current time: {} ({timestamp})",
                 dt.format("%Y-%m-%d %H:%M:%S")
             );
@@ -246,7 +376,7 @@ impl Display {
                 std::cmp::Ordering::Greater => format!("{} hops", contact.out_path_len),
             };
 
-            println!(
+            outln!(
                 "{} ({}) - {} [{}]",
                 contact.name,
                 type_str,
@@ -254,34 +384,59 @@ impl Display {
                 path_str
             );
             if let (Some(lat), Some(lon)) = (contact.latitude, contact.longitude) {
-                println!("  Location: {lat:.6}, {lon:.6}");
+                outln!("  Location: {lat:.6}, {lon:.6}");
             }
         }
     }
 
     /// Prints a contact list.
     pub fn print_contacts(&self, contacts: &[Contact]) {
-        if self.is_json() {
+        if self.is_tabular() {
+            let records: Vec<Value> = contacts.iter().map(contact_to_json).collect();
+            self.print_table(
+                &[
+                    "name",
+                    "type_name",
+                    "public_key",
+                    "path_len",
+                    "last_advert",
+                    "latitude",
+                    "longitude",
+                ],
+                &records,
+            );
+        } else if self.is_json() {
             let json_contacts: Vec<Value> = contacts.iter().map(contact_to_json).collect();
             self.print_json(&json_contacts);
         } else {
             for contact in contacts {
                 self.print_contact(contact);
             }
-            println!("\nTotal: {} contacts", contacts.len());
+            outln!("\nTotal: {} contacts", contacts.len());
+        }
+    }
+
+    /// Prints a channel list.
+    pub fn print_channels(&self, channels: &[Channel]) {
+        if self.is_tabular() {
+            let records: Vec<Value> = channels.iter().map(channel_to_json).collect();
+            self.print_table(&["index", "name", "secret"], &records);
+        } else if self.is_json() {
+            let json_channels: Vec<Value> = channels.iter().map(channel_to_json).collect();
+            self.print_json(&json_channels);
+        } else {
+            for channel in channels {
+                self.print_channel(channel);
+            }
         }
     }
 
     /// Prints a channel.
     pub fn print_channel(&self, channel: &Channel) {
         if self.is_json() {
-            self.print_json(&json!({
-                "index": channel.index,
-                "name": channel.name,
-                "secret": hex::encode(channel.secret),
-            }));
+            self.print_json(&channel_to_json(channel));
         } else {
-            println!(
+            outln!(
                 "Channel {}: {} (secret: {})",
                 channel.index,
                 channel.name,
@@ -294,70 +449,149 @@ impl Display {
     pub fn print_stats(&self, stats: &StatsData) {
         match stats {
             StatsData::Core(s) => {
-                if self.is_json() {
-                    self.print_json(&json!({
-                        "type": "core",
-                        "battery_mv": s.battery_mv,
-                        "uptime_secs": s.uptime_secs,
-                        "errors": s.errors,
-                        "queue_len": s.queue_len,
-                    }));
+                if self.is_machine() {
+                    self.print_record(
+                        &["type", "battery_mv", "uptime_secs", "errors", "queue_len"],
+                        json!({
+                            "type": "core",
+                            "battery_mv": s.battery_mv,
+                            "uptime_secs": s.uptime_secs,
+                            "errors": s.errors,
+                            "queue_len": s.queue_len,
+                        }),
+                    );
                 } else {
                     let voltage = f64::from(s.battery_mv) / 1000.0;
                     let uptime_hours = s.uptime_secs / 3600;
                     let uptime_mins = (s.uptime_secs % 3600) / 60;
-                    println!("Core Statistics:");
-                    println!("  Battery: {voltage:.2}V");
-                    println!("  Uptime: {uptime_hours}h {uptime_mins}m");
-                    println!("  Errors: {}", s.errors);
-                    println!("  Queue: {}", s.queue_len);
+                    outln!("Core Statistics:");
+                    outln!("  Battery: {voltage:.2}V");
+                    outln!("  Uptime: {uptime_hours}h {uptime_mins}m");
+                    outln!("  Errors: {}", s.errors);
+                    outln!("  Queue: {}", s.queue_len);
                 }
             }
             StatsData::Radio(s) => {
-                if self.is_json() {
-                    self.print_json(&json!({
-                        "type": "radio",
-                        "noise_floor": s.noise_floor,
-                        "rssi": s.rssi,
-                        "snr": s.snr,
-                        "tx_airtime_secs": s.tx_airtime_secs,
-                        "rx_airtime_secs": s.rx_airtime_secs,
-                    }));
+                if self.is_machine() {
+                    self.print_record(
+                        &[
+                            "type",
+                            "noise_floor",
+                            "rssi",
+                            "snr",
+                            "tx_airtime_secs",
+                            "rx_airtime_secs",
+                        ],
+                        json!({
+                            "type": "radio",
+                            "noise_floor": s.noise_floor,
+                            "rssi": s.rssi,
+                            "snr": s.snr,
+                            "tx_airtime_secs": s.tx_airtime_secs,
+                            "rx_airtime_secs": s.rx_airtime_secs,
+                        }),
+                    );
                 } else {
-                    println!("Radio Statistics:");
-                    println!("  Noise Floor: {} dBm", s.noise_floor);
-                    println!("  Last RSSI: {} dBm", s.rssi);
-                    println!("  Last SNR: {:.2} dB", s.snr);
-                    println!("  TX Airtime: {}s", s.tx_airtime_secs);
-                    println!("  RX Airtime: {}s", s.rx_airtime_secs);
+                    outln!("Radio Statistics:");
+                    outln!("  Noise Floor: {} dBm", s.noise_floor);
+                    outln!("  Last RSSI: {} dBm", s.rssi);
+                    outln!("  Last SNR: {:.2} dB", s.snr);
+                    outln!("  TX Airtime: {}s", s.tx_airtime_secs);
+                    outln!("  RX Airtime: {}s", s.rx_airtime_secs);
                 }
             }
             StatsData::Packets(s) => {
-                if self.is_json() {
-                    self.print_json(&json!({
-                        "type": "packets",
-                        "received": s.received,
-                        "sent": s.sent,
-                        "flood_tx": s.flood_tx,
-                        "direct_tx": s.direct_tx,
-                        "flood_rx": s.flood_rx,
-                        "direct_rx": s.direct_rx,
-                    }));
+                if self.is_machine() {
+                    self.print_record(
+                        &[
+                            "type",
+                            "received",
+                            "sent",
+                            "flood_tx",
+                            "direct_tx",
+                            "flood_rx",
+                            "direct_rx",
+                        ],
+                        json!({
+                            "type": "packets",
+                            "received": s.received,
+                            "sent": s.sent,
+                            "flood_tx": s.flood_tx,
+                            "direct_tx": s.direct_tx,
+                            "flood_rx": s.flood_rx,
+                            "direct_rx": s.direct_rx,
+                        }),
+                    );
                 } else {
-                    println!("Packet Statistics:");
-                    println!(
+                    outln!("Packet Statistics:");
+                    outln!(
                         "  Received: {} (flood: {}, direct: {})",
-                        s.received, s.flood_rx, s.direct_rx
+                        s.received,
+                        s.flood_rx,
+                        s.direct_rx
                     );
-                    println!(
+                    outln!(
                         "  Sent: {} (flood: {}, direct: {})",
-                        s.sent, s.flood_tx, s.direct_tx
+                        s.sent,
+                        s.flood_tx,
+                        s.direct_tx
                     );
                 }
             }
         }
     }
 
+    /// Prints telemetry readings under a title, as an aligned table in human mode.
+    pub fn print_telemetry(&self, title: &str, readings: &[Reading]) {
+        if self.is_tabular() {
+            let records: Vec<Value> = readings
+                .iter()
+                .map(|r| {
+                    json!({
+                        "channel": r.channel,
+                        "name": r.name(),
+                        "value": r.format_value(),
+                        "unit": r.unit(),
+                    })
+                })
+                .collect();
+            self.print_table(&["channel", "name", "value", "unit"], &records);
+        } else if self.is_json() {
+            let readings: Vec<Value> = readings.iter().map(Reading::to_json).collect();
+            self.print_json(&json!({ "readings": readings }));
+        } else {
+            outln!("{title}:");
+            if readings.is_empty() {
+                outln!("  No readings");
+                return;
+            }
+
+            let header = ["CH", "SENSOR", "VALUE", "UNIT"].map(String::from);
+            let rows: Vec<[String; 4]> = readings
+                .iter()
+                .map(|r| {
+                    [
+                        r.channel.to_string(),
+                        r.name(),
+                        r.format_value(),
+                        r.unit().to_string(),
+                    ]
+                })
+                .collect();
+            let mut widths = header.clone().map(|h| h.chars().count());
+            for row in &rows {
+                for (width, field) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(field.chars().count());
+                }
+            }
+
+            outln!("  {}", align_row(&header, &widths));
+            for row in &rows {
+                outln!("  {}", align_row(row, &widths));
+            }
+        }
+    }
+
     /// Prints a message.
     pub fn print_message(
         &self,
@@ -382,7 +616,32 @@ impl Display {
                 _ => String::new(),
             };
             let prefix = if is_command { "$" } else { "" };
-            println!("{sender}{signal}: {prefix}{text}");
+            outln!("{sender}{signal}: {prefix}{text}");
+        }
+    }
+
+    /// Prints a channel message, with the sender split off the text.
+    pub fn print_channel_message(
+        &self,
+        channel_index: u8,
+        channel_name: Option<&str>,
+        sender: Option<&str>,
+        text: &str,
+        snr: Option<f32>,
+    ) {
+        if self.is_json() {
+            self.print_json(&json!({
+                "channel_index": channel_index,
+                "channel_name": channel_name,
+                "sender": sender,
+                "text": text,
+                "snr": snr,
+            }));
+        } else {
+            let label = channel_label(channel_index, channel_name);
+            let sender = sender.map(|s| format!(" {s}")).unwrap_or_default();
+            let signal = snr.map(|s| format!(" [{s:.2}]")).unwrap_or_default();
+            outln!("{label}{sender}{signal}: {text}");
         }
     }
 
@@ -399,14 +658,95 @@ impl Display {
         }
     }
 
-    /// Prints ACK received.
-    pub fn print_ack(&self, code: u32) {
+    /// Prints the outcome of one attempt of a retried message.
+    pub fn print_msg_attempt(
+        &self,
+        recipient: &str,
+        attempt: u32,
+        attempts: u32,
+        flood: bool,
+        expected_ack: u32,
+        acked_after: Option<Duration>,
+    ) {
+        let route = if flood { "flood" } else { "direct" };
+        if self.is_json() {
+            self.print_json(&json!({
+                "recipient": recipient,
+                "attempt": attempt,
+                "route": route,
+                "expected_ack": format!("{expected_ack:08x}"),
+                "acked": acked_after.is_some(),
+                "rtt_ms": acked_after.map(|d| d.as_millis()),
+            }));
+        } else if let Some(elapsed) = acked_after {
+            outln!(
+                "Attempt {attempt}/{attempts} to {recipient} ({route}): acked after {:.1}s",
+                elapsed.as_secs_f64()
+            );
+        } else {
+            outln!("Attempt {attempt}/{attempts} to {recipient} ({route}): no ACK");
+        }
+    }
+
+    /// Prints that the path to a contact was reset before retrying via flood.
+    pub fn print_path_reset(&self, recipient: &str) {
+        if !self.is_json() {
+            self.print_colored(
+                &format!("Path to {recipient} reset, retrying via flood"),
+                Color::Yellow,
+            );
+        }
+    }
+
+    /// Prints the final status of a retried message.
+    pub fn print_delivery(&self, recipient: &str, delivered: bool, attempts: u32, flood: bool) {
+        if self.is_json() {
+            self.print_json(&json!({
+                "recipient": recipient,
+                "status": if delivered { "delivered" } else { "failed" },
+                "attempts": attempts,
+                "flood": flood,
+            }));
+        } else if delivered {
+            self.print_colored(
+                &format!("Delivered to {recipient} after {attempts} attempt(s)"),
+                Color::Green,
+            );
+        } else {
+            self.print_colored(
+                &format!("Delivery to {recipient} failed after {attempts} attempt(s)"),
+                Color::Red,
+            );
+        }
+    }
+
+    /// Prints ACK received, with the message it acknowledges when known.
+    pub fn print_ack(&self, code: u32, pending: Option<&PendingAck>) {
         if self.is_json() {
             self.print_json(&json!({
                 "code": format!("{code:08x}"),
+                "recipient": pending.map(|p| &p.recipient),
+                "text": pending.map(|p| &p.text),
+                "rtt_ms": pending.map(|p| p.sent_at.elapsed().as_millis()),
+            }));
+        } else {
+            self.print_colored(&describe_ack(code, pending), Color::Green);
+        }
+    }
+
+    /// Prints a reply sent by the bot.
+    pub fn print_bot_reply(&self, rule: &str, sender: &str, channel: Option<&str>, text: &str) {
+        if self.is_json() {
+            self.print_json(&json!({
+                "type": "bot_reply",
+                "rule": rule,
+                "to": sender,
+                "channel": channel,
+                "text": text,
             }));
         } else {
-            self.print_colored("Msg acked", Color::Green);
+            let target = channel.map_or_else(|| sender.to_string(), |c| format!("{c} ({sender})"));
+            self.print_colored(&format!("[{rule}] -> {target}: {text}"), Color::Cyan);
         }
     }
 
@@ -415,9 +755,114 @@ impl Display {
         if self.is_json() {
             self.print_json(&json!({ "no_more_messages": true }));
         } else {
-            println!("No more messages");
+            outln!("No more messages");
         }
     }
+
+    /// Prints message history entries.
+    pub fn print_history(&self, records: &[HistoryRecord]) {
+        if self.is_json() {
+            self.print_json(&records);
+            return;
+        }
+
+        if records.is_empty() {
+            outln!("No messages");
+            return;
+        }
+
+        for record in records {
+            let entry = &record.entry;
+            let time = Local
+                .timestamp_opt(i64::from(entry.time), 0)
+                .single()
+                .map_or_else(
+                    || entry.time.to_string(),
+                    |t| t.format("%Y-%m-%d %H:%M:%S").to_string(),
+                );
+            let direction = match entry.kind {
+                EntryKind::Sent | EntryKind::ChannelSent => ">",
+                _ => "<",
+            };
+            let peer = match (entry.channel, entry.contact.as_deref()) {
+                (Some(index), _) => format!("#{index}"),
+                (None, Some(contact)) => contact.to_string(),
+                (None, None) => "?".to_string(),
+            };
+            let signal = entry.snr.map(|s| format!(" [{s:.2}]")).unwrap_or_default();
+            let text = entry.text.as_deref().unwrap_or_default();
+            let line = format!("{time} {direction} {peer}{signal}: {text}");
+
+            if record.acked == Some(true) {
+                out!("{line} ");
+                self.print_colored("✓", Color::Green);
+            } else {
+                outln!("{line}");
+            }
+        }
+    }
+
+    /// Prints the frames of a capture with their packet types.
+    pub fn print_capture(&self, records: &[CaptureRecord]) {
+        if self.is_json() {
+            let frames: Vec<Value> = records
+                .iter()
+                .map(|r| {
+                    json!({
+                        "t_ms": r.t_ms,
+                        "dir": r.dir,
+                        "type": r.packet_type(),
+                        "frame": r.frame,
+                    })
+                })
+                .collect();
+            self.print_json(&frames);
+            return;
+        }
+
+        for record in records {
+            let direction = match record.dir {
+                Direction::Tx => ">",
+                Direction::Rx => "<",
+            };
+            #[allow(clippy::cast_precision_loss)]
+            let secs = record.t_ms as f64 / 1000.0;
+            outln!(
+                "{secs:>9.3}s {direction} {:<22} {}",
+                record.packet_type(),
+                record.frame
+            );
+        }
+        outln!("\nTotal: {} frames", records.len());
+    }
+}
+
+/// Converts a channel to JSON value.
+fn channel_to_json(channel: &Channel) -> Value {
+    json!({
+        "index": channel.index,
+        "name": channel.name,
+        "secret": hex::encode(channel.secret),
+    })
+}
+
+/// Quotes a CSV field if needed.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Pads fields to the column widths (without trailing padding).
+fn align_row(fields: &[String], widths: &[usize]) -> String {
+    let padded: Vec<String> = fields
+        .iter()
+        .zip(widths)
+        .map(|(field, width)| format!("{field:<width$}"))
+        .collect();
+    padded.join("  ").trim_end().to_string()
 }
 
 /// Converts a contact to JSON value.
@@ -446,3 +891,68 @@ fn contact_to_json(contact: &Contact) -> Value {
         "last_modified": contact.last_modified,
     })
 }
+
+/// Describes an ACK, e.g. "ACK from Bob for 'hi' after 4.2s".
+#[must_use]
+pub fn describe_ack(code: u32, pending: Option<&PendingAck>) -> String {
+    match pending {
+        Some(pending) => format!(
+            "ACK from {} for '{}' after {:.1}s",
+            pending.recipient,
+            pending.text,
+            pending.sent_at.elapsed().as_secs_f64()
+        ),
+        None => format!("ACK {code:08x}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::capture;
+
+    fn render(mode: OutputMode, f: impl FnOnce(&Display)) -> String {
+        let display = Display::new(false, false).with_mode(mode);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(capture(async { f(&display) })).1
+    }
+
+    fn records() -> Vec<Value> {
+        vec![
+            json!({ "name": "Alice", "snr": 6.5 }),
+            json!({ "name": "Bob, \"the\" relay", "snr": null }),
+        ]
+    }
+
+    #[test]
+    fn test_csv_output() {
+        let output = render(OutputMode::Csv, |d| {
+            d.print_table(&["name", "snr"], &records());
+        });
+        assert_eq!(output, "name,snr\nAlice,6.5\n\"Bob, \"\"the\"\" relay\",\n");
+    }
+
+    #[test]
+    fn test_table_output() {
+        let output = render(OutputMode::Table, |d| {
+            d.print_table(&["name", "snr"], &records());
+        });
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "NAME              SNR");
+        assert_eq!(lines[1], "Alice             6.5");
+        assert_eq!(lines[2], "Bob, \"the\" relay");
+    }
+
+    #[test]
+    fn test_ndjson_output() {
+        let output = render(OutputMode::Ndjson, |d| d.print_json(&records()));
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], r#"{"name":"Alice","snr":6.5}"#);
+
+        let output = render(OutputMode::Json, |d| d.print_json(&records()));
+        assert!(output.starts_with("[\n"));
+    }
+}
@@ -1,7 +1,27 @@
 //! Error types for the CLI.
 
+use serde_json::{Value, json};
 use thiserror::Error;
 
+/// Exit codes by error kind, stable across releases (`2` matches clap's usage errors).
+pub const EXIT_CODES: &[(&str, i32)] = &[
+    ("command", 1),
+    ("usage", 2),
+    ("connection", 3),
+    ("serial", 4),
+    ("tcp", 5),
+    ("timeout", 6),
+    ("contact_not_found", 7),
+    ("channel_not_found", 8),
+    ("invalid_argument", 9),
+    ("io", 10),
+    ("json", 11),
+    ("config", 12),
+    ("daemon", 13),
+    ("script", 14),
+    ("mqtt", 15),
+];
+
 /// CLI error type.
 #[derive(Debug, Error)]
 pub enum CliError {
@@ -13,6 +33,10 @@ pub enum CliError {
     #[error("Serial port error: {0}")]
     Serial(String),
 
+    /// TCP connection error.
+    #[error("TCP error: {0}")]
+    Tcp(String),
+
     /// Command error.
     #[error("Command error: {0}")]
     Command(String),
@@ -25,6 +49,10 @@ pub enum CliError {
     #[error("Channel not found: {0}")]
     ChannelNotFound(String),
 
+    /// Command line syntax error (message includes usage).
+    #[error("{0}")]
+    Usage(String),
+
     /// Invalid argument.
     #[error("Invalid argument: {0}")]
     InvalidArgument(String),
@@ -41,10 +69,108 @@ pub enum CliError {
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
 
+    /// Config file error.
+    #[error("Config error: {0}")]
+    Config(String),
+
+    /// Daemon connection or protocol error.
+    #[error("Daemon error: {0}")]
+    Daemon(String),
+
+    /// Error reported by the daemon for a remote command, with the daemon-side kind.
+    #[error("{message}")]
+    Remote { kind: String, message: String },
+
     /// Script error.
     #[error("Script error at line {line}: {message}")]
     Script { line: usize, message: String },
+
+    /// MQTT broker error.
+    #[error("MQTT error: {0}")]
+    Mqtt(String),
+}
+
+impl CliError {
+    /// Returns the error kind used in JSON errors and for the exit code.
+    #[must_use]
+    pub fn kind(&self) -> &str {
+        match self {
+            Self::Connection(_) => "connection",
+            Self::Serial(_) => "serial",
+            Self::Tcp(_) => "tcp",
+            Self::Command(_) => "command",
+            Self::ContactNotFound(_) => "contact_not_found",
+            Self::ChannelNotFound(_) => "channel_not_found",
+            Self::Usage(_) => "usage",
+            Self::InvalidArgument(_) => "invalid_argument",
+            Self::Timeout(_) => "timeout",
+            Self::Io(_) => "io",
+            Self::Json(_) => "json",
+            Self::Config(_) => "config",
+            Self::Daemon(_) => "daemon",
+            Self::Remote { kind, .. } => kind,
+            Self::Script { .. } => "script",
+            Self::Mqtt(_) => "mqtt",
+        }
+    }
+
+    /// Returns the process exit code for this error.
+    #[must_use]
+    pub fn exit_code(&self) -> i32 {
+        let kind = self.kind();
+        EXIT_CODES
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .map_or(1, |(_, code)| *code)
+    }
+
+    /// Returns the error as `{"error": {"kind": ..., "message": ...}}`.
+    #[must_use]
+    pub fn to_json(&self) -> Value {
+        json!({ "error": { "kind": self.kind(), "message": self.to_string() } })
+    }
 }
 
 /// Result type for CLI operations.
 pub type Result<T> = std::result::Result<T, CliError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(CliError::Command("x".into()).exit_code(), 1);
+        assert_eq!(CliError::Timeout("ACK".into()).exit_code(), 6);
+        assert_eq!(CliError::ContactNotFound("Bob".into()).exit_code(), 7);
+
+        let remote = CliError::Remote {
+            kind: "timeout".into(),
+            message: "Timeout waiting for ACK".into(),
+        };
+        assert_eq!(remote.exit_code(), 6);
+
+        let unknown = CliError::Remote {
+            kind: "future_kind".into(),
+            message: "x".into(),
+        };
+        assert_eq!(unknown.exit_code(), 1);
+
+        let codes: std::collections::HashSet<_> = EXIT_CODES.iter().map(|(_, c)| c).collect();
+        assert_eq!(codes.len(), EXIT_CODES.len());
+    }
+
+    #[test]
+    fn test_json_error() {
+        let error = CliError::Timeout("status response".into());
+        assert_eq!(
+            error.to_json(),
+            json!({
+                "error": {
+                    "kind": "timeout",
+                    "message": "Timeout waiting for status response",
+                }
+            })
+        );
+    }
+}
@@ -0,0 +1,206 @@
+//! Event helpers: channel message parsing and JSON serialization for streaming
+//! consumers (daemon subscribers, the HTTP API, the MQTT bridge, the IRC gateway).
+
+use std::collections::HashMap;
+
+use meshcore::event::Event;
+use meshcore::transport::Transport;
+use meshcore::types::{Contact, PublicKey, StatusResponse};
+use serde_json::{Value, json};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::commands::{CommandContext, lookup_sender_name};
+use crate::telemetry;
+
+/// Longest sender name accepted in a channel message prefix.
+const MAX_SENDER_LEN: usize = 32;
+
+/// Splits the conventional `SenderName: ` prefix off a channel message text.
+///
+/// Returns `None` as the sender when the text doesn't start with a plausible name.
+#[must_use]
+pub fn split_channel_sender(text: &str) -> (Option<&str>, &str) {
+    match text.split_once(": ") {
+        Some((sender, rest))
+            if !sender.is_empty()
+                && sender.len() <= MAX_SENDER_LEN
+                && sender.trim() == sender
+                && !sender.contains('\n') =>
+        {
+            (Some(sender), rest)
+        }
+        _ => (None, text),
+    }
+}
+
+/// Formats a channel label: `#rust`, `#Public`, or `#3` when the name is unknown.
+#[must_use]
+pub fn channel_label(index: u8, name: Option<&str>) -> String {
+    match name {
+        Some(name) if name.starts_with('#') => name.to_string(),
+        Some(name) => format!("#{name}"),
+        None => format!("#{index}"),
+    }
+}
+
+/// Converts an event to JSON, or `None` for events that aren't streamed.
+///
+/// Every value carries a `type` field naming the event. `channels` maps channel
+/// slots to their names.
+#[must_use]
+pub fn event_to_json(
+    event: &Event,
+    contacts: &HashMap<PublicKey, Contact>,
+    channels: &HashMap<u8, String>,
+) -> Option<Value> {
+    let value = match event {
+        Event::ContactMessage(msg) => json!({
+            "type": "contact_message",
+            "sender": lookup_sender_name(contacts, &msg.sender_prefix),
+            "sender_prefix": hex::encode(msg.sender_prefix),
+            "text": msg.text,
+            "is_command": msg.text_type == meshcore::types::TextType::Command,
+            "snr": msg.signal.as_ref().map(|s| s.snr),
+        }),
+        Event::ChannelMessage(msg) => {
+            let (sender, text) = split_channel_sender(&msg.text);
+            json!({
+                "type": "channel_message",
+                "channel_index": msg.channel_index,
+                "channel_name": channels.get(&msg.channel_index),
+                "sender": sender,
+                "text": text,
+                "snr": msg.signal.as_ref().map(|s| s.snr),
+            })
+        }
+        Event::MessageSent {
+            expected_ack,
+            timeout_ms,
+        } => json!({
+            "type": "message_sent",
+            "expected_ack": format!("{expected_ack:08x}"),
+            "suggested_timeout": timeout_ms,
+        }),
+        Event::Ack(ack) => json!({
+            "type": "ack",
+            "code": format!("{:08x}", ack.code),
+        }),
+        Event::Advertisement(key) => json!({
+            "type": "advert",
+            "public_key": key.to_hex(),
+        }),
+        Event::NewContactAdvert(contact) => json!({
+            "type": "new_contact",
+            "name": contact.name,
+            "public_key": contact.public_key.to_hex(),
+        }),
+        Event::StatusResponse(status) => {
+            let mut value = status_to_json(status);
+            value["type"] = json!("status");
+            value["sender"] = json!(lookup_sender_name(contacts, &status.pubkey_prefix));
+            value
+        }
+        Event::TelemetryResponse(response) => json!({
+            "type": "telemetry",
+            "readings": telemetry::from_telemetry(response)
+                .iter()
+                .map(telemetry::Reading::to_json)
+                .collect::<Vec<_>>(),
+        }),
+        Event::LoginSuccess => json!({ "type": "login_success" }),
+        Event::LoginFailed => json!({ "type": "login_failed" }),
+        Event::MessagesWaiting => json!({ "type": "messages_waiting" }),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+/// Starts converting device events to JSON for several listeners (WebSocket or IRC
+/// clients), fetching waiting messages once for all of them.
+pub async fn forward_json<T: Transport>(
+    ctx: &CommandContext<T>,
+    tx: broadcast::Sender<Value>,
+) -> JoinHandle<()> {
+    let mut events = ctx.event_stream().await;
+    let ctx = ctx.with_display(ctx.display.clone());
+
+    tokio::spawn(async move {
+        while let Some(event) = events.next().await {
+            let events = match ctx.resolve_waiting(event).await {
+                Ok(events) => events,
+                Err(e) => {
+                    tracing::warn!("Failed to fetch waiting messages: {e}");
+                    continue;
+                }
+            };
+            let contacts = ctx.client.lock().await.contacts().await;
+            let channels = ctx.state.lock().await.channels.clone();
+            for event in &events {
+                if let Some(value) = event_to_json(event, &contacts, &channels) {
+                    // Nobody listening is fine
+                    let _ = tx.send(value);
+                }
+            }
+        }
+    })
+}
+
+/// Converts a repeater status response to JSON.
+#[must_use]
+pub fn status_to_json(status: &StatusResponse) -> Value {
+    json!({
+        "pubkey_prefix": hex::encode(status.pubkey_prefix),
+        "battery_mv": status.battery_mv,
+        "tx_queue_len": status.tx_queue_len,
+        "noise_floor": status.noise_floor,
+        "last_rssi": status.last_rssi,
+        "packets_received": status.packets_received,
+        "packets_sent": status.packets_sent,
+        "airtime_secs": status.airtime_secs,
+        "uptime_secs": status.uptime_secs,
+        "sent_flood": status.sent_flood,
+        "sent_direct": status.sent_direct,
+        "recv_flood": status.recv_flood,
+        "recv_direct": status.recv_direct,
+        "full_events": status.full_events,
+        "last_snr": status.last_snr,
+        "direct_dups": status.direct_dups,
+        "flood_dups": status.flood_dups,
+        "rx_airtime_secs": status.rx_airtime_secs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_channel_sender() {
+        assert_eq!(
+            split_channel_sender("Alice: hi there"),
+            (Some("Alice"), "hi there")
+        );
+        assert_eq!(
+            split_channel_sender("Bob Smith: a: b"),
+            (Some("Bob Smith"), "a: b")
+        );
+        assert_eq!(
+            split_channel_sender("no sender here"),
+            (None, "no sender here")
+        );
+        assert_eq!(split_channel_sender(": empty"), (None, ": empty"));
+        assert_eq!(split_channel_sender(" Alice: hi"), (None, " Alice: hi"));
+
+        let long = format!("{}: hi", "x".repeat(40));
+        assert_eq!(split_channel_sender(&long), (None, long.as_str()));
+    }
+
+    #[test]
+    fn test_channel_label() {
+        assert_eq!(channel_label(2, Some("#rust")), "#rust");
+        assert_eq!(channel_label(0, Some("Public")), "#Public");
+        assert_eq!(channel_label(5, None), "#5");
+    }
+}
@@ -0,0 +1,405 @@
+//! Persistent message history.
+//!
+//! Received and sent messages and ACKs are appended as JSON lines to a
+//! per-device file in the config directory (`messages/<public key>.jsonl`).
+
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::commands::current_timestamp;
+use crate::config::Config;
+use crate::error::Result;
+
+/// Kind of a history entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    /// Private message received from a contact.
+    Received,
+    /// Private message sent to a contact.
+    Sent,
+    /// Channel message received.
+    ChannelReceived,
+    /// Channel message sent.
+    ChannelSent,
+    /// ACK received for a sent message.
+    Ack,
+}
+
+/// A single history entry, stored as one JSON line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Unix timestamp when the entry was recorded.
+    pub time: u32,
+    /// Entry kind.
+    pub kind: EntryKind,
+    /// Contact name (private messages).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contact: Option<String>,
+    /// Contact public key or key prefix, hex encoded (private messages).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Channel index (channel messages).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<u8>,
+    /// Message text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Expected ACK code (sent messages) or received ACK code.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ack: Option<u32>,
+    /// Signal-to-noise ratio (received messages).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snr: Option<f32>,
+}
+
+impl HistoryEntry {
+    /// Creates an empty entry of the given kind, stamped with the current time.
+    fn new(kind: EntryKind) -> Self {
+        Self {
+            time: current_timestamp(),
+            kind,
+            contact: None,
+            key: None,
+            channel: None,
+            text: None,
+            ack: None,
+            snr: None,
+        }
+    }
+
+    /// Creates an entry for a received private message.
+    #[must_use]
+    pub fn received(contact: &str, key: &str, text: &str, snr: Option<f32>) -> Self {
+        Self {
+            contact: Some(contact.to_string()),
+            key: Some(key.to_string()),
+            text: Some(text.to_string()),
+            snr,
+            ..Self::new(EntryKind::Received)
+        }
+    }
+
+    /// Creates an entry for a sent private message.
+    #[must_use]
+    pub fn sent(contact: &str, key: &str, text: &str, expected_ack: Option<u32>) -> Self {
+        Self {
+            contact: Some(contact.to_string()),
+            key: Some(key.to_string()),
+            text: Some(text.to_string()),
+            ack: expected_ack,
+            ..Self::new(EntryKind::Sent)
+        }
+    }
+
+    /// Creates an entry for a received channel message.
+    #[must_use]
+    pub fn channel_received(channel: u8, text: &str, snr: Option<f32>) -> Self {
+        Self {
+            channel: Some(channel),
+            text: Some(text.to_string()),
+            snr,
+            ..Self::new(EntryKind::ChannelReceived)
+        }
+    }
+
+    /// Creates an entry for a sent channel message.
+    #[must_use]
+    pub fn channel_sent(channel: u8, text: &str) -> Self {
+        Self {
+            channel: Some(channel),
+            text: Some(text.to_string()),
+            ..Self::new(EntryKind::ChannelSent)
+        }
+    }
+
+    /// Creates an entry for a received ACK.
+    #[must_use]
+    pub fn ack(code: u32) -> Self {
+        Self {
+            ack: Some(code),
+            ..Self::new(EntryKind::Ack)
+        }
+    }
+}
+
+/// Conversation selected by the `history` command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HistoryTarget {
+    /// Private conversation with a contact (name and full public key).
+    Contact { name: String, key: String },
+    /// Channel conversation.
+    Channel(u8),
+}
+
+/// Filter for history queries.
+#[derive(Debug, Clone)]
+pub struct HistoryFilter {
+    /// Conversation to show.
+    pub target: HistoryTarget,
+    /// Only entries recorded at or after this Unix timestamp.
+    pub since: Option<u32>,
+    /// Only messages containing this text (case-insensitive).
+    pub grep: Option<String>,
+}
+
+impl HistoryFilter {
+    /// Checks whether a message entry matches the filter.
+    #[must_use]
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        let in_target = match &self.target {
+            HistoryTarget::Contact { name, key } => {
+                matches!(entry.kind, EntryKind::Received | EntryKind::Sent)
+                    && (entry.key.as_deref().is_some_and(|k| key.starts_with(k))
+                        || entry
+                            .contact
+                            .as_deref()
+                            .is_some_and(|c| c.eq_ignore_ascii_case(name)))
+            }
+            HistoryTarget::Channel(index) => {
+                matches!(
+                    entry.kind,
+                    EntryKind::ChannelReceived | EntryKind::ChannelSent
+                ) && entry.channel == Some(*index)
+            }
+        };
+
+        let recent = self.since.is_none_or(|since| entry.time >= since);
+
+        let found = self.grep.as_deref().is_none_or(|grep| {
+            entry
+                .text
+                .as_deref()
+                .is_some_and(|t| t.to_lowercase().contains(&grep.to_lowercase()))
+        });
+
+        in_target && recent && found
+    }
+}
+
+/// A history entry returned by a query.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryRecord {
+    /// The stored entry.
+    #[serde(flatten)]
+    pub entry: HistoryEntry,
+    /// Whether a sent message was acknowledged (`None` for other kinds).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acked: Option<bool>,
+}
+
+/// Append-only JSONL message store for one device.
+#[derive(Debug, Clone)]
+pub struct MessageStore {
+    path: PathBuf,
+}
+
+impl MessageStore {
+    /// Creates a store backed by the given file.
+    #[must_use]
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Creates the store for a device, keyed by its public key.
+    #[must_use]
+    pub fn for_device(public_key: &str) -> Option<Self> {
+        Config::messages_dir().map(|dir| Self::new(dir.join(format!("{public_key}.jsonl"))))
+    }
+
+    /// Appends an entry to the store.
+    pub fn append(&self, entry: &HistoryEntry) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Loads all entries, skipping lines that can't be parsed.
+    pub fn load(&self) -> Result<Vec<HistoryEntry>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let file = fs::File::open(&self.path)?;
+        let mut entries = Vec::new();
+        for (line_num, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => tracing::debug!(
+                    "Skipping invalid history line {} in {}: {e}",
+                    line_num + 1,
+                    self.path.display()
+                ),
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Returns the entries matching the filter, with ACK state for sent messages.
+    pub fn query(&self, filter: &HistoryFilter) -> Result<Vec<HistoryRecord>> {
+        let entries = self.load()?;
+
+        let acks: HashSet<u32> = entries
+            .iter()
+            .filter(|e| e.kind == EntryKind::Ack)
+            .filter_map(|e| e.ack)
+            .collect();
+
+        Ok(entries
+            .into_iter()
+            .filter(|e| filter.matches(e))
+            .map(|entry| {
+                let acked = (entry.kind == EntryKind::Sent)
+                    .then(|| entry.ack.is_some_and(|code| acks.contains(&code)));
+                HistoryRecord { entry, acked }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bob() -> HistoryTarget {
+        HistoryTarget::Contact {
+            name: "Bob".into(),
+            key: "a1b2c3d4e5f6a7b8".into(),
+        }
+    }
+
+    #[test]
+    fn test_append_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = MessageStore::new(dir.path().join("messages").join("dev.jsonl"));
+
+        assert!(store.load().unwrap().is_empty());
+
+        store
+            .append(&HistoryEntry::received(
+                "Bob",
+                "a1b2c3d4e5f6",
+                "hi",
+                Some(5.5),
+            ))
+            .unwrap();
+        store
+            .append(&HistoryEntry::channel_sent(1, "hello"))
+            .unwrap();
+
+        let entries = store.load().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].kind, EntryKind::Received);
+        assert_eq!(entries[0].text.as_deref(), Some("hi"));
+        assert_eq!(entries[1].channel, Some(1));
+    }
+
+    #[test]
+    fn test_load_skips_invalid_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dev.jsonl");
+        fs::write(&path, "not json\n\n").unwrap();
+        let store = MessageStore::new(path);
+        store.append(&HistoryEntry::ack(1)).unwrap();
+
+        assert_eq!(store.load().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_filter_contact() {
+        let filter = HistoryFilter {
+            target: bob(),
+            since: None,
+            grep: None,
+        };
+
+        assert!(filter.matches(&HistoryEntry::received("Bob", "a1b2c3d4e5f6", "hi", None)));
+        assert!(filter.matches(&HistoryEntry::sent("bob", "ffff", "yo", None)));
+        assert!(!filter.matches(&HistoryEntry::received("Alice", "ffffff", "hi", None)));
+        assert!(!filter.matches(&HistoryEntry::channel_received(0, "hi", None)));
+        assert!(!filter.matches(&HistoryEntry::ack(1)));
+    }
+
+    #[test]
+    fn test_filter_channel_since_grep() {
+        let filter = HistoryFilter {
+            target: HistoryTarget::Channel(2),
+            since: Some(1000),
+            grep: Some("HELLO".into()),
+        };
+
+        let mut entry = HistoryEntry::channel_received(2, "well hello there", None);
+        entry.time = 1000;
+        assert!(filter.matches(&entry));
+
+        entry.time = 999;
+        assert!(!filter.matches(&entry));
+
+        entry.time = 2000;
+        entry.text = Some("bye".into());
+        assert!(!filter.matches(&entry));
+
+        entry.text = Some("hello".into());
+        entry.channel = Some(3);
+        assert!(!filter.matches(&entry));
+    }
+
+    #[test]
+    fn test_query_marks_acked() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = MessageStore::new(dir.path().join("dev.jsonl"));
+
+        store
+            .append(&HistoryEntry::sent(
+                "Bob",
+                "a1b2c3d4e5f6a7b8",
+                "one",
+                Some(7),
+            ))
+            .unwrap();
+        store
+            .append(&HistoryEntry::sent(
+                "Bob",
+                "a1b2c3d4e5f6a7b8",
+                "two",
+                Some(8),
+            ))
+            .unwrap();
+        store
+            .append(&HistoryEntry::received("Bob", "a1b2c3d4e5f6", "ok", None))
+            .unwrap();
+        store.append(&HistoryEntry::ack(7)).unwrap();
+
+        let records = store
+            .query(&HistoryFilter {
+                target: bob(),
+                since: None,
+                grep: None,
+            })
+            .unwrap();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].acked, Some(true));
+        assert_eq!(records[1].acked, Some(false));
+        assert_eq!(records[2].acked, None);
+    }
+}
@@ -0,0 +1,272 @@
+//! Event hooks: external programs run on incoming events.
+//!
+//! Each hook receives the event as a JSON line on stdin (the same shape daemon
+//! subscribers get) plus its key fields as `MESHCORE_*` environment variables.
+//! Hooks run in the background with a timeout, at most `max_concurrent` at a time;
+//! events arriving while every slot is busy are dropped with a warning.
+
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use meshcore::event::Event;
+use meshcore::types::{Contact, PublicKey};
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+
+use crate::config::HooksConfig;
+use crate::error::{CliError, Result};
+use crate::events::{channel_label, event_to_json};
+
+/// Configured hooks with the slots limiting how many run at once.
+pub struct Hooks {
+    config: HooksConfig,
+    slots: Arc<Semaphore>,
+}
+
+impl Hooks {
+    /// Creates the hook runner, or `None` if no hook is configured.
+    #[must_use]
+    pub fn from_config(config: &HooksConfig) -> Option<Arc<Self>> {
+        if config.is_empty() {
+            return None;
+        }
+        Some(Arc::new(Self {
+            config: config.clone(),
+            slots: Arc::new(Semaphore::new(config.max_concurrent.max(1))),
+        }))
+    }
+
+    /// Starts the hook configured for an event, if any, without waiting for it.
+    ///
+    /// When every slot is busy the event is dropped, so a burst of messages can't pile
+    /// up waiting hooks.
+    pub fn dispatch(
+        &self,
+        event: &Event,
+        contacts: &HashMap<PublicKey, Contact>,
+        channels: &HashMap<u8, String>,
+    ) {
+        let Some(name) = hook_name(event) else {
+            return;
+        };
+        let Some(command) = self.config.get(name) else {
+            return;
+        };
+        let Some(value) = event_to_json(event, contacts, channels) else {
+            return;
+        };
+
+        let Ok(permit) = self.slots.clone().try_acquire_owned() else {
+            tracing::warn!(
+                "Hook {name} skipped: {} hooks already running",
+                self.config.max_concurrent.max(1)
+            );
+            return;
+        };
+
+        let command = command.to_string();
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+        tokio::spawn(async move {
+            let _permit = permit;
+            if let Err(e) = run(&command, &value, timeout).await {
+                tracing::warn!("Hook {name} failed: {e}");
+            }
+        });
+    }
+}
+
+/// Returns the name of the hook that handles an event.
+#[must_use]
+pub fn hook_name(event: &Event) -> Option<&'static str> {
+    match event {
+        Event::ContactMessage(_) => Some("on_message"),
+        Event::ChannelMessage(_) => Some("on_channel"),
+        Event::Advertisement(_) | Event::NewContactAdvert(_) => Some("on_advert"),
+        Event::Ack(_) => Some("on_ack"),
+        Event::LoginSuccess | Event::LoginFailed => Some("on_login"),
+        _ => None,
+    }
+}
+
+/// Builds the environment variables for an event's JSON.
+///
+/// Fields the event doesn't have are left out.
+#[must_use]
+pub fn hook_env(value: &Value) -> Vec<(&'static str, String)> {
+    let mut env = Vec::new();
+    let mut push = |key: &'static str, field: Option<String>| {
+        if let Some(field) = field {
+            env.push((key, field));
+        }
+    };
+    let text = |key: &str| value.get(key).and_then(Value::as_str).map(str::to_string);
+
+    push("MESHCORE_EVENT", text("type"));
+    push("MESHCORE_SENDER", text("sender").or_else(|| text("name")));
+    push(
+        "MESHCORE_CHANNEL",
+        value
+            .get("channel_index")
+            .and_then(Value::as_u64)
+            .and_then(|index| u8::try_from(index).ok())
+            .map(|index| channel_label(index, value["channel_name"].as_str())),
+    );
+    push("MESHCORE_TEXT", text("text"));
+    push(
+        "MESHCORE_SNR",
+        value
+            .get("snr")
+            .and_then(Value::as_f64)
+            .map(|snr| snr.to_string()),
+    );
+    push(
+        "MESHCORE_PUBKEY",
+        text("public_key").or_else(|| text("sender_prefix")),
+    );
+    push("MESHCORE_ACK", text("code"));
+    env
+}
+
+/// Runs a hook command with the event on stdin, killing it after `timeout`.
+async fn run(command: &str, value: &Value, timeout: Duration) -> Result<()> {
+    let words = shell_words::split(command)
+        .map_err(|e| CliError::Config(format!("Invalid hook command '{command}': {e}")))?;
+    let Some((program, args)) = words.split_first() else {
+        return Err(CliError::Config("Empty hook command".into()));
+    };
+
+    let mut child = Command::new(program)
+        .args(args)
+        .envs(hook_env(value))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| CliError::Command(format!("Failed to start '{program}': {e}")))?;
+
+    let input = format!("{value}\n");
+    let finished = tokio::time::timeout(timeout, async {
+        if let Some(mut stdin) = child.stdin.take() {
+            // A hook that ignores stdin may exit before reading it
+            let _ = stdin.write_all(input.as_bytes()).await;
+        }
+        child.wait().await
+    })
+    .await;
+
+    let Ok(status) = finished else {
+        let _ = child.kill().await;
+        return Err(CliError::Timeout(format!("hook '{program}'")));
+    };
+    let status = status?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(CliError::Command(format!(
+            "'{program}' exited with {status}"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_hook_name() {
+        assert_eq!(hook_name(&Event::LoginSuccess), Some("on_login"));
+        assert_eq!(hook_name(&Event::LoginFailed), Some("on_login"));
+        assert_eq!(hook_name(&Event::MessagesWaiting), None);
+    }
+
+    #[test]
+    fn test_hook_env() {
+        let env = hook_env(&json!({
+            "type": "channel_message",
+            "channel_index": 2,
+            "channel_name": "rust",
+            "sender": "Alice",
+            "text": "hi",
+            "snr": 7.5,
+        }));
+        assert_eq!(
+            env,
+            vec![
+                ("MESHCORE_EVENT", "channel_message".to_string()),
+                ("MESHCORE_SENDER", "Alice".to_string()),
+                ("MESHCORE_CHANNEL", "#rust".to_string()),
+                ("MESHCORE_TEXT", "hi".to_string()),
+                ("MESHCORE_SNR", "7.5".to_string()),
+            ]
+        );
+
+        let env = hook_env(&json!({ "type": "ack", "code": "0000abcd" }));
+        assert_eq!(
+            env,
+            vec![
+                ("MESHCORE_EVENT", "ack".to_string()),
+                ("MESHCORE_ACK", "0000abcd".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_config() {
+        assert!(Hooks::from_config(&HooksConfig::default()).is_none());
+        let config = HooksConfig {
+            on_ack: Some("true".into()),
+            ..HooksConfig::default()
+        };
+        assert!(Hooks::from_config(&config).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_drops_events_when_busy() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("runs");
+        let config = HooksConfig {
+            on_login: Some(format!("sh -c 'echo run >> {}; sleep 0.5'", log.display())),
+            max_concurrent: 1,
+            ..HooksConfig::default()
+        };
+        let hooks = Hooks::from_config(&config).unwrap();
+
+        for _ in 0..3 {
+            hooks.dispatch(&Event::LoginSuccess, &HashMap::new(), &HashMap::new());
+        }
+        assert_eq!(hooks.slots.available_permits(), 0);
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(hooks.slots.available_permits(), 1);
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "run\n");
+    }
+
+    #[tokio::test]
+    async fn test_run_hook() {
+        let value = json!({ "type": "ack", "code": "0000abcd" });
+        let timeout = Duration::from_secs(5);
+        assert!(run("true", &value, timeout).await.is_ok());
+        assert!(matches!(
+            run("false", &value, timeout).await,
+            Err(CliError::Command(_))
+        ));
+        assert!(matches!(
+            run("sleep 5", &value, Duration::from_millis(50)).await,
+            Err(CliError::Timeout(_))
+        ));
+        assert!(matches!(
+            run(
+                "sh -c 'read line; test \"$MESHCORE_ACK\" = 0000abcd'",
+                &value,
+                timeout
+            )
+            .await,
+            Ok(())
+        ));
+    }
+}
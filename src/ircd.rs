@@ -0,0 +1,703 @@
+//! IRC gateway: a minimal IRC server in front of the device.
+//!
+//! Channel slots appear as IRC channels and contacts as users. `PRIVMSG` to a
+//! channel or nick sends a channel or private message, and incoming messages
+//! are delivered as `PRIVMSG` from their sender. Only what common clients need
+//! to register, join and chat is implemented.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use meshcore::transport::Transport;
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::commands::CommandContext;
+use crate::display::Display;
+use crate::error::{CliError, Result};
+use crate::events::forward_json;
+use crate::output;
+use crate::serve::tokens_match;
+
+/// Server name used as the source of replies.
+const SERVER: &str = "meshcore";
+
+/// Events buffered per client before it starts missing them.
+const EVENT_BUFFER: usize = 256;
+
+/// A parsed IRC message (the source prefix is ignored).
+#[derive(Debug, PartialEq, Eq)]
+pub struct IrcMessage {
+    /// Command, uppercased.
+    pub command: String,
+    /// Parameters, the trailing one included.
+    pub params: Vec<String>,
+}
+
+/// Parses an IRC line: `[:prefix] COMMAND param ... [:trailing]`.
+#[must_use]
+pub fn parse_line(line: &str) -> Option<IrcMessage> {
+    let mut rest = line.trim_end_matches(['\r', '\n']);
+    if let Some(prefixed) = rest.strip_prefix(':') {
+        rest = prefixed.split_once(' ')?.1;
+    }
+    let (head, trailing) = match rest.split_once(" :") {
+        Some((head, trailing)) => (head, Some(trailing)),
+        None => (rest, None),
+    };
+
+    let mut words = head.split(' ').filter(|word| !word.is_empty());
+    let command = words.next()?.to_ascii_uppercase();
+    let mut params: Vec<String> = words.map(str::to_string).collect();
+    params.extend(trailing.map(str::to_string));
+    Some(IrcMessage { command, params })
+}
+
+/// Turns a contact or sender name into a valid IRC nick.
+#[must_use]
+pub fn nick(name: &str) -> String {
+    let nick: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-[]\\`^{}|_".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    match nick.chars().next() {
+        Some(first) if !first.is_ascii_digit() && first != '-' => nick,
+        _ => format!("_{nick}"),
+    }
+}
+
+/// Returns the IRC name of a channel slot: `#rust`, or `#3` when it has no name.
+#[must_use]
+pub fn irc_channel(index: u8, name: Option<&str>) -> String {
+    match name.map(|name| name.trim_start_matches('#')) {
+        Some(name) if !name.is_empty() => format!("#{}", name.replace([' ', ',', '\x07'], "_")),
+        _ => format!("#{index}"),
+    }
+}
+
+/// Removes characters that would end or corrupt an IRC line.
+fn clean(text: &str) -> String {
+    text.replace(['\r', '\n', '\0'], " ")
+}
+
+/// Gives every contact a nick, mapped to its public key.
+///
+/// IRC compares nicks without case, so names that turn into the same nick
+/// get a public key prefix appended (`Bob|a1b2`) to keep each one reachable.
+#[must_use]
+pub fn contact_nicks<'a>(
+    contacts: impl IntoIterator<Item = (&'a str, String)>,
+) -> HashMap<String, String> {
+    let mut groups: HashMap<String, Vec<(String, String)>> = HashMap::new();
+    for (name, key) in contacts {
+        let nick = nick(name);
+        groups
+            .entry(nick.to_ascii_lowercase())
+            .or_default()
+            .push((nick, key));
+    }
+
+    let mut nicks = HashMap::new();
+    for group in groups.into_values() {
+        if let [(nick, key)] = group.as_slice() {
+            nicks.insert(nick.clone(), key.clone());
+            continue;
+        }
+        // The shortest prefix that tells the keys apart
+        let unique = |len: usize| {
+            let mut seen: Vec<String> = group
+                .iter()
+                .map(|(_, key)| key[..len.min(key.len())].to_ascii_lowercase())
+                .collect();
+            seen.sort();
+            seen.dedup();
+            seen.len() == group.len()
+        };
+        let longest = group.iter().map(|(_, key)| key.len()).max().unwrap_or(0);
+        let len = (4..longest)
+            .step_by(2)
+            .find(|&len| unique(len))
+            .unwrap_or(longest);
+        for (nick, key) in group {
+            nicks.insert(format!("{nick}|{}", &key[..len.min(key.len())]), key);
+        }
+    }
+    nicks
+}
+
+/// Converts a streamed event to a line for the client `nick`, if it has one.
+///
+/// Contact messages come from the sender's nick in `nicks` (see
+/// [`contact_nicks`]); other senders are only known by name.
+#[must_use]
+pub fn event_line(event: &Value, to: &str, nicks: &HashMap<String, String>) -> Option<String> {
+    let field = |key: &str| event.get(key).and_then(Value::as_str);
+    let known = field("sender_prefix").and_then(|prefix| {
+        nicks.iter().find_map(|(nick, key)| {
+            key.get(..prefix.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+                .then(|| nick.clone())
+        })
+    });
+    let sender = known.unwrap_or_else(|| nick(field("sender").unwrap_or("unknown")));
+    let text = clean(field("text")?);
+
+    match field("type")? {
+        "contact_message" => Some(format!("{}PRIVMSG {to} :{text}", source(&sender))),
+        "channel_message" => {
+            let index = event
+                .get("channel_index")
+                .and_then(Value::as_u64)
+                .and_then(|index| u8::try_from(index).ok())?;
+            let channel = irc_channel(index, field("channel_name"));
+            Some(format!("{}PRIVMSG {channel} :{text}", source(&sender)))
+        }
+        _ => None,
+    }
+}
+
+/// Formats the source prefix of a user.
+fn source(nick: &str) -> String {
+    format!(":{nick}!{nick}@{SERVER} ")
+}
+
+/// State shared by all clients.
+struct Gateway<T: Transport> {
+    ctx: CommandContext<T>,
+    password: Option<String>,
+    events: broadcast::Sender<Value>,
+}
+
+/// Runs the gateway until Ctrl+C.
+pub async fn run<T: Transport>(
+    ctx: &CommandContext<T>,
+    listen: &str,
+    password: Option<String>,
+) -> Result<()> {
+    let listener = TcpListener::bind(listen).await.map_err(|e| {
+        CliError::Io(std::io::Error::new(
+            e.kind(),
+            format!("Failed to listen on {listen}: {e}"),
+        ))
+    })?;
+
+    // Channel names are needed to map IRC channels to slots
+    if let Err(e) = ctx.refresh_channels().await {
+        ctx.display
+            .print_warning(&format!("Failed to load channels: {e}"));
+    }
+
+    let (events, _) = broadcast::channel(EVENT_BUFFER);
+    let gateway = Arc::new(Gateway {
+        ctx: ctx.with_display(Display::new(false, false)),
+        password,
+        events,
+    });
+    let forwarder = forward_json(ctx, gateway.events.clone()).await;
+
+    ctx.display.print_ok(&format!(
+        "IRC gateway listening on {}",
+        listener.local_addr()?
+    ));
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle_client(gateway.clone(), stream));
+                }
+                Err(e) => tracing::warn!("Failed to accept connection: {e}"),
+            },
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    forwarder.abort();
+    Ok(())
+}
+
+/// Serves one client until it quits or disconnects.
+async fn handle_client<T: Transport>(gateway: Arc<Gateway<T>>, stream: TcpStream) {
+    let (reader, writer) = stream.into_split();
+    let mut events = gateway.events.subscribe();
+    let mut session = Session {
+        gateway: &gateway,
+        writer,
+        nick: None,
+        user: false,
+        password: None,
+        registered: false,
+    };
+
+    let mut lines = BufReader::new(reader).lines();
+    loop {
+        let sent = tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) => match parse_line(&line) {
+                    Some(message) => session.handle(message).await,
+                    None => Ok(true),
+                },
+                _ => break,
+            },
+            event = events.recv(), if session.registered => match event {
+                Ok(event) => session.deliver(&event).await.map(|()| true),
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!("IRC client missed {missed} events");
+                    Ok(true)
+                }
+                Err(RecvError::Closed) => break,
+            },
+        };
+        match sent {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => {
+                tracing::debug!("IRC client disconnected: {e}");
+                break;
+            }
+        }
+    }
+}
+
+/// One client connection.
+struct Session<'a, T: Transport> {
+    gateway: &'a Gateway<T>,
+    writer: OwnedWriteHalf,
+    nick: Option<String>,
+    user: bool,
+    password: Option<String>,
+    registered: bool,
+}
+
+impl<T: Transport> Session<'_, T> {
+    /// Returns the client's nick, `*` before it has one.
+    fn nick(&self) -> &str {
+        self.nick.as_deref().unwrap_or("*")
+    }
+
+    /// Sends a raw line.
+    async fn send(&mut self, line: &str) -> std::io::Result<()> {
+        self.writer.write_all(line.as_bytes()).await?;
+        self.writer.write_all(b"\r\n").await
+    }
+
+    /// Sends a numeric reply.
+    async fn reply(&mut self, code: &str, params: &str) -> std::io::Result<()> {
+        let line = format!(":{SERVER} {code} {} {params}", self.nick());
+        self.send(&line).await
+    }
+
+    /// Sends a notice from the server.
+    async fn notice(&mut self, text: &str) -> std::io::Result<()> {
+        let line = format!(":{SERVER} NOTICE {} :{}", self.nick(), clean(text));
+        self.send(&line).await
+    }
+
+    /// Delivers a device event, if it maps to an IRC message.
+    async fn deliver(&mut self, event: &Value) -> std::io::Result<()> {
+        let nicks = self.contact_nicks().await;
+        match event_line(event, self.nick(), &nicks) {
+            Some(line) => self.send(&line).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Handles a client message. Returns false when the connection should close.
+    async fn handle(&mut self, message: IrcMessage) -> std::io::Result<bool> {
+        let params = message.params;
+        let first = params.first().map(String::as_str);
+
+        match message.command.as_str() {
+            "CAP" => match first {
+                Some("LS") => self.send(&format!(":{SERVER} CAP * LS :")).await?,
+                Some("REQ") => {
+                    let caps = params.get(1).map_or("", String::as_str);
+                    self.send(&format!(":{SERVER} CAP * NAK :{caps}")).await?;
+                }
+                _ => {}
+            },
+            "PASS" => self.password = first.map(str::to_string),
+            "NICK" => return self.set_nick(first).await,
+            "USER" => {
+                self.user = true;
+                return self.try_register().await;
+            }
+            "PING" => {
+                let token = first.unwrap_or(SERVER).to_string();
+                self.send(&format!(":{SERVER} PONG {SERVER} :{token}"))
+                    .await?;
+            }
+            "PONG" | "NOTICE" => {}
+            "QUIT" => {
+                self.send("ERROR :Closing link").await?;
+                return Ok(false);
+            }
+            _ if !self.registered => {
+                self.reply("451", ":You have not registered").await?;
+            }
+            "PRIVMSG" => match (first, params.get(1)) {
+                (Some(target), Some(text)) => {
+                    let (target, text) = (target.to_string(), text.clone());
+                    self.privmsg(&target, &text).await?;
+                }
+                (None, _) => self.reply("411", ":No recipient given (PRIVMSG)").await?,
+                (Some(_), None) => self.reply("412", ":No text to send").await?,
+            },
+            "JOIN" => {
+                for channel in first.unwrap_or_default().split(',') {
+                    if !channel.is_empty() && channel != "0" {
+                        self.join(channel).await?;
+                    }
+                }
+            }
+            "PART" => {
+                let nick = self.nick().to_string();
+                for channel in first.unwrap_or_default().split(',') {
+                    self.send(&format!("{}PART {channel}", source(&nick)))
+                        .await?;
+                }
+            }
+            "NAMES" => {
+                if let Some(channel) = first {
+                    let channel = channel.to_string();
+                    self.names(&channel).await?;
+                }
+            }
+            "LIST" => self.list().await?,
+            "MODE" => match first {
+                Some(target) if target.starts_with('#') => {
+                    let target = target.to_string();
+                    self.reply("324", &format!("{target} +nt")).await?;
+                }
+                _ => self.reply("221", "+i").await?,
+            },
+            "WHO" => {
+                let target = first.unwrap_or("*").to_string();
+                self.reply("315", &format!("{target} :End of WHO list"))
+                    .await?;
+            }
+            "WHOIS" => {
+                if let Some(target) = params.last() {
+                    let target = target.clone();
+                    self.whois(&target).await?;
+                }
+            }
+            command => {
+                let command = command.to_string();
+                self.reply("421", &format!("{command} :Unknown command"))
+                    .await?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Handles `NICK`.
+    async fn set_nick(&mut self, nick: Option<&str>) -> std::io::Result<bool> {
+        let Some(new) = nick.filter(|n| !n.is_empty()) else {
+            self.reply("431", ":No nickname given").await?;
+            return Ok(true);
+        };
+        if self.registered {
+            let line = format!("{}NICK :{new}", source(self.nick()));
+            self.send(&line).await?;
+        }
+        self.nick = Some(new.to_string());
+        self.try_register().await
+    }
+
+    /// Completes the registration once both `NICK` and `USER` were received.
+    async fn try_register(&mut self) -> std::io::Result<bool> {
+        if self.registered || !self.user || self.nick.is_none() {
+            return Ok(true);
+        }
+
+        if let Some(expected) = &self.gateway.password
+            && !self
+                .password
+                .as_deref()
+                .is_some_and(|given| tokens_match(given, expected))
+        {
+            self.reply("464", ":Password incorrect").await?;
+            self.send("ERROR :Closing link (password incorrect)")
+                .await?;
+            return Ok(false);
+        }
+
+        self.registered = true;
+        let device = self
+            .gateway
+            .ctx
+            .device_name
+            .clone()
+            .unwrap_or_else(|| "device".into());
+        let nick = self.nick().to_string();
+        self.reply(
+            "001",
+            &format!(":Welcome to the MeshCore IRC gateway {nick}"),
+        )
+        .await?;
+        self.reply("002", &format!(":Your host is {SERVER}, bridging {device}"))
+            .await?;
+        self.reply(
+            "004",
+            &format!("{SERVER} {} i nt", env!("CARGO_PKG_VERSION")),
+        )
+        .await?;
+        self.reply("422", ":MOTD File is missing").await?;
+
+        let contacts = self.contact_nicks().await;
+        let mut names: Vec<&String> = contacts.keys().collect();
+        names.sort();
+        let names: Vec<&str> = names.into_iter().map(String::as_str).collect();
+        self.notice(&format!("Contacts: {}", names.join(" ")))
+            .await?;
+
+        // Every channel slot is joined right away
+        for channel in self.channels().await.into_values() {
+            self.join(&channel).await?;
+        }
+        Ok(true)
+    }
+
+    /// Returns the IRC names of the known channel slots.
+    async fn channels(&self) -> HashMap<u8, String> {
+        self.gateway
+            .ctx
+            .state
+            .lock()
+            .await
+            .channels
+            .iter()
+            .map(|(index, name)| (*index, irc_channel(*index, Some(name))))
+            .collect()
+    }
+
+    /// Returns the contacts by unique nick, with their public keys.
+    async fn contact_nicks(&self) -> HashMap<String, String> {
+        let contacts = self.gateway.ctx.client.lock().await.contacts().await;
+        contact_nicks(
+            contacts
+                .values()
+                .map(|contact| (contact.name.as_str(), contact.public_key.to_hex())),
+        )
+    }
+
+    /// Resolves an IRC channel name to a channel slot.
+    async fn channel_index(&self, channel: &str) -> Result<u8> {
+        let found = self
+            .channels()
+            .await
+            .into_iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(channel));
+        match found {
+            Some((index, _)) => Ok(index),
+            None => self.gateway.ctx.get_channel_index(channel).await,
+        }
+    }
+
+    /// Handles `JOIN` of one channel.
+    async fn join(&mut self, channel: &str) -> std::io::Result<()> {
+        if self.channel_index(channel).await.is_err() {
+            return self
+                .reply("403", &format!("{channel} :No such channel"))
+                .await;
+        }
+        let line = format!("{}JOIN {channel}", source(self.nick()));
+        self.send(&line).await?;
+        self.reply("331", &format!("{channel} :No topic is set"))
+            .await?;
+        self.names(channel).await
+    }
+
+    /// Sends the member list of a channel: just the client, as the mesh has no member list.
+    async fn names(&mut self, channel: &str) -> std::io::Result<()> {
+        let nick = self.nick().to_string();
+        self.reply("353", &format!("= {channel} :{nick}")).await?;
+        self.reply("366", &format!("{channel} :End of NAMES list"))
+            .await
+    }
+
+    /// Handles `LIST`.
+    async fn list(&mut self) -> std::io::Result<()> {
+        let mut channels: Vec<(u8, String)> = self.channels().await.into_iter().collect();
+        channels.sort();
+        self.reply("321", "Channel :Users Name").await?;
+        for (index, channel) in channels {
+            self.reply("322", &format!("{channel} 0 :Channel slot {index}"))
+                .await?;
+        }
+        self.reply("323", ":End of LIST").await
+    }
+
+    /// Handles `WHOIS` of a contact.
+    async fn whois(&mut self, target: &str) -> std::io::Result<()> {
+        let contacts = self.contact_nicks().await;
+        match contacts
+            .iter()
+            .find(|(nick, _)| nick.eq_ignore_ascii_case(target))
+        {
+            Some((nick, key)) => {
+                let line = format!("{nick} {} {SERVER} * :{key}", &key[..12.min(key.len())]);
+                self.reply("311", &line).await?;
+            }
+            None => {
+                self.reply("401", &format!("{target} :No such nick/channel"))
+                    .await?;
+            }
+        }
+        self.reply("318", &format!("{target} :End of WHOIS list"))
+            .await
+    }
+
+    /// Sends a `PRIVMSG` to the mesh, reporting failures to the client.
+    async fn privmsg(&mut self, target: &str, text: &str) -> std::io::Result<()> {
+        let ctx = &self.gateway.ctx;
+        let message = [text.to_string()];
+        let (result, _) = if target.starts_with('#') {
+            match self.channel_index(target).await {
+                Ok(index) => output::capture(ctx.cmd_chan(index, &message)).await,
+                Err(e) => (Err(e), String::new()),
+            }
+        } else {
+            let contacts = self.contact_nicks().await;
+            let contact = contacts
+                .iter()
+                .find(|(nick, _)| nick.eq_ignore_ascii_case(target))
+                .map_or(target, |(_, key)| key.as_str());
+            output::capture(ctx.cmd_msg(contact, &message, false, 0)).await
+        };
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(CliError::ContactNotFound(_)) => {
+                self.reply("401", &format!("{target} :No such nick/channel"))
+                    .await
+            }
+            Err(CliError::ChannelNotFound(_)) => {
+                self.reply("403", &format!("{target} :No such channel"))
+                    .await
+            }
+            Err(e) => self.notice(&format!("{target}: {e}")).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(
+            parse_line("PRIVMSG #rust :hello there\r\n"),
+            Some(IrcMessage {
+                command: "PRIVMSG".into(),
+                params: vec!["#rust".into(), "hello there".into()],
+            })
+        );
+        assert_eq!(
+            parse_line(":alice!a@host nick bob"),
+            Some(IrcMessage {
+                command: "NICK".into(),
+                params: vec!["bob".into()],
+            })
+        );
+        assert_eq!(
+            parse_line("USER alice 0 * :Alice Smith"),
+            Some(IrcMessage {
+                command: "USER".into(),
+                params: vec!["alice".into(), "0".into(), "*".into(), "Alice Smith".into()],
+            })
+        );
+        assert_eq!(parse_line(""), None);
+        assert_eq!(parse_line(":prefix-only"), None);
+    }
+
+    #[test]
+    fn test_nick() {
+        assert_eq!(nick("Alice"), "Alice");
+        assert_eq!(nick("Bob Repeater"), "Bob_Repeater");
+        assert_eq!(nick("1stNode"), "_1stNode");
+        assert_eq!(nick("Ünïcode 📡"), "_n_code__");
+        assert_eq!(nick(""), "_");
+    }
+
+    #[test]
+    fn test_irc_channel() {
+        assert_eq!(irc_channel(2, Some("#rust")), "#rust");
+        assert_eq!(irc_channel(0, Some("Public")), "#Public");
+        assert_eq!(irc_channel(1, Some("my group")), "#my_group");
+        assert_eq!(irc_channel(3, None), "#3");
+    }
+
+    #[test]
+    fn test_contact_nicks() {
+        let nicks = contact_nicks([
+            ("Alice", "aa11".repeat(16)),
+            ("Ünï", "ab12cd".repeat(10) + "0000"),
+            ("_n_", "ab12ef".repeat(10) + "0000"),
+            ("_N_", "99".repeat(32)),
+        ]);
+        assert_eq!(nicks.len(), 4);
+        assert_eq!(nicks["Alice"], "aa11".repeat(16));
+        assert_eq!(nicks["_n_|ab12cd"], "ab12cd".repeat(10) + "0000");
+        assert_eq!(nicks["_n_|ab12ef"], "ab12ef".repeat(10) + "0000");
+        assert_eq!(nicks["_N_|999999"], "99".repeat(32));
+    }
+
+    #[test]
+    fn test_event_line() {
+        let nicks = contact_nicks([
+            ("Ünï", "ab12cd".repeat(10) + "0000"),
+            ("_n_", "ef34".repeat(16)),
+        ]);
+        let message = json!({
+            "type": "contact_message",
+            "sender": "Bob Repeater",
+            "text": "hi\r\nQUIT",
+        });
+        assert_eq!(
+            event_line(&message, "alice", &nicks).unwrap(),
+            ":Bob_Repeater!Bob_Repeater@meshcore PRIVMSG alice :hi  QUIT"
+        );
+
+        let collided = json!({
+            "type": "contact_message",
+            "sender": "_n_",
+            "sender_prefix": "ef34ef34ef34",
+            "text": "hello",
+        });
+        assert_eq!(
+            event_line(&collided, "alice", &nicks).unwrap(),
+            ":_n_|ef34!_n_|ef34@meshcore PRIVMSG alice :hello"
+        );
+
+        let channel = json!({
+            "type": "channel_message",
+            "channel_index": 2,
+            "channel_name": "#rust",
+            "sender": null,
+            "text": "anyone?",
+        });
+        assert_eq!(
+            event_line(&channel, "alice", &nicks).unwrap(),
+            ":unknown!unknown@meshcore PRIVMSG #rust :anyone?"
+        );
+
+        assert_eq!(
+            event_line(&json!({ "type": "ack", "code": "1" }), "alice", &nicks),
+            None
+        );
+    }
+}
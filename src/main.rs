@@ -1,34 +1,60 @@
 //! meshcore-cli-rs - Rust CLI for `MeshCore` companion radios.
 
+mod bot;
+mod capture;
 mod cli;
 mod commands;
 mod config;
+#[cfg(unix)]
+mod daemon;
+mod detect;
 mod display;
 mod error;
+mod events;
+mod history;
+mod hooks;
 mod interactive;
+mod ircd;
+mod mqtt;
+mod output;
+mod serve;
+mod telemetry;
+mod web;
+
+use std::path::{Path, PathBuf};
 
 use clap::Parser;
 use meshcore::MeshCore;
-use meshcore::transport::serial::SerialConfig;
+use meshcore::transport::Transport;
+use meshcore::transport::serial::{SerialConfig, SerialTransport};
+use meshcore::transport::tcp::{TcpConfig, TcpTransport};
 use tracing_subscriber::EnvFilter;
 
-use cli::{Cli, Command};
+use capture::{CaptureTransport, ReplayTransport};
+use cli::{Cli, Command, OutputFormat};
 use commands::CommandContext;
-use config::Config;
-use display::Display;
+use config::{Config, DEFAULT_BAUDRATE, Profile};
+use display::{Display, OutputMode};
 use error::{CliError, Result};
+use history::MessageStore;
+use hooks::Hooks;
 
 #[tokio::main]
 async fn main() {
-    if let Err(e) = run().await {
-        eprintln!("Error: {e}");
-        std::process::exit(1);
+    let cli = Cli::parse();
+    let json = cli.json || matches!(cli.format, Some(OutputFormat::Json | OutputFormat::Ndjson));
+
+    if let Err(e) = run(cli).await {
+        if json {
+            println!("{}", e.to_json());
+        } else {
+            eprintln!("Error: {e}");
+        }
+        std::process::exit(e.exit_code());
     }
 }
 
-async fn run() -> Result<()> {
-    let cli = Cli::parse();
-
+async fn run(cli: Cli) -> Result<()> {
     // Setup logging - respect RUST_LOG if set, otherwise use --debug flag
     let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
         if cli.debug {
@@ -42,99 +68,382 @@ async fn run() -> Result<()> {
         .with_target(false)
         .init();
 
-    // Handle list command
-    if cli.list {
-        list_serial_ports()?;
-        return Ok(());
-    }
+    // Load config file (CLI flags take precedence over it). A broken file is an error
+    // rather than silently dropping the user's settings.
+    let config = Config::load()?;
+
+    // Load the device profile (CLI flags take precedence over it, it over the config file)
+    let profile = cli.profile.as_deref().map(Profile::load).transpose()?;
 
     // Determine color setting
-    let color = cli.color.unwrap_or(true);
+    let color = cli.color.unwrap_or(config.color);
 
     // Create display
-    let display = Display::new(cli.json, color);
+    let format = cli.format.unwrap_or(if cli.json {
+        OutputFormat::Json
+    } else {
+        OutputFormat::Human
+    });
+    let mode = match format {
+        OutputFormat::Human => OutputMode::Human,
+        OutputFormat::Json => OutputMode::Json,
+        OutputFormat::Ndjson => OutputMode::Ndjson,
+        OutputFormat::Csv => OutputMode::Csv,
+        OutputFormat::Table => OutputMode::Table,
+    };
+    let display = Display::new(cli.json, color)
+        .with_mode(mode)
+        .with_profile(profile.as_ref().map(|p| p.name.clone()));
+
+    // Config commands only touch the config file, no device needed
+    if let Some(Command::Config { action }) = &cli.command {
+        return commands::config::execute_config(&display, action);
+    }
 
-    // If no command and no serial port, show help
-    if cli.command.is_none() && cli.serial.is_none() {
-        // Enter interactive mode with device selection
-        println!("No serial port specified. Use -s <port> to specify a serial port.");
-        println!("Use -l to list available serial ports.");
-        return Ok(());
+    // Decoding a capture doesn't need a device either
+    if let Some(Command::DecodeCapture { file }) = &cli.command {
+        return capture::decode(&display, file);
+    }
+
+    // Proxy the command through a running daemon instead of opening the device
+    if let Some(socket) = &cli.remote {
+        let command = cli
+            .command
+            .ok_or_else(|| CliError::InvalidArgument("--remote needs a command to run".into()))?;
+        return run_remote(socket, command, &display).await;
+    }
+
+    // Drive the session from a capture instead of a radio
+    if let Some(path) = &cli.replay {
+        let ctx = connect_replay(path, display)
+            .await?
+            .with_hooks(Hooks::from_config(&config.hooks));
+        return run_session(
+            &ctx,
+            &config,
+            profile.as_ref(),
+            cli.command,
+            !cli.no_reconnect,
+        )
+        .await;
     }
 
-    // Get serial port
-    let port = cli
-        .serial
-        .ok_or_else(|| CliError::Serial("No serial port specified. Use -s <port>".into()))?;
+    let baudrate = cli
+        .baudrate
+        .or(profile.as_ref().and_then(|p| p.baudrate))
+        .or(config.default_baudrate)
+        .unwrap_or(DEFAULT_BAUDRATE);
 
-    // Connect to device
-    let ctx = connect_device(&port, cli.baudrate, display).await?;
+    // Handle list command
+    if cli.list {
+        return list_serial_ports(baudrate, cli.probe).await;
+    }
 
-    // Run init scripts if not in JSON mode
-    if !cli.json {
-        run_init_scripts(&ctx).await?;
+    // `-s auto`, or a device name without a port, probes the serial ports
+    let auto = cli.serial.as_deref() == Some(detect::AUTO_PORT)
+        || (cli.serial.is_none() && cli.device_name.is_some());
+    let serial_requested = cli.serial.is_some() || cli.device_name.is_some();
+    let serial = if auto {
+        None
+    } else {
+        cli.serial
+            .or_else(|| profile.as_ref().and_then(|p| p.port.clone()))
+            .or_else(|| config.default_port.clone())
+    };
+
+    // Connect over TCP if requested, otherwise over the serial port
+    let tcp = cli.tcp.or_else(|| {
+        profile
+            .as_ref()
+            .and_then(|p| p.tcp.clone())
+            .filter(|_| !serial_requested)
+    });
+    if let Some(addr) = &tcp {
+        let ctx = connect_tcp(
+            addr,
+            display,
+            config.message_history,
+            cli.capture.as_deref(),
+        )
+        .await?
+        .with_hooks(Hooks::from_config(&config.hooks));
+        return run_session(
+            &ctx,
+            &config,
+            profile.as_ref(),
+            cli.command,
+            !cli.no_reconnect,
+        )
+        .await;
     }
 
+    let port = if let Some(port) = serial {
+        port
+    } else {
+        if !display.is_machine() {
+            println!("Searching for MeshCore devices...");
+        }
+        let device =
+            detect::find_device(baudrate, cli.device_name.as_deref(), !display.is_machine())
+                .await?;
+        if !display.is_machine() {
+            println!("Using {} on {}", device.name, device.port);
+        }
+        device.port
+    };
+
+    let ctx = connect_device(
+        &port,
+        baudrate,
+        display,
+        config.message_history,
+        cli.capture.as_deref(),
+    )
+    .await?
+    .with_hooks(Hooks::from_config(&config.hooks));
+    run_session(
+        &ctx,
+        &config,
+        profile.as_ref(),
+        cli.command,
+        !cli.no_reconnect,
+    )
+    .await
+}
+
+/// Runs init scripts and then the given command or interactive mode.
+async fn run_session<T: Transport>(
+    ctx: &CommandContext<T>,
+    config: &Config,
+    profile: Option<&Profile>,
+    command: Option<Command>,
+    reconnect: bool,
+) -> Result<()> {
+    // Apply persisted contact timeouts, profile ones last so they win
+    {
+        let mut state = ctx.state.lock().await;
+        state
+            .contact_timeouts
+            .extend(config.contact_timeouts.clone());
+        if let Some(profile) = profile {
+            state
+                .contact_timeouts
+                .extend(profile.contact_timeouts.clone());
+        }
+    }
+
+    // Apply the profile's default scope
+    if let Some(scope) = profile.and_then(|p| p.scope.as_deref())
+        && let Err(e) = ctx.set_scope(scope).await
+    {
+        tracing::warn!("Failed to set profile scope {scope}: {e}");
+    }
+
+    // Init script output would corrupt machine-readable output
+    if !ctx.display.is_machine() {
+        run_init_scripts(ctx, profile).await?;
+    }
+
+    // Long-running sessions reconnect when the device drops off
+    let supervisor = if reconnect && is_long_running(command.as_ref()) {
+        Some(ctx.start_supervisor().await)
+    } else {
+        None
+    };
+
+    // Record incoming messages while the session runs
+    let recorder = ctx.start_recorder().await;
+
     // Execute command or enter interactive mode
-    match cli.command {
-        Some(cmd) => execute_command(&ctx, cmd).await?,
-        None => {
-            // Enter interactive mode
-            interactive::run(&ctx).await?;
+    let result = match command {
+        Some(Command::Chat) | None => interactive::run(ctx).await,
+        Some(Command::Daemon { socket }) => run_daemon(ctx, socket).await,
+        Some(Command::Bot { rules }) => bot::run(ctx, &rules).await,
+        Some(Command::Mqtt {
+            broker,
+            prefix,
+            username,
+            password,
+        }) => {
+            let credentials = username.as_deref().zip(password.as_deref());
+            mqtt::run(ctx, &broker, &prefix, credentials).await
         }
+        Some(Command::Serve { listen, token }) => serve::run(ctx, &listen, token, false).await,
+        Some(Command::Web { listen, token }) => serve::run(ctx, &listen, token, true).await,
+        Some(Command::Ircd { listen, password }) => ircd::run(ctx, &listen, password).await,
+        Some(cmd @ Command::ChatTo { .. }) => match ctx.execute(cmd).await {
+            Ok(()) => interactive::run(ctx).await,
+            Err(e) => Err(e),
+        },
+        Some(cmd) => ctx.execute(cmd).await,
+    };
+
+    if let Some(recorder) = recorder {
+        recorder.stop().await;
+    }
+    if let Some(supervisor) = supervisor {
+        supervisor.stop();
     }
 
-    Ok(())
+    result
+}
+
+/// Returns true for commands that keep the session open until the user quits.
+fn is_long_running(command: Option<&Command>) -> bool {
+    matches!(
+        command,
+        None | Some(
+            Command::Chat
+                | Command::ChatTo { .. }
+                | Command::Daemon { .. }
+                | Command::Bot { .. }
+                | Command::Mqtt { .. }
+                | Command::Serve { .. }
+                | Command::Web { .. }
+                | Command::Ircd { .. }
+                | Command::MsgsSubscribe
+        )
+    )
+}
+
+/// Runs the daemon on the given or default socket.
+#[cfg(unix)]
+async fn run_daemon<T: Transport>(ctx: &CommandContext<T>, socket: Option<PathBuf>) -> Result<()> {
+    let socket = socket
+        .or_else(Config::socket_file)
+        .ok_or_else(|| CliError::Daemon("No socket path available, use --socket".into()))?;
+    daemon::run(ctx, &socket).await
 }
 
-/// Connects to a device via serial port.
-async fn connect_device(port: &str, baudrate: u32, display: Display) -> Result<CommandContext> {
+#[cfg(not(unix))]
+async fn run_daemon<T: Transport>(
+    _ctx: &CommandContext<T>,
+    _socket: Option<PathBuf>,
+) -> Result<()> {
+    Err(CliError::Daemon(
+        "Daemon mode requires Unix domain sockets".into(),
+    ))
+}
+
+/// Executes a command through a running daemon.
+#[cfg(unix)]
+async fn run_remote(socket: &Path, command: Command, display: &Display) -> Result<()> {
+    daemon::execute_remote(socket, command, display).await
+}
+
+#[cfg(not(unix))]
+async fn run_remote(_socket: &Path, _command: Command, _display: &Display) -> Result<()> {
+    Err(CliError::Daemon(
+        "Daemon mode requires Unix domain sockets".into(),
+    ))
+}
+
+/// Connects to a device via serial port, capturing frames to `capture` if given.
+async fn connect_device(
+    port: &str,
+    baudrate: u32,
+    display: Display,
+    message_history: bool,
+    capture: Option<&Path>,
+) -> Result<CommandContext<CaptureTransport<SerialTransport>>> {
     let config = SerialConfig::new(port).baud_rate(baudrate);
+    let transport = CaptureTransport::new(SerialTransport::new(config), capture)?;
+    let client = MeshCore::new(transport);
 
-    let mut client = MeshCore::with_serial_config(config);
+    init_context(client, display, message_history)
+        .await
+        .map_err(|e| CliError::Serial(format!("Failed to connect to {port}: {e}")))
+}
 
-    // Connect and get self info
-    let self_info = client
-        .connect()
+/// Connects to a device via TCP (Wi-Fi companion firmware, ser2net, ...).
+async fn connect_tcp(
+    addr: &str,
+    display: Display,
+    message_history: bool,
+    capture: Option<&Path>,
+) -> Result<CommandContext<CaptureTransport<TcpTransport>>> {
+    let config = TcpConfig::new(addr);
+    let transport = CaptureTransport::new(TcpTransport::new(config), capture)?;
+    let client = MeshCore::new(transport);
+
+    init_context(client, display, message_history)
         .await
-        .map_err(|e| CliError::Serial(format!("Failed to connect to {port}: {e}")))?;
+        .map_err(|e| CliError::Tcp(format!("Failed to connect to {addr}: {e}")))
+}
+
+/// Replays a capture file in place of a device.
+///
+/// Replayed messages are not recorded in the message history.
+async fn connect_replay(path: &Path, display: Display) -> Result<CommandContext<ReplayTransport>> {
+    let client = MeshCore::new(ReplayTransport::open(path)?);
+
+    init_context(client, display, false)
+        .await
+        .map_err(|e| CliError::Command(format!("Failed to replay {}: {e}", path.display())))
+}
+
+/// Connects the client, preloads contacts and wraps it in a command context.
+///
+/// The message history is keyed by the device public key, so it's opened here.
+async fn init_context<T: Transport>(
+    mut client: MeshCore<T>,
+    display: Display,
+    message_history: bool,
+) -> std::result::Result<CommandContext<T>, meshcore::Error> {
+    // Connect and get self info
+    let self_info = client.connect().await?;
 
     // Preload contacts so they're available for contact-based commands
     if let Err(e) = client.get_contacts().await {
         tracing::debug!("Failed to preload contacts: {e}");
     }
 
-    let ctx = CommandContext::new(client, display, Some(self_info.name.clone()));
+    let store = if message_history {
+        MessageStore::for_device(&self_info.public_key.to_hex())
+    } else {
+        None
+    };
 
-    Ok(ctx)
+    Ok(CommandContext::new(client, display, Some(self_info.name.clone())).with_store(store))
 }
 
-/// Lists available serial ports.
-fn list_serial_ports() -> Result<()> {
+/// Lists available serial ports, with the radios answering on them if `probe` is set.
+async fn list_serial_ports(baudrate: u32, probe: bool) -> Result<()> {
     let ports = meshcore::transport::serial::list_ports()
         .map_err(|e| CliError::Serial(format!("Failed to list ports: {e}")))?;
 
     if ports.is_empty() {
         println!("No serial ports found");
+        return Ok(());
+    }
+
+    // Probing opens every port, which can disturb unrelated serial devices
+    let devices = if probe {
+        detect::probe_ports(baudrate).await?
     } else {
-        println!("Available serial ports:");
-        for port in ports {
-            println!("  {port}");
+        Vec::new()
+    };
+    println!("Available serial ports:");
+    for port in ports {
+        match devices.iter().find(|d| d.port == port) {
+            Some(device) => println!("  {port}  {} ({})", device.name, device.public_key),
+            None => println!("  {port}"),
         }
     }
 
     Ok(())
 }
 
-/// Runs init scripts.
-async fn run_init_scripts(ctx: &CommandContext) -> Result<()> {
+/// Runs the global, device and profile init scripts, in that order.
+async fn run_init_scripts<T: Transport>(
+    ctx: &CommandContext<T>,
+    profile: Option<&Profile>,
+) -> Result<()> {
     // Run global init script
     if let Ok(lines) = Config::read_init_script() {
         for line in lines {
-            if let Some(cmd) = parse_command_line(&line) {
-                if let Err(e) = execute_command(ctx, cmd).await {
-                    tracing::warn!("Init script error: {e}");
-                }
+            if let Err(e) = ctx.execute_line(&line).await {
+                tracing::warn!("Init script error: {e}");
             }
         }
     }
@@ -143,346 +452,21 @@ async fn run_init_scripts(ctx: &CommandContext) -> Result<()> {
     if let Some(name) = &ctx.device_name {
         if let Ok(lines) = Config::read_device_init_script(name) {
             for line in lines {
-                if let Some(cmd) = parse_command_line(&line) {
-                    if let Err(e) = execute_command(ctx, cmd).await {
-                        tracing::warn!("Device init script error: {e}");
-                    }
+                if let Err(e) = ctx.execute_line(&line).await {
+                    tracing::warn!("Device init script error: {e}");
                 }
             }
         }
     }
 
-    Ok(())
-}
-
-/// Parses a command line string into a Command.
-fn parse_command_line(line: &str) -> Option<Command> {
-    let parts: Vec<&str> = line.split_whitespace().collect();
-    if parts.is_empty() {
-        return None;
-    }
-
-    let cmd = parts[0].to_lowercase();
-    let cmd_str = cmd.as_str();
-
-    match cmd_str {
-        // General commands
-        "infos" | "i" => Some(Command::Infos),
-        "ver" | "v" | "q" | "query" => Some(Command::Ver),
-        "battery" => Some(Command::Battery),
-        "clock" => Some(Command::Clock { sync: false }),
-        "sync_time" | "st" => Some(Command::SyncTime),
-        "reboot" => Some(Command::Reboot),
-        "wait_key" | "wk" => Some(Command::WaitKey),
-        "self_telemetry" | "t" => Some(Command::SelfTelemetry),
-        "card" | "e" => Some(Command::Card),
-
-        // Sleep
-        "sleep" | "s" if parts.len() > 1 => {
-            parts[1].parse().ok().map(|secs| Command::Sleep { secs })
-        }
-
-        // Contact commands
-        "contacts" | "list" | "lc" => Some(Command::Contacts),
-        "reload_contacts" | "rc" => Some(Command::ReloadContacts),
-        "advert" | "a" => Some(Command::Advert),
-        "floodadv" | "flood_advert" => Some(Command::FloodAdv),
-        "pending_contacts" => Some(Command::PendingContacts),
-        "flush_pending" => Some(Command::FlushPending),
-
-        "contact_info" | "ci" if parts.len() > 1 => Some(Command::ContactInfo {
-            contact: parts[1].to_string(),
-        }),
-        "contact_timeout" if parts.len() > 2 => {
-            parts[2]
-                .parse()
-                .ok()
-                .map(|timeout| Command::ContactTimeout {
-                    contact: parts[1].to_string(),
-                    timeout,
-                })
-        }
-        "share_contact" | "sc" if parts.len() > 1 => Some(Command::ShareContact {
-            contact: parts[1].to_string(),
-        }),
-        "export_contact" | "ec" => Some(Command::ExportContact {
-            contact: parts.get(1).map(|s| (*s).to_string()),
-        }),
-        "import_contact" | "ic" if parts.len() > 1 => Some(Command::ImportContact {
-            uri: parts[1].to_string(),
-        }),
-        "remove_contact" if parts.len() > 1 => Some(Command::RemoveContact {
-            contact: parts[1].to_string(),
-        }),
-        "add_pending" if parts.len() > 1 => Some(Command::AddPending {
-            pending: parts[1].to_string(),
-        }),
-        "path" if parts.len() > 1 => Some(Command::Path {
-            contact: parts[1].to_string(),
-        }),
-        "disc_path" | "dp" if parts.len() > 1 => Some(Command::DiscPath {
-            contact: parts[1].to_string(),
-        }),
-        "reset_path" | "rp" if parts.len() > 1 => Some(Command::ResetPath {
-            contact: parts[1].to_string(),
-        }),
-        "change_path" | "cp" if parts.len() > 2 => Some(Command::ChangePath {
-            contact: parts[1].to_string(),
-            path: parts[2].to_string(),
-        }),
-        "change_flags" | "cf" if parts.len() > 2 => Some(Command::ChangeFlags {
-            contact: parts[1].to_string(),
-            flags: parts[2].to_string(),
-        }),
-
-        // Messaging commands
-        "msg" | "m" if parts.len() > 2 => Some(Command::Msg {
-            name: parts[1].to_string(),
-            message: parts[2..].iter().map(|s| (*s).to_string()).collect(),
-            wait: false,
-            timeout: 30,
-        }),
-        "chan" | "ch" if parts.len() > 2 => parts[1].parse().ok().map(|channel| Command::Chan {
-            channel,
-            message: parts[2..].iter().map(|s| (*s).to_string()).collect(),
-        }),
-        "public" | "dch" if parts.len() > 1 => Some(Command::Public {
-            message: parts[1..].iter().map(|s| (*s).to_string()).collect(),
-        }),
-        "recv" | "r" => Some(Command::Recv),
-        "wait_msg" | "wm" => Some(Command::WaitMsg {
-            timeout: parts.get(1).and_then(|s| s.parse().ok()).unwrap_or(30),
-        }),
-        "trywait_msg" | "wmt" if parts.len() > 1 => parts[1]
-            .parse()
-            .ok()
-            .map(|timeout| Command::TrywaitMsg { timeout }),
-        "wmt8" => Some(Command::Wmt8),
-        "sync_msgs" | "sm" => Some(Command::SyncMsgs),
-        "wait_ack" | "wa" => Some(Command::WaitAck {
-            timeout: parts.get(1).and_then(|s| s.parse().ok()).unwrap_or(30),
-        }),
-
-        // Channel commands
-        "get_channels" | "gc" => Some(Command::GetChannels),
-        "get_channel" if parts.len() > 1 => Some(Command::GetChannel {
-            channel: parts[1].to_string(),
-        }),
-        "set_channel" if parts.len() > 2 => Some(Command::SetChannel {
-            number: parts[1].parse().ok()?,
-            name: parts[2].to_string(),
-            key: parts.get(3).map(|s| (*s).to_string()),
-        }),
-        "add_channel" if parts.len() > 1 => Some(Command::AddChannel {
-            name: parts[1].to_string(),
-            key: parts.get(2).map(|s| (*s).to_string()),
-        }),
-        "remove_channel" if parts.len() > 1 => Some(Command::RemoveChannel {
-            channel: parts[1].to_string(),
-        }),
-        "scope" if parts.len() > 1 => Some(Command::Scope {
-            scope: parts[1].to_string(),
-        }),
-
-        // Get/Set commands
-        "get" if parts.len() > 1 => Some(Command::Get {
-            param: parts[1].to_string(),
-        }),
-        "set" if parts.len() > 2 => Some(Command::Set {
-            param: parts[1].to_string(),
-            value: parts[2..].join(" "),
-        }),
-        "time" if parts.len() > 1 => parts[1].parse().ok().map(|epoch| Command::Time { epoch }),
-
-        // Repeater commands
-        "login" | "l" if parts.len() > 2 => Some(Command::Login {
-            name: parts[1].to_string(),
-            password: parts[2].to_string(),
-        }),
-        "logout" if parts.len() > 1 => Some(Command::Logout {
-            name: parts[1].to_string(),
-        }),
-        "cmd" | "c" if parts.len() > 2 => Some(Command::Cmd {
-            name: parts[1].to_string(),
-            command: parts[2..].iter().map(|s| (*s).to_string()).collect(),
-            wait: false,
-            timeout: 30,
-        }),
-        "req_status" | "rs" if parts.len() > 1 => Some(Command::ReqStatus {
-            name: parts[1].to_string(),
-        }),
-        "req_neighbours" | "rn" if parts.len() > 1 => Some(Command::ReqNeighbours {
-            name: parts[1].to_string(),
-        }),
-        "req_telemetry" | "rt" if parts.len() > 1 => Some(Command::ReqTelemetry {
-            contact: parts[1].to_string(),
-        }),
-        "req_mma" | "rm" if parts.len() > 1 => Some(Command::ReqMma {
-            contact: parts[1].to_string(),
-        }),
-        "req_acl" if parts.len() > 1 => Some(Command::ReqAcl {
-            contact: parts[1].to_string(),
-        }),
-        "trace" | "tr" if parts.len() > 1 => Some(Command::Trace {
-            path: parts[1].to_string(),
-        }),
-
-        // Node discovery
-        "node_discover" | "nd" => Some(Command::NodeDiscover {
-            filter: parts.get(1).and_then(|s| s.parse().ok()).unwrap_or(0),
-        }),
-
-        // Advanced commands
-        "export_key" => Some(Command::ExportKey),
-        "import_key" if parts.len() > 1 => Some(Command::ImportKey {
-            key: parts[1].to_string(),
-        }),
-        "get_vars" => Some(Command::GetVars),
-        "set_var" if parts.len() > 2 => Some(Command::SetVar {
-            key: parts[1].to_string(),
-            value: parts[2..].join(" "),
-        }),
-        "stats" => Some(Command::Stats {
-            stats_type: match parts.get(1).map(|s| s.to_lowercase()).as_deref() {
-                Some("radio") => cli::StatsTypeArg::Radio,
-                Some("packets") => cli::StatsTypeArg::Packets,
-                _ => cli::StatsTypeArg::Core,
-            },
-        }),
-
-        _ => None,
-    }
-}
-
-/// Executes a single command.
-async fn execute_command(ctx: &CommandContext, cmd: Command) -> Result<()> {
-    match cmd {
-        // General commands
-        Command::Chat => interactive::run(ctx).await,
-        Command::ChatTo { contact } => {
-            ctx.state.lock().await.set_contact(Some(contact));
-            interactive::run(ctx).await
-        }
-        Command::Script { filename } => Box::pin(execute_script(ctx, &filename)).await,
-        Command::Infos => ctx.cmd_infos().await,
-        Command::SelfTelemetry => ctx.cmd_self_telemetry().await,
-        Command::Card => ctx.cmd_card().await,
-        Command::Ver => ctx.cmd_ver().await,
-        Command::Reboot => ctx.cmd_reboot().await,
-        Command::Sleep { secs } => ctx.cmd_sleep(secs).await,
-        Command::WaitKey => {
-            CommandContext::cmd_wait_key();
-            Ok(())
-        }
-        Command::ApplyTo { filter, commands } => ctx.cmd_apply_to(&filter, &commands).await,
-
-        // Messaging commands
-        Command::Msg {
-            name,
-            message,
-            wait,
-            timeout,
-        } => ctx.cmd_msg(&name, &message, wait, timeout).await,
-        Command::WaitAck { timeout } => ctx.cmd_wait_ack(timeout).await,
-        Command::Chan { channel, message } => ctx.cmd_chan(channel, &message).await,
-        Command::Public { message } => ctx.cmd_public(&message).await,
-        Command::Recv => ctx.cmd_recv().await,
-        Command::WaitMsg { timeout } => ctx.cmd_wait_msg(timeout).await,
-        Command::TrywaitMsg { timeout } => ctx.cmd_trywait_msg(timeout).await,
-        Command::SyncMsgs => ctx.cmd_sync_msgs().await,
-        Command::MsgsSubscribe => ctx.cmd_msgs_subscribe().await,
-        Command::GetChannels => ctx.cmd_get_channels().await,
-        Command::GetChannel { channel } => ctx.cmd_get_channel(&channel).await,
-        Command::SetChannel { number, name, key } => {
-            ctx.cmd_set_channel(number, &name, key.as_deref()).await
-        }
-        Command::RemoveChannel { channel } => ctx.cmd_remove_channel(&channel).await,
-        Command::AddChannel { name, key } => ctx.cmd_add_channel(&name, key.as_deref()).await,
-        Command::Scope { scope } => ctx.cmd_scope(&scope).await,
-
-        // Management commands
-        Command::Advert => ctx.cmd_advert(false).await,
-        Command::FloodAdv => ctx.cmd_advert(true).await,
-        Command::Get { param } => ctx.cmd_get(&param).await,
-        Command::Set { param, value } => ctx.cmd_set(&param, &value).await,
-        Command::Time { epoch } => ctx.cmd_set_time(epoch).await,
-        Command::Clock { sync } => ctx.cmd_clock(sync).await,
-        Command::SyncTime => ctx.cmd_sync_time().await,
-        Command::NodeDiscover { filter } => ctx.cmd_node_discover(filter).await,
-
-        // Contact commands
-        Command::Contacts | Command::List => ctx.cmd_contacts().await,
-        Command::ReloadContacts => ctx.cmd_reload_contacts().await,
-        Command::ContactInfo { contact } => ctx.cmd_contact_info(&contact).await,
-        Command::ContactTimeout { contact, timeout } => {
-            ctx.cmd_contact_timeout(&contact, timeout).await
-        }
-        Command::ShareContact { contact } => ctx.cmd_share_contact(&contact).await,
-        Command::ExportContact { contact } => ctx.cmd_export_contact(contact.as_deref()).await,
-        Command::ImportContact { uri } => ctx.cmd_import_contact(&uri).await,
-        Command::RemoveContact { contact } => ctx.cmd_remove_contact(&contact).await,
-        Command::Path { contact } => ctx.cmd_path(&contact).await,
-        Command::DiscPath { contact } => ctx.cmd_disc_path(&contact).await,
-        Command::ResetPath { contact } => ctx.cmd_reset_path(&contact).await,
-        Command::ChangePath { contact, path } => ctx.cmd_change_path(&contact, &path).await,
-        Command::ChangeFlags { contact, flags } => ctx.cmd_change_flags(&contact, &flags).await,
-        Command::ReqTelemetry { contact } => ctx.cmd_req_telemetry(&contact).await,
-        Command::ReqMma { contact } => ctx.cmd_req_mma(&contact).await,
-        Command::ReqAcl { contact } => ctx.cmd_req_acl(&contact).await,
-        Command::PendingContacts => ctx.cmd_pending_contacts().await,
-        Command::AddPending { pending } => ctx.cmd_add_pending(&pending).await,
-        Command::FlushPending => ctx.cmd_flush_pending().await,
-
-        // Repeater commands
-        Command::Login { name, password } => ctx.cmd_login(&name, &password).await,
-        Command::Logout { name } => ctx.cmd_logout(&name).await,
-        Command::Cmd {
-            name,
-            command,
-            wait,
-            timeout,
-        } => ctx.cmd_cmd(&name, &command, wait, timeout).await,
-        Command::Wmt8 => ctx.cmd_wmt8().await,
-        Command::ReqStatus { name } => ctx.cmd_req_status(&name).await,
-        Command::ReqNeighbours { name } => ctx.cmd_req_neighbours(&name).await,
-        Command::ReqBinary { name, data } => ctx.cmd_req_binary(&name, &data).await,
-        Command::Trace { path } => ctx.cmd_trace(&path).await,
-
-        // Advanced commands
-        Command::Battery => ctx.cmd_battery().await,
-        Command::Stats { stats_type } => ctx.cmd_stats(stats_type).await,
-        Command::ExportKey => ctx.cmd_export_key().await,
-        Command::ImportKey { key } => ctx.cmd_import_key(&key).await,
-        Command::GetVars => ctx.cmd_get_vars().await,
-        Command::SetVar { key, value } => ctx.cmd_set_var(&key, &value).await,
-    }
-}
-
-/// Executes a script file.
-async fn execute_script(ctx: &CommandContext, filename: &str) -> Result<()> {
-    let content = std::fs::read_to_string(filename).map_err(|e| CliError::Script {
-        line: 0,
-        message: format!("Failed to read script: {e}"),
-    })?;
-
-    for (line_num, line) in content.lines().enumerate() {
-        let line = line.trim();
-        if line.is_empty() || line.starts_with('#') {
-            continue;
-        }
-
-        if let Some(cmd) = parse_command_line(line) {
-            Box::pin(execute_command(ctx, cmd))
-                .await
-                .map_err(|e| CliError::Script {
-                    line: line_num + 1,
-                    message: e.to_string(),
-                })?;
-        } else {
-            return Err(CliError::Script {
-                line: line_num + 1,
-                message: format!("Unknown command: {line}"),
-            });
+    // Run profile init script
+    if let Some(profile) = profile
+        && let Ok(lines) = Config::read_profile_init_script(&profile.name)
+    {
+        for line in lines {
+            if let Err(e) = ctx.execute_line(&line).await {
+                tracing::warn!("Profile init script error: {e}");
+            }
         }
     }
 
@@ -491,40 +475,66 @@ async fn execute_script(ctx: &CommandContext, filename: &str) -> Result<()> {
 
 #[cfg(test)]
 mod tests {
-    use crate::commands::parse_time_value;
+    use std::collections::HashMap;
+
+    use crate::commands::{parse_channel_index, parse_time_value};
 
     #[test]
     fn test_parse_time_value_seconds() {
-        assert_eq!(parse_time_value("60s"), 60);
-        assert_eq!(parse_time_value("60"), 60);
-        assert_eq!(parse_time_value("0s"), 0);
+        assert_eq!(parse_time_value("60s").unwrap(), 60);
+        assert_eq!(parse_time_value("60").unwrap(), 60);
+        assert_eq!(parse_time_value("0s").unwrap(), 0);
     }
 
     #[test]
     fn test_parse_time_value_minutes() {
-        assert_eq!(parse_time_value("1m"), 60);
-        assert_eq!(parse_time_value("30m"), 1800);
-        assert_eq!(parse_time_value("0m"), 0);
+        assert_eq!(parse_time_value("1m").unwrap(), 60);
+        assert_eq!(parse_time_value("30m").unwrap(), 1800);
+        assert_eq!(parse_time_value("0m").unwrap(), 0);
     }
 
     #[test]
     fn test_parse_time_value_hours() {
-        assert_eq!(parse_time_value("1h"), 3600);
-        assert_eq!(parse_time_value("24h"), 86400);
-        assert_eq!(parse_time_value("0h"), 0);
+        assert_eq!(parse_time_value("1h").unwrap(), 3600);
+        assert_eq!(parse_time_value("24h").unwrap(), 86400);
+        assert_eq!(parse_time_value("0h").unwrap(), 0);
     }
 
     #[test]
     fn test_parse_time_value_days() {
-        assert_eq!(parse_time_value("1d"), 86_400);
-        assert_eq!(parse_time_value("7d"), 604_800);
-        assert_eq!(parse_time_value("0d"), 0);
+        assert_eq!(parse_time_value("1d").unwrap(), 86_400);
+        assert_eq!(parse_time_value("7d").unwrap(), 604_800);
+        assert_eq!(parse_time_value("0d").unwrap(), 0);
     }
 
     #[test]
     fn test_parse_time_value_edge_cases() {
-        assert_eq!(parse_time_value(""), 0);
-        assert_eq!(parse_time_value("  2h  "), 7200);
-        assert_eq!(parse_time_value("invalid"), 0);
+        assert_eq!(parse_time_value("").unwrap(), 0);
+        assert_eq!(parse_time_value("  2h  ").unwrap(), 7200);
+        assert_eq!(parse_time_value("invalid").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_parse_time_value_overflow() {
+        assert_eq!(parse_time_value("49710d").unwrap(), 4_294_944_000);
+        assert!(matches!(
+            parse_time_value("50000d"),
+            Err(crate::error::CliError::InvalidArgument(_))
+        ));
+        assert!(parse_time_value("1193047h").is_err());
+        assert!(parse_time_value("71582789m").is_err());
+    }
+
+    #[test]
+    fn test_parse_channel_index() {
+        let channels = HashMap::from([(0, "Public".to_string()), (2, "#rust".to_string())]);
+
+        assert_eq!(parse_channel_index("3", &channels).unwrap(), 3);
+        assert_eq!(parse_channel_index("public", &channels).unwrap(), 0);
+        assert_eq!(parse_channel_index("#public", &channels).unwrap(), 0);
+        assert_eq!(parse_channel_index("#rust", &channels).unwrap(), 2);
+        assert_eq!(parse_channel_index("RUST", &channels).unwrap(), 2);
+        assert_eq!(parse_channel_index("#5", &channels).unwrap(), 5);
+        assert!(parse_channel_index("#go", &channels).is_err());
     }
 }
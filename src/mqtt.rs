@@ -0,0 +1,404 @@
+//! MQTT bridge: publishes device events and sends messages published by clients.
+//!
+//! Events go to `<prefix>/<device>/rx/...` as JSON, in the same shape daemon
+//! subscribers get. Payloads published to `<prefix>/<device>/tx/contact/<name>`,
+//! `tx/channel/<channel>` and `tx/cmd/<name>` are sent as private messages,
+//! channel messages and repeater commands.
+
+use std::time::Duration;
+
+use meshcore::event::Event;
+use meshcore::transport::Transport;
+use rumqttc::{AsyncClient, Event as MqttEvent, MqttOptions, Packet, Publish, QoS};
+use serde_json::{Value, json};
+use tokio::sync::mpsc;
+
+use crate::commands::CommandContext;
+use crate::error::{CliError, Result};
+use crate::events::event_to_json;
+
+/// Port used when `--broker` has none.
+const DEFAULT_PORT: u16 = 1883;
+
+/// Keep-alive interval sent to the broker.
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// Delay between reconnect attempts after the broker connection drops.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Splits a `host[:port]` broker address, accepting an optional `mqtt://` scheme.
+pub fn parse_broker(broker: &str) -> Result<(String, u16)> {
+    let address = broker.strip_prefix("mqtt://").unwrap_or(broker);
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => {
+            let port = port
+                .parse()
+                .map_err(|_| CliError::InvalidArgument(format!("Invalid broker port: {port}")))?;
+            (host, port)
+        }
+        None => (address, DEFAULT_PORT),
+    };
+    if host.is_empty() {
+        return Err(CliError::InvalidArgument(format!(
+            "Invalid broker address: {broker}"
+        )));
+    }
+    Ok((host.to_string(), port))
+}
+
+/// Makes a name usable as a single topic level (no separators or wildcards).
+fn topic_level(name: &str) -> String {
+    name.replace(['/', '+', '#'], "_")
+}
+
+/// A message or command requested over MQTT.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outgoing {
+    /// Private message to a contact.
+    Contact { name: String, text: String },
+    /// Message on a channel (number or name).
+    Channel { channel: String, text: String },
+    /// Command to a repeater.
+    Command { name: String, text: String },
+}
+
+/// Topic layout of one device.
+pub struct Topics {
+    root: String,
+}
+
+impl Topics {
+    /// Creates the topics under `<prefix>/<device>`.
+    #[must_use]
+    pub fn new(prefix: &str, device: &str) -> Self {
+        Self {
+            root: format!("{}/{}", prefix.trim_end_matches('/'), topic_level(device)),
+        }
+    }
+
+    /// Returns the filter subscribed to for outgoing messages.
+    #[must_use]
+    pub fn tx_filter(&self) -> String {
+        format!("{}/tx/#", self.root)
+    }
+
+    /// Returns the topic failed `tx` requests are reported on.
+    #[must_use]
+    pub fn error(&self) -> String {
+        format!("{}/rx/error", self.root)
+    }
+
+    /// Returns the topic an event (as JSON) is published on.
+    #[must_use]
+    pub fn rx(&self, event: &Value) -> String {
+        let field = |key: &str| event.get(key).and_then(Value::as_str);
+        let sender = || topic_level(field("sender").unwrap_or("unknown"));
+
+        match field("type").unwrap_or("event") {
+            "contact_message" => format!("{}/rx/contact/{}", self.root, sender()),
+            "status" => format!("{}/rx/status/{}", self.root, sender()),
+            "channel_message" => {
+                let channel = field("channel_name").map_or_else(
+                    || event["channel_index"].to_string(),
+                    |name| topic_level(name.trim_start_matches('#')),
+                );
+                format!("{}/rx/channel/{channel}", self.root)
+            }
+            kind => format!("{}/rx/{kind}", self.root),
+        }
+    }
+
+    /// Parses a publish on a `tx` topic.
+    ///
+    /// The payload is the text itself, or a JSON object with a `text` field.
+    pub fn parse_tx(&self, topic: &str, payload: &[u8]) -> Result<Outgoing> {
+        let target = topic
+            .strip_prefix(&self.root)
+            .and_then(|rest| rest.strip_prefix("/tx/"))
+            .and_then(|rest| rest.split_once('/'))
+            .filter(|(_, target)| !target.is_empty());
+        let Some((kind, target)) = target else {
+            return Err(CliError::InvalidArgument(format!("Unknown topic: {topic}")));
+        };
+
+        let text = payload_text(payload)?;
+        let target = target.to_string();
+        match kind {
+            "contact" => Ok(Outgoing::Contact { name: target, text }),
+            "channel" => Ok(Outgoing::Channel {
+                channel: target,
+                text,
+            }),
+            "cmd" => Ok(Outgoing::Command { name: target, text }),
+            _ => Err(CliError::InvalidArgument(format!("Unknown topic: {topic}"))),
+        }
+    }
+}
+
+/// Extracts the text of a `tx` payload.
+fn payload_text(payload: &[u8]) -> Result<String> {
+    let payload = std::str::from_utf8(payload)
+        .map_err(|_| CliError::InvalidArgument("Payload is not UTF-8".into()))?;
+    let text = match serde_json::from_str::<Value>(payload) {
+        Ok(Value::Object(object)) => object
+            .get("text")
+            .and_then(Value::as_str)
+            .ok_or_else(|| CliError::InvalidArgument("Payload has no text field".into()))?
+            .to_string(),
+        _ => payload.trim_end_matches(['\r', '\n']).to_string(),
+    };
+    if text.is_empty() {
+        return Err(CliError::InvalidArgument("Empty payload".into()));
+    }
+    Ok(text)
+}
+
+/// Runs the bridge until Ctrl+C.
+pub async fn run<T: Transport>(
+    ctx: &CommandContext<T>,
+    broker: &str,
+    prefix: &str,
+    credentials: Option<(&str, &str)>,
+) -> Result<()> {
+    let (host, port) = parse_broker(broker)?;
+    let topics = Topics::new(prefix, ctx.device_name.as_deref().unwrap_or("device"));
+
+    let mut options = MqttOptions::new(
+        format!("meshcore-cli-rs-{}", std::process::id()),
+        host,
+        port,
+    );
+    options.set_keep_alive(KEEP_ALIVE);
+    if let Some((username, password)) = credentials {
+        options.set_credentials(username, password);
+    }
+    let (client, mut eventloop) = AsyncClient::new(options, 64);
+
+    // The first connection must succeed, later drops are retried
+    loop {
+        match eventloop.poll().await {
+            Ok(MqttEvent::Incoming(Packet::ConnAck(_))) => break,
+            Ok(_) => {}
+            Err(e) => {
+                return Err(CliError::Mqtt(format!(
+                    "Failed to connect to {broker}: {e}"
+                )));
+            }
+        }
+    }
+    let filter = topics.tx_filter();
+    client
+        .subscribe(&filter, QoS::AtLeastOnce)
+        .await
+        .map_err(|e| CliError::Mqtt(e.to_string()))?;
+    ctx.display.print_ok(&format!(
+        "mqtt bridge connected to {broker}, listening on {filter}"
+    ));
+
+    // Poll the connection in its own task so publishing never waits on it
+    let (requests_tx, mut requests) = mpsc::unbounded_channel();
+    let subscriber = client.clone();
+    let poller = tokio::spawn(async move {
+        loop {
+            match eventloop.poll().await {
+                Ok(MqttEvent::Incoming(Packet::ConnAck(_))) => {
+                    tracing::info!("Reconnected to MQTT broker");
+                    // Subscriptions don't survive a clean session
+                    if let Err(e) = subscriber.try_subscribe(&filter, QoS::AtLeastOnce) {
+                        tracing::warn!("Failed to subscribe to {filter}: {e}");
+                    }
+                }
+                Ok(MqttEvent::Incoming(Packet::Publish(publish))) => {
+                    if requests_tx.send(publish).is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::warn!("MQTT connection error: {e}");
+                    tokio::time::sleep(RETRY_DELAY).await;
+                }
+            }
+        }
+    });
+
+    let mut events = ctx.event_stream().await;
+    loop {
+        tokio::select! {
+            event = events.next() => {
+                let Some(event) = event else { break };
+                // A failed fetch (e.g. a device timeout) must not stop the bridge
+                match ctx.resolve_waiting(event).await {
+                    Ok(events) => {
+                        for event in events {
+                            publish_event(ctx, &client, &topics, &event).await;
+                        }
+                    }
+                    Err(e) => tracing::warn!("Failed to fetch waiting messages: {e}"),
+                }
+            }
+            Some(publish) = requests.recv() => {
+                handle_request(ctx, &client, &topics, &publish).await;
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    poller.abort();
+    Ok(())
+}
+
+/// Publishes an event on its `rx` topic.
+async fn publish_event<T: Transport>(
+    ctx: &CommandContext<T>,
+    client: &AsyncClient,
+    topics: &Topics,
+    event: &Event,
+) {
+    let contacts = ctx.client.lock().await.contacts().await;
+    let channels = ctx.state.lock().await.channels.clone();
+    let Some(value) = event_to_json(event, &contacts, &channels) else {
+        return;
+    };
+
+    let topic = topics.rx(&value);
+    if let Err(e) = client
+        .publish(&topic, QoS::AtLeastOnce, false, value.to_string())
+        .await
+    {
+        tracing::warn!("Failed to publish to {topic}: {e}");
+    }
+}
+
+/// Sends a message or command published on a `tx` topic, reporting failures on `rx/error`.
+async fn handle_request<T: Transport>(
+    ctx: &CommandContext<T>,
+    client: &AsyncClient,
+    topics: &Topics,
+    publish: &Publish,
+) {
+    let result = match topics.parse_tx(&publish.topic, &publish.payload) {
+        Ok(outgoing) => send(ctx, outgoing).await,
+        Err(e) => Err(e),
+    };
+    let Err(e) = result else {
+        return;
+    };
+
+    ctx.display
+        .print_warning(&format!("{}: {e}", publish.topic));
+    let mut value = e.to_json();
+    value["topic"] = json!(publish.topic);
+    if let Err(e) = client
+        .publish(topics.error(), QoS::AtLeastOnce, false, value.to_string())
+        .await
+    {
+        tracing::warn!("Failed to publish error: {e}");
+    }
+}
+
+/// Sends an outgoing message or command.
+async fn send<T: Transport>(ctx: &CommandContext<T>, outgoing: Outgoing) -> Result<()> {
+    match outgoing {
+        Outgoing::Contact { name, text } => ctx.cmd_msg(&name, &[text], false, 0).await,
+        Outgoing::Channel { channel, text } => {
+            let index = ctx.get_channel_index(&channel).await?;
+            ctx.cmd_chan(index, &[text]).await
+        }
+        Outgoing::Command { name, text } => ctx.cmd_cmd(&name, &[text], false, 0).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_broker() {
+        assert_eq!(
+            parse_broker("localhost:1883").unwrap(),
+            ("localhost".to_string(), 1883)
+        );
+        assert_eq!(
+            parse_broker("mqtt://broker.lan").unwrap(),
+            ("broker.lan".to_string(), DEFAULT_PORT)
+        );
+        assert!(parse_broker("localhost:abc").is_err());
+        assert!(parse_broker(":1883").is_err());
+    }
+
+    #[test]
+    fn test_rx_topics() {
+        let topics = Topics::new("meshcore/", "Base/1");
+        assert_eq!(topics.tx_filter(), "meshcore/Base_1/tx/#");
+
+        let message = json!({ "type": "contact_message", "sender": "Alice", "text": "hi" });
+        assert_eq!(topics.rx(&message), "meshcore/Base_1/rx/contact/Alice");
+
+        let channel =
+            json!({ "type": "channel_message", "channel_index": 2, "channel_name": "#rust" });
+        assert_eq!(topics.rx(&channel), "meshcore/Base_1/rx/channel/rust");
+
+        let unnamed =
+            json!({ "type": "channel_message", "channel_index": 3, "channel_name": null });
+        assert_eq!(topics.rx(&unnamed), "meshcore/Base_1/rx/channel/3");
+
+        let status = json!({ "type": "status", "sender": "Hill+top" });
+        assert_eq!(topics.rx(&status), "meshcore/Base_1/rx/status/Hill_top");
+
+        assert_eq!(
+            topics.rx(&json!({ "type": "ack" })),
+            "meshcore/Base_1/rx/ack"
+        );
+    }
+
+    #[test]
+    fn test_parse_tx() {
+        let topics = Topics::new("meshcore", "Base");
+        assert_eq!(
+            topics
+                .parse_tx("meshcore/Base/tx/contact/Bob", b"hello\n")
+                .unwrap(),
+            Outgoing::Contact {
+                name: "Bob".into(),
+                text: "hello".into()
+            }
+        );
+        assert_eq!(
+            topics
+                .parse_tx("meshcore/Base/tx/channel/rust", br#"{"text": "hi all"}"#)
+                .unwrap(),
+            Outgoing::Channel {
+                channel: "rust".into(),
+                text: "hi all".into()
+            }
+        );
+        assert_eq!(
+            topics
+                .parse_tx("meshcore/Base/tx/cmd/Hilltop", b"clock sync")
+                .unwrap(),
+            Outgoing::Command {
+                name: "Hilltop".into(),
+                text: "clock sync".into()
+            }
+        );
+
+        assert!(topics.parse_tx("meshcore/Base/tx/contact/", b"x").is_err());
+        assert!(topics.parse_tx("meshcore/Base/tx/dm/Bob", b"x").is_err());
+        assert!(
+            topics
+                .parse_tx("meshcore/Other/tx/contact/Bob", b"x")
+                .is_err()
+        );
+        assert!(
+            topics
+                .parse_tx("meshcore/Base/tx/contact/Bob", b"")
+                .is_err()
+        );
+        assert!(
+            topics
+                .parse_tx("meshcore/Base/tx/contact/Bob", br#"{"msg": "x"}"#)
+                .is_err()
+        );
+    }
+}
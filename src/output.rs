@@ -0,0 +1,71 @@
+//! Command output sink.
+//!
+//! Command output normally goes to stdout. The daemon runs each request inside
+//! [`capture`] so the output can be returned to the remote client instead.
+
+use std::cell::RefCell;
+use std::fmt::{self, Write as _};
+use std::io::{self, Write as _};
+
+tokio::task_local! {
+    static CAPTURE: RefCell<String>;
+}
+
+/// Writes formatted output to the current capture buffer, or to stdout.
+pub fn write_fmt(args: fmt::Arguments<'_>) {
+    let captured = CAPTURE
+        .try_with(|buf| {
+            let _ = buf.borrow_mut().write_fmt(args);
+        })
+        .is_ok();
+
+    if !captured {
+        let _ = io::stdout().lock().write_fmt(args);
+    }
+}
+
+/// Runs a future and returns its output together with everything it printed.
+pub async fn capture<F: Future>(future: F) -> (F::Output, String) {
+    CAPTURE
+        .scope(RefCell::new(String::new()), async {
+            let result = future.await;
+            let output = CAPTURE.with(RefCell::take);
+            (result, output)
+        })
+        .await
+}
+
+/// Prints to the command output (like `print!`).
+macro_rules! out {
+    ($($arg:tt)*) => {
+        $crate::output::write_fmt(format_args!($($arg)*))
+    };
+}
+
+/// Prints a line to the command output (like `println!`).
+macro_rules! outln {
+    () => {
+        $crate::output::write_fmt(format_args!("\n"))
+    };
+    ($($arg:tt)*) => {
+        $crate::output::write_fmt(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+pub(crate) use {out, outln};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_capture() {
+        let ((), output) = capture(async {
+            outln!("hello {}", 42);
+            out!("no newline");
+            outln!();
+        })
+        .await;
+        assert_eq!(output, "hello 42\nno newline\n");
+    }
+}
@@ -0,0 +1,653 @@
+//! HTTP API: REST endpoints for the device and a WebSocket event stream.
+//!
+//! Endpoints run the same commands as the CLI with JSON output and return what
+//! they printed. `/events` streams device events in the same shape daemon
+//! subscribers get. With a token, every request needs `Authorization: Bearer
+//! <token>`; `/events` also accepts `?token=`, as browsers can't set headers on
+//! a WebSocket. Without one, only requests whose `Host` and `Origin` name the
+//! listen address are served, so other web pages can't reach the API.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query, Request, State};
+use axum::http::uri::Authority;
+use axum::http::{StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use meshcore::transport::Transport;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::cli::{Command, StatsTypeArg};
+use crate::commands::CommandContext;
+use crate::display::Display;
+use crate::error::{CliError, Result};
+use crate::events::forward_json;
+use crate::output;
+use crate::web;
+
+/// Events buffered per WebSocket client before it starts missing them.
+const EVENT_BUFFER: usize = 256;
+
+/// Shared state of the API.
+pub struct Api<T: Transport> {
+    /// Context with JSON output, used for every request.
+    ctx: CommandContext<T>,
+    /// Bearer token required on every request, if set.
+    token: Option<String>,
+    /// Address the API listens on, checked against `Host` and `Origin` without a token.
+    address: SocketAddr,
+    /// Device events as JSON, fanned out to WebSocket clients.
+    events: broadcast::Sender<Value>,
+}
+
+impl<T: Transport> Api<T> {
+    /// Runs a command and responds with the JSON it printed.
+    async fn execute(&self, command: Command) -> Response {
+        let (result, output) = output::capture(self.ctx.execute(command)).await;
+        match result {
+            Ok(()) => Json(output_json(&output)).into_response(),
+            Err(e) => error_response(&e),
+        }
+    }
+}
+
+/// Body of `POST /api/messages`.
+#[derive(Debug, Deserialize)]
+struct MessageBody {
+    /// Contact name or public key prefix.
+    to: String,
+    /// Message text.
+    text: String,
+    /// Wait for the ACK before responding.
+    #[serde(default)]
+    wait: bool,
+}
+
+/// Body of `POST /api/channels/{channel}/messages`.
+#[derive(Debug, Deserialize)]
+struct TextBody {
+    /// Message text.
+    text: String,
+}
+
+/// Body of `POST /api/repeaters/{name}/login`.
+#[derive(Debug, Deserialize)]
+struct LoginBody {
+    /// Repeater password.
+    password: String,
+}
+
+/// Body of `POST /api/repeaters/{name}/cmd`.
+#[derive(Debug, Deserialize)]
+struct CommandBody {
+    /// Command line sent to the repeater.
+    command: String,
+}
+
+/// Query of `GET /api/stats`.
+#[derive(Debug, Deserialize)]
+struct StatsQuery {
+    /// Stats type (`core`, `radio` or `packets`).
+    #[serde(rename = "type")]
+    stats_type: Option<StatsTypeArg>,
+}
+
+/// Runs the API until Ctrl+C, with the chat page at `/` if `ui` is set.
+pub async fn run<T: Transport>(
+    ctx: &CommandContext<T>,
+    listen: &str,
+    token: Option<String>,
+    ui: bool,
+) -> Result<()> {
+    let listener = TcpListener::bind(listen).await.map_err(|e| {
+        CliError::Io(std::io::Error::new(
+            e.kind(),
+            format!("Failed to listen on {listen}: {e}"),
+        ))
+    })?;
+
+    let address = listener.local_addr()?;
+    let (events, _) = broadcast::channel(EVENT_BUFFER);
+    let api = Arc::new(Api {
+        ctx: ctx.with_display(Display::new(true, false).with_profile(ctx.display.profile.clone())),
+        token,
+        address,
+        events,
+    });
+    let forwarder = forward_json(ctx, api.events.clone()).await;
+
+    let app = if ui {
+        ctx.display
+            .print_ok(&format!("serving chat UI on http://{address}/"));
+        router(api).merge(web::pages())
+    } else {
+        ctx.display
+            .print_ok(&format!("serving HTTP API on http://{address}"));
+        router(api)
+    };
+
+    // Open WebSockets would hold up a graceful shutdown, so stop right away
+    let result = tokio::select! {
+        result = axum::serve(listener, app).into_future() => result.map_err(CliError::Io),
+        _ = tokio::signal::ctrl_c() => Ok(()),
+    };
+
+    forwarder.abort();
+    result
+}
+
+/// Builds the routes of the API.
+pub fn router<T: Transport>(api: Arc<Api<T>>) -> Router {
+    Router::new()
+        .route("/api/info", get(info))
+        .route("/api/contacts", get(contacts))
+        .route("/api/channels", get(channels))
+        .route("/api/stats", get(stats))
+        .route("/api/messages", post(send_message))
+        .route(
+            "/api/channels/{channel}/messages",
+            post(send_channel_message),
+        )
+        .route("/api/repeaters/{name}/login", post(login))
+        .route("/api/repeaters/{name}/status", post(req_status))
+        .route("/api/repeaters/{name}/telemetry", post(req_telemetry))
+        .route("/api/repeaters/{name}/neighbours", post(req_neighbours))
+        .route("/api/repeaters/{name}/cmd", post(repeater_cmd))
+        .route("/events", get(events))
+        .route_layer(middleware::from_fn_with_state(api.clone(), authorize))
+        .with_state(api)
+}
+
+async fn info<T: Transport>(State(api): State<Arc<Api<T>>>) -> Response {
+    api.execute(Command::Infos).await
+}
+
+async fn contacts<T: Transport>(State(api): State<Arc<Api<T>>>) -> Response {
+    api.execute(Command::Contacts).await
+}
+
+async fn channels<T: Transport>(State(api): State<Arc<Api<T>>>) -> Response {
+    api.execute(Command::GetChannels).await
+}
+
+async fn stats<T: Transport>(
+    State(api): State<Arc<Api<T>>>,
+    Query(query): Query<StatsQuery>,
+) -> Response {
+    let stats_type = query.stats_type.unwrap_or(StatsTypeArg::Core);
+    api.execute(Command::Stats { stats_type }).await
+}
+
+async fn send_message<T: Transport>(
+    State(api): State<Arc<Api<T>>>,
+    Json(body): Json<MessageBody>,
+) -> Response {
+    api.execute(Command::Msg {
+        name: body.to,
+        message: vec![body.text],
+        wait: body.wait,
+        timeout: 30,
+        retry: None,
+        flood_after: 2,
+        split: false,
+    })
+    .await
+}
+
+async fn send_channel_message<T: Transport>(
+    State(api): State<Arc<Api<T>>>,
+    Path(channel): Path<String>,
+    Json(body): Json<TextBody>,
+) -> Response {
+    api.execute(Command::Chan {
+        channel,
+        message: vec![body.text],
+        split: false,
+    })
+    .await
+}
+
+async fn login<T: Transport>(
+    State(api): State<Arc<Api<T>>>,
+    Path(name): Path<String>,
+    Json(body): Json<LoginBody>,
+) -> Response {
+    api.execute(Command::Login {
+        name,
+        password: body.password,
+    })
+    .await
+}
+
+async fn req_status<T: Transport>(
+    State(api): State<Arc<Api<T>>>,
+    Path(name): Path<String>,
+) -> Response {
+    api.execute(Command::ReqStatus { name }).await
+}
+
+async fn req_telemetry<T: Transport>(
+    State(api): State<Arc<Api<T>>>,
+    Path(contact): Path<String>,
+) -> Response {
+    api.execute(Command::ReqTelemetry { contact }).await
+}
+
+async fn req_neighbours<T: Transport>(
+    State(api): State<Arc<Api<T>>>,
+    Path(name): Path<String>,
+) -> Response {
+    api.execute(Command::ReqNeighbours { name }).await
+}
+
+async fn repeater_cmd<T: Transport>(
+    State(api): State<Arc<Api<T>>>,
+    Path(name): Path<String>,
+    Json(body): Json<CommandBody>,
+) -> Response {
+    api.execute(Command::Cmd {
+        name,
+        command: vec![body.command],
+        wait: false,
+        timeout: 30,
+    })
+    .await
+}
+
+/// Upgrades to a WebSocket streaming device events.
+async fn events<T: Transport>(
+    State(api): State<Arc<Api<T>>>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let events = api.events.subscribe();
+    upgrade.on_upgrade(move |socket| stream_events(socket, events))
+}
+
+/// Sends events to a WebSocket client until it disconnects.
+async fn stream_events(mut socket: WebSocket, mut events: broadcast::Receiver<Value>) {
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(value) => {
+                    if socket.send(Message::Text(value.to_string().into())).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!("WebSocket client missed {missed} events");
+                }
+                Err(RecvError::Closed) => break,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// Rejects requests without the configured bearer token, or without a token,
+/// requests from other origins.
+async fn authorize<T: Transport>(
+    State(api): State<Arc<Api<T>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match &api.token {
+        Some(token) if !request_token(&request).is_some_and(|given| tokens_match(given, token)) => {
+            let error = json!({
+                "error": { "kind": "unauthorized", "message": "Missing or invalid bearer token" }
+            });
+            return (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                Json(error),
+            )
+                .into_response();
+        }
+        None if !is_same_origin(&request, api.address) => {
+            let error = json!({
+                "error": {
+                    "kind": "forbidden",
+                    "message": "Requests from other origins need a token (--token)",
+                }
+            });
+            return (StatusCode::FORBIDDEN, Json(error)).into_response();
+        }
+        _ => {}
+    }
+    next.run(request).await
+}
+
+/// Returns true if a request is for the listen address and, when it sends an
+/// `Origin`, comes from a page served from it.
+///
+/// This keeps other web pages from reading the API or the event stream, including
+/// through DNS rebinding, which sends a foreign host name.
+fn is_same_origin(request: &Request, address: SocketAddr) -> bool {
+    let header = |name| {
+        request
+            .headers()
+            .get(name)
+            .map(|value| value.to_str().unwrap_or_default())
+    };
+    let host = header(header::HOST).is_some_and(|host| is_local_authority(host, address));
+    let origin = header(header::ORIGIN).is_none_or(|origin| {
+        origin
+            .strip_prefix("http://")
+            .is_some_and(|authority| is_local_authority(authority, address))
+    });
+    host && origin
+}
+
+/// Returns true if `host[:port]` names the listen address: its IP, or `localhost`
+/// when listening on loopback. Other host names are refused.
+fn is_local_authority(authority: &str, address: SocketAddr) -> bool {
+    let Ok(authority) = authority.parse::<Authority>() else {
+        return false;
+    };
+    if authority.port_u16().unwrap_or(80) != address.port() {
+        return false;
+    }
+
+    let listen = address.ip();
+    let host = authority
+        .host()
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    if host.eq_ignore_ascii_case("localhost") {
+        return listen.is_loopback() || listen.is_unspecified();
+    }
+    host.parse::<IpAddr>()
+        .is_ok_and(|ip| ip == listen || listen.is_unspecified())
+}
+
+/// Returns the token sent with a request: the bearer token, or `?token=` on `/events`.
+fn request_token(request: &Request) -> Option<&str> {
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if bearer.is_some() || request.uri().path() != "/events" {
+        return bearer;
+    }
+
+    request
+        .uri()
+        .query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="))
+}
+
+/// Compares tokens in time independent of where they differ.
+pub fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Parses what a command printed in JSON mode.
+///
+/// A single value is returned as is, several as an array. Output that isn't JSON
+/// is wrapped as `{"output": "..."}`.
+fn output_json(output: &str) -> Value {
+    let values: std::result::Result<Vec<Value>, _> = serde_json::Deserializer::from_str(output)
+        .into_iter()
+        .collect();
+    match values {
+        Ok(mut values) if values.len() == 1 => values.remove(0),
+        Ok(values) if values.is_empty() => json!({ "ok": true }),
+        Ok(values) => Value::Array(values),
+        Err(_) => json!({ "output": output }),
+    }
+}
+
+/// Returns the HTTP status for a failed command.
+fn error_status(error: &CliError) -> StatusCode {
+    match error {
+        CliError::ContactNotFound(_) | CliError::ChannelNotFound(_) => StatusCode::NOT_FOUND,
+        CliError::InvalidArgument(_) | CliError::Usage(_) => StatusCode::BAD_REQUEST,
+        CliError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        CliError::Connection(_) | CliError::Serial(_) | CliError::Tcp(_) | CliError::Command(_) => {
+            StatusCode::BAD_GATEWAY
+        }
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Responds with a failed command's JSON error.
+fn error_response(error: &CliError) -> Response {
+    (error_status(error), Json(error.to_json())).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::mock::{self, ADV_TYPE_CHAT, MockRadio};
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    /// Builds the API in front of a mock radio, listening on 127.0.0.1:8080.
+    async fn app(token: Option<&str>) -> Router {
+        let radio = MockRadio::new("Base").with_contact("Alice", 0x11, ADV_TYPE_CHAT);
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        router(Arc::new(Api {
+            ctx: mock::context(&radio, true).await,
+            token: token.map(str::to_string),
+            address: SocketAddr::from(([127, 0, 0, 1], 8080)),
+            events,
+        }))
+    }
+
+    /// Starts a request to the API's own host.
+    fn request(method: &str, uri: &str) -> axum::http::request::Builder {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::HOST, "127.0.0.1:8080")
+    }
+
+    /// Sends a request, returning the status and the JSON body.
+    async fn send(app: &Router, request: Request) -> (StatusCode, Value) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_token_required() {
+        let app = app(Some("secret")).await;
+
+        let (status, body) = send(
+            &app,
+            request("GET", "/api/info").body(Body::empty()).unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["error"]["kind"], "unauthorized");
+
+        let authorized = request("GET", "/api/info")
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .body(Body::empty())
+            .unwrap();
+        let (status, body) = send(&app, authorized).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["name"], "Base");
+    }
+
+    #[tokio::test]
+    async fn test_missing_contact_error() {
+        let app = app(Some("secret")).await;
+        let message = request("POST", "/api/messages")
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"to": "Nobody", "text": "hi"}"#))
+            .unwrap();
+
+        let (status, body) = send(&app, message).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["kind"], "contact_not_found");
+        assert!(
+            body["error"]["message"]
+                .as_str()
+                .unwrap()
+                .contains("Nobody")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_other_origins_need_a_token() {
+        let app = app(None).await;
+
+        let (status, _) = send(
+            &app,
+            request("GET", "/api/info").body(Body::empty()).unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let same_origin = request("GET", "/api/contacts")
+            .header(header::ORIGIN, "http://127.0.0.1:8080")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(send(&app, same_origin).await.0, StatusCode::OK);
+
+        let cross_origin = request("GET", "/api/contacts")
+            .header(header::ORIGIN, "https://example.com")
+            .body(Body::empty())
+            .unwrap();
+        let (status, body) = send(&app, cross_origin).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["error"]["kind"], "forbidden");
+
+        // DNS rebinding: the browser sends the attacker's host name
+        let rebound = Request::builder()
+            .uri("/api/contacts")
+            .header(header::HOST, "rebind.example:8080")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(send(&app, rebound).await.0, StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_is_local_authority() {
+        let loopback = SocketAddr::from(([127, 0, 0, 1], 8080));
+        assert!(is_local_authority("127.0.0.1:8080", loopback));
+        assert!(is_local_authority("LocalHost:8080", loopback));
+        assert!(!is_local_authority("127.0.0.1:9090", loopback));
+        assert!(!is_local_authority("127.0.0.1", loopback));
+        assert!(!is_local_authority("10.0.0.2:8080", loopback));
+        assert!(!is_local_authority("evil.example:8080", loopback));
+        assert!(!is_local_authority("", loopback));
+
+        let any = SocketAddr::from(([0, 0, 0, 0], 8080));
+        assert!(is_local_authority("192.168.1.5:8080", any));
+        assert!(!is_local_authority("mesh.lan:8080", any));
+
+        let ipv6 = SocketAddr::from((std::net::Ipv6Addr::LOCALHOST, 80));
+        assert!(is_local_authority("[::1]", ipv6));
+    }
+
+    #[test]
+    fn test_is_same_origin() {
+        let address = SocketAddr::from(([127, 0, 0, 1], 8080));
+        let with = |headers: &[(header::HeaderName, &str)]| {
+            let mut builder = Request::builder().uri("/events");
+            for (name, value) in headers {
+                builder = builder.header(name, *value);
+            }
+            is_same_origin(&builder.body(Body::empty()).unwrap(), address)
+        };
+
+        assert!(with(&[(header::HOST, "localhost:8080")]));
+        assert!(with(&[
+            (header::HOST, "localhost:8080"),
+            (header::ORIGIN, "http://localhost:8080"),
+        ]));
+        assert!(!with(&[
+            (header::HOST, "localhost:8080"),
+            (header::ORIGIN, "http://localhost.evil.example:8080"),
+        ]));
+        assert!(!with(&[
+            (header::HOST, "localhost:8080"),
+            (header::ORIGIN, "null"),
+        ]));
+        assert!(!with(&[]));
+    }
+
+    #[test]
+    fn test_output_json() {
+        assert_eq!(output_json("{\"a\": 1}\n"), json!({ "a": 1 }));
+        assert_eq!(
+            output_json("{\"a\": 1}\n{\"b\": 2}\n"),
+            json!([{ "a": 1 }, { "b": 2 }])
+        );
+        assert_eq!(output_json(""), json!({ "ok": true }));
+        assert_eq!(
+            output_json("Message sent\n"),
+            json!({ "output": "Message sent\n" })
+        );
+    }
+
+    #[test]
+    fn test_request_token() {
+        let request = Request::builder()
+            .uri("/api/contacts")
+            .header("Authorization", "Bearer secret")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(request_token(&request), Some("secret"));
+
+        let request = Request::builder()
+            .uri("/events?token=secret")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(request_token(&request), Some("secret"));
+
+        // Tokens in URLs end up in logs, so only the WebSocket accepts them
+        let request = Request::builder()
+            .uri("/api/contacts?token=secret")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(request_token(&request), None);
+    }
+
+    #[test]
+    fn test_tokens_match() {
+        assert!(tokens_match("secret", "secret"));
+        assert!(!tokens_match("secreT", "secret"));
+        assert!(!tokens_match("secret2", "secret"));
+        assert!(!tokens_match("", "secret"));
+    }
+
+    #[test]
+    fn test_error_status() {
+        assert_eq!(
+            error_status(&CliError::ContactNotFound("Bob".into())),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            error_status(&CliError::Timeout("ACK".into())),
+            StatusCode::GATEWAY_TIMEOUT
+        );
+        assert_eq!(
+            error_status(&CliError::Command("Login failed".into())),
+            StatusCode::BAD_GATEWAY
+        );
+    }
+}
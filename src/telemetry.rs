@@ -0,0 +1,348 @@
+//! Cayenne LPP telemetry decoding.
+//!
+//! Readings are `[channel][type][payload]` records with big-endian payloads whose
+//! size and scale depend on the type. Each type maps to a name, a unit and a
+//! numeric or structured value.
+
+use meshcore::types::{LppValue, Telemetry};
+use serde_json::{Value, json};
+
+/// An LPP data type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LppType {
+    /// Type code.
+    pub code: u8,
+    /// Sensor name.
+    pub name: &'static str,
+    /// Unit symbol (empty for unitless values).
+    pub unit: &'static str,
+    /// Payload size in bytes.
+    size: usize,
+    /// How the payload is decoded.
+    format: Format,
+}
+
+/// Payload layout of an LPP type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    /// Unsigned big-endian integer divided by the scale.
+    Unsigned(f64),
+    /// Signed big-endian integer divided by the scale.
+    Signed(f64),
+    /// Three signed 16-bit axes divided by the scale.
+    Xyz(f64),
+    /// Three 24-bit signed values: latitude, longitude (1e-4 deg), altitude (cm).
+    Gps,
+    /// Three bytes: red, green, blue.
+    Colour,
+}
+
+/// Known LPP types, by code.
+const TYPES: &[LppType] = &[
+    lpp(0, "digital_input", "", 1, Format::Unsigned(1.0)),
+    lpp(1, "digital_output", "", 1, Format::Unsigned(1.0)),
+    lpp(2, "analog_input", "", 2, Format::Signed(100.0)),
+    lpp(3, "analog_output", "", 2, Format::Signed(100.0)),
+    lpp(100, "generic", "", 4, Format::Unsigned(1.0)),
+    lpp(101, "illuminance", "lx", 2, Format::Unsigned(1.0)),
+    lpp(102, "presence", "", 1, Format::Unsigned(1.0)),
+    lpp(103, "temperature", "°C", 2, Format::Signed(10.0)),
+    lpp(104, "humidity", "%", 1, Format::Unsigned(2.0)),
+    lpp(113, "accelerometer", "G", 6, Format::Xyz(1000.0)),
+    lpp(115, "barometer", "hPa", 2, Format::Unsigned(10.0)),
+    lpp(116, "voltage", "V", 2, Format::Unsigned(100.0)),
+    lpp(117, "current", "A", 2, Format::Unsigned(1000.0)),
+    lpp(118, "frequency", "Hz", 4, Format::Unsigned(1.0)),
+    lpp(120, "percentage", "%", 1, Format::Unsigned(1.0)),
+    lpp(121, "altitude", "m", 2, Format::Signed(1.0)),
+    lpp(125, "concentration", "ppm", 2, Format::Unsigned(1.0)),
+    lpp(128, "power", "W", 2, Format::Unsigned(1.0)),
+    lpp(130, "distance", "m", 4, Format::Unsigned(1000.0)),
+    lpp(131, "energy", "kWh", 4, Format::Unsigned(1000.0)),
+    lpp(132, "direction", "°", 2, Format::Unsigned(1.0)),
+    lpp(133, "unix_time", "s", 4, Format::Unsigned(1.0)),
+    lpp(134, "gyrometer", "°/s", 6, Format::Xyz(100.0)),
+    lpp(135, "colour", "", 3, Format::Colour),
+    lpp(136, "gps", "", 9, Format::Gps),
+    lpp(142, "switch", "", 1, Format::Unsigned(1.0)),
+];
+
+const fn lpp(
+    code: u8,
+    name: &'static str,
+    unit: &'static str,
+    size: usize,
+    format: Format,
+) -> LppType {
+    LppType {
+        code,
+        name,
+        unit,
+        size,
+        format,
+    }
+}
+
+impl LppType {
+    /// Looks up a type by code.
+    #[must_use]
+    pub fn from_code(code: u8) -> Option<Self> {
+        TYPES.iter().find(|t| t.code == code).copied()
+    }
+
+    /// Decodes a payload of this type.
+    fn decode(self, payload: &[u8]) -> Reading {
+        let value = match self.format {
+            Format::Unsigned(scale) => ReadingValue::Number(f64::from(unsigned(payload)) / scale),
+            Format::Signed(scale) => ReadingValue::Number(f64::from(signed(payload)) / scale),
+            Format::Xyz(scale) => ReadingValue::Xyz {
+                x: f64::from(signed(&payload[0..2])) / scale,
+                y: f64::from(signed(&payload[2..4])) / scale,
+                z: f64::from(signed(&payload[4..6])) / scale,
+            },
+            Format::Gps => ReadingValue::Gps {
+                latitude: f64::from(signed(&payload[0..3])) / 10_000.0,
+                longitude: f64::from(signed(&payload[3..6])) / 10_000.0,
+                altitude: f64::from(signed(&payload[6..9])) / 100.0,
+            },
+            Format::Colour => ReadingValue::Colour {
+                r: payload[0],
+                g: payload[1],
+                b: payload[2],
+            },
+        };
+        Reading {
+            channel: 0,
+            lpp_type: self.code,
+            value,
+        }
+    }
+}
+
+/// Decoded value of a reading.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReadingValue {
+    /// Scalar value.
+    Number(f64),
+    /// Three-axis value (accelerometer, gyrometer).
+    Xyz { x: f64, y: f64, z: f64 },
+    /// GPS position.
+    Gps {
+        latitude: f64,
+        longitude: f64,
+        altitude: f64,
+    },
+    /// RGB colour.
+    Colour { r: u8, g: u8, b: u8 },
+    /// Payload of an unknown type.
+    Raw(Vec<u8>),
+}
+
+/// A decoded telemetry reading.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reading {
+    /// LPP channel.
+    pub channel: u8,
+    /// LPP type code.
+    pub lpp_type: u8,
+    /// Decoded value.
+    pub value: ReadingValue,
+}
+
+impl Reading {
+    /// Returns the sensor name.
+    #[must_use]
+    pub fn name(&self) -> String {
+        LppType::from_code(self.lpp_type)
+            .map_or_else(|| format!("type_{}", self.lpp_type), |t| t.name.to_string())
+    }
+
+    /// Returns the unit symbol.
+    #[must_use]
+    pub fn unit(&self) -> &'static str {
+        LppType::from_code(self.lpp_type).map_or("", |t| t.unit)
+    }
+
+    /// Formats the value without its unit.
+    #[must_use]
+    pub fn format_value(&self) -> String {
+        match &self.value {
+            ReadingValue::Number(v) => format_number(*v),
+            ReadingValue::Xyz { x, y, z } => format!(
+                "x={} y={} z={}",
+                format_number(*x),
+                format_number(*y),
+                format_number(*z)
+            ),
+            ReadingValue::Gps {
+                latitude,
+                longitude,
+                altitude,
+            } => format!(
+                "{latitude:.4}, {longitude:.4} @ {}m",
+                format_number(*altitude)
+            ),
+            ReadingValue::Colour { r, g, b } => format!("#{r:02x}{g:02x}{b:02x}"),
+            ReadingValue::Raw(bytes) => hex::encode(bytes),
+        }
+    }
+
+    /// Converts the reading to JSON, with a numeric or structured value.
+    #[must_use]
+    pub fn to_json(&self) -> Value {
+        let value = match &self.value {
+            ReadingValue::Number(v) => json!(v),
+            ReadingValue::Xyz { x, y, z } => json!({ "x": x, "y": y, "z": z }),
+            ReadingValue::Gps {
+                latitude,
+                longitude,
+                altitude,
+            } => json!({ "latitude": latitude, "longitude": longitude, "altitude": altitude }),
+            ReadingValue::Colour { r, g, b } => json!({ "r": r, "g": g, "b": b }),
+            ReadingValue::Raw(bytes) => json!(hex::encode(bytes)),
+        };
+        json!({
+            "channel": self.channel,
+            "type": self.lpp_type,
+            "name": self.name(),
+            "unit": self.unit(),
+            "value": value,
+        })
+    }
+}
+
+/// Decodes raw LPP data. Stops at the first unknown type or truncated record,
+/// since the payload size of the rest can't be known.
+#[must_use]
+pub fn decode(data: &[u8]) -> Vec<Reading> {
+    let mut readings = Vec::new();
+    let mut rest = data;
+
+    while let [channel, code, payload @ ..] = rest {
+        let Some(lpp_type) = LppType::from_code(*code) else {
+            break;
+        };
+        if payload.len() < lpp_type.size {
+            break;
+        }
+
+        let mut reading = lpp_type.decode(&payload[..lpp_type.size]);
+        reading.channel = *channel;
+        readings.push(reading);
+        rest = &payload[lpp_type.size..];
+    }
+
+    readings
+}
+
+/// Converts readings already parsed by the `meshcore` library.
+#[must_use]
+pub fn from_telemetry(telemetry: &Telemetry) -> Vec<Reading> {
+    telemetry
+        .readings
+        .iter()
+        .map(|r| {
+            let value = match &r.value {
+                LppValue::Digital(v) => ReadingValue::Number(f64::from(*v)),
+                LppValue::Analog(v)
+                | LppValue::Temperature(v)
+                | LppValue::Humidity(v)
+                | LppValue::Voltage(v) => ReadingValue::Number(f64::from(*v)),
+                LppValue::Other(bytes) => match LppType::from_code(r.lpp_type) {
+                    Some(t) if bytes.len() >= t.size => t.decode(bytes).value,
+                    _ => ReadingValue::Raw(bytes.clone()),
+                },
+            };
+            Reading {
+                channel: r.channel,
+                lpp_type: r.lpp_type,
+                value,
+            }
+        })
+        .collect()
+}
+
+/// Reads a big-endian unsigned integer of up to four bytes.
+fn unsigned(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |acc, b| (acc << 8) | u32::from(*b))
+}
+
+/// Reads a big-endian two's complement integer of up to four bytes.
+fn signed(bytes: &[u8]) -> i32 {
+    // Sign-extend from the payload width
+    let fill = if bytes.first().is_some_and(|b| b & 0x80 != 0) {
+        0xff
+    } else {
+        0
+    };
+    let mut buf = [fill; 4];
+    buf[4 - bytes.len()..].copy_from_slice(bytes);
+    i32::from_be_bytes(buf)
+}
+
+/// Formats a number with up to three decimals, trimming trailing zeros.
+fn format_number(value: f64) -> String {
+    let formatted = format!("{value:.3}");
+    formatted
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_scalars() {
+        // Temperature 27.2°C, humidity 45.5%, voltage 4.12V
+        let data = [1, 103, 0x01, 0x10, 2, 104, 91, 3, 116, 0x01, 0x9c];
+        let readings = decode(&data);
+        assert_eq!(readings.len(), 3);
+        assert_eq!(readings[0].name(), "temperature");
+        assert_eq!(readings[0].value, ReadingValue::Number(27.2));
+        assert_eq!(readings[1].value, ReadingValue::Number(45.5));
+        assert_eq!(readings[2].unit(), "V");
+        assert_eq!(readings[2].format_value(), "4.12");
+        assert_eq!(readings[2].to_json()["value"], json!(4.12));
+    }
+
+    #[test]
+    fn test_decode_signed_and_structured() {
+        // Temperature -4.1°C
+        let readings = decode(&[1, 103, 0xff, 0xd7]);
+        assert_eq!(readings[0].value, ReadingValue::Number(-4.1));
+
+        // Accelerometer x=1.0 y=-0.5 z=0.0
+        let readings = decode(&[2, 113, 0x03, 0xe8, 0xfe, 0x0c, 0x00, 0x00]);
+        assert_eq!(
+            readings[0].value,
+            ReadingValue::Xyz {
+                x: 1.0,
+                y: -0.5,
+                z: 0.0
+            }
+        );
+
+        // GPS 48.8566, -2.3522, 35.5m
+        let lat = 488_566i32.to_be_bytes();
+        let lon = (-23_522i32).to_be_bytes();
+        let alt = 3550i32.to_be_bytes();
+        let mut data = vec![3, 136];
+        data.extend_from_slice(&lat[1..]);
+        data.extend_from_slice(&lon[1..]);
+        data.extend_from_slice(&alt[1..]);
+        let json = decode(&data)[0].to_json();
+        assert_eq!(json["name"], "gps");
+        assert_eq!(json["value"]["latitude"], json!(48.8566));
+        assert_eq!(json["value"]["longitude"], json!(-2.3522));
+        assert_eq!(json["value"]["altitude"], json!(35.5));
+    }
+
+    #[test]
+    fn test_decode_stops_at_unknown_or_truncated() {
+        assert_eq!(decode(&[1, 103, 0x01, 0x10, 1, 250, 1, 2]).len(), 1);
+        assert_eq!(decode(&[1, 103, 0x01]).len(), 0);
+        assert!(decode(&[]).is_empty());
+    }
+}
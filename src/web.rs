@@ -0,0 +1,43 @@
+//! Browser chat UI: a self-contained page driving the HTTP API and its event stream.
+
+use axum::Router;
+use axum::response::Html;
+use axum::routing::get;
+
+/// The chat page, embedded in the binary.
+const INDEX: &str = include_str!("web/index.html");
+
+/// Routes serving the page. They need no token: the page asks for one when the API does.
+pub fn pages() -> Router {
+    Router::new().route("/", get(|| async { Html(INDEX) }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode, header};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_pages_serve_index() {
+        let request = Request::builder().uri("/").body(Body::empty()).unwrap();
+        let response = pages().oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let content_type = response.headers()[header::CONTENT_TYPE].to_str().unwrap();
+        assert!(content_type.starts_with("text/html"), "{content_type}");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, INDEX.as_bytes());
+    }
+
+    #[test]
+    fn test_page_is_self_contained() {
+        assert!(!INDEX.contains("http://"));
+        assert!(!INDEX.contains("https://"));
+        assert!(INDEX.contains("/api/messages"));
+        assert!(INDEX.contains("/events"));
+    }
+}